@@ -27,6 +27,8 @@ const DATA_PADDING = 1;
 class Chunk {
   public voxels: NdArray;
   public lights: NdArray;
+  // biome tints at the corners of the columns, for meshing on this side
+  public tints: number[] = [];
 
   public name: string;
   public size: number;
@@ -95,6 +97,12 @@ class Chunk {
     return this.voxels.get(lx, ly, lz);
   };
 
+  setLight = (vx: number, vy: number, vz: number, level: number) => {
+    if (!this.contains(vx, vy, vz)) return;
+    const [lx, ly, lz] = this.toLocal(vx, vy, vz);
+    return this.lights.set(lx, ly, lz, level);
+  };
+
   getLocalRedLight = (lx: number, ly: number, lz: number) => {
     return (this.lights.get(lx, ly, lz) >> 8) & 0xf;
   };
@@ -224,12 +232,13 @@ class Chunk {
           this.altMeshes.set(type, []);
         }
 
-        const { positions, indices, uvs, aos, lights } = meshData[type];
+        const { positions, indices, uvs, aos, lights, tints } = meshData[type];
 
         const positionNumComponents = 3;
         const uvNumComponents = 2;
         const occlusionNumComponents = 1;
         const lightNumComponents = 1;
+        const tintNumComponents = 1;
 
         let geometries = this.geometries.get(type);
         if (!geometries) {
@@ -244,6 +253,13 @@ class Chunk {
         geometry.setAttribute('uv', new Float32BufferAttribute(uvs, uvNumComponents));
         geometry.setAttribute('ao', new Int32BufferAttribute(aos, occlusionNumComponents));
         geometry.setAttribute('light', new Int32BufferAttribute(lights, lightNumComponents));
+        geometry.setAttribute(
+          'tint',
+          new Int32BufferAttribute(
+            tints && tints.length ? tints : new Array(lights.length).fill(0xffffff),
+            tintNumComponents,
+          ),
+        );
         geometry.setIndex(Array.from(indices));
 
         const min = new Vector3(
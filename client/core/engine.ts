@@ -135,6 +135,7 @@ const defaultConfig: ConfigType = {
   },
   network: {
     reconnectTimeout: 5000,
+    clientMeshing: true,
   },
   particles: {
     count: 10,
@@ -250,12 +251,24 @@ class Engine extends EventEmitter {
 
   load = (worldData) => {
     const { world, registry } = this.config;
-    const { chunkSize, dimension, maxHeight, subChunks, name, blocks, ranges, uvSideCount, uvTextureSize } = worldData;
+    const {
+      chunkSize,
+      dimension,
+      maxHeight,
+      subChunks,
+      name,
+      blocks,
+      ranges,
+      uvSideCount,
+      uvTextureSize,
+      uvPadding,
+    } = worldData;
 
     registry.blocks = blocks;
     registry.ranges = ranges;
     registry.countPerSide = uvSideCount;
     registry.textureSize = uvTextureSize;
+    registry.uvPadding = uvPadding;
 
     world.name = name;
     world.chunkSize = chunkSize;
@@ -336,7 +349,7 @@ class Engine extends EventEmitter {
     if (sideEffect)
       this.rendering.engine.network.server.sendEvent({
         type: 'CONFIG',
-        json: {
+        config: {
           tickSpeed: this.tickSpeed,
         },
       });
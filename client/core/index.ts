@@ -7,6 +7,7 @@ export * from './engine';
 export * from './entities';
 export * from './inputs';
 export * from './inventory';
+export * from './mesher';
 export * from './network';
 export * from './particles';
 export * from './peers';
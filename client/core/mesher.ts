@@ -0,0 +1,501 @@
+import { Coords3, MeshType, ServerMeshType } from '../libs/types';
+
+import { RegistryOptionsType } from './registry';
+
+// the blocks and texture ranges the server describes in /world
+type MesherRegistry = Pick<RegistryOptionsType, 'blocks' | 'ranges'>;
+
+// voxels and lights of a chunk along with a voxel of padding around, as sent by the server
+type MesherChunk = {
+  min: Coords3;
+  max: Coords3;
+  size: number;
+  maxHeight: number;
+  subChunks: number;
+  dimension: number;
+  voxels: { get: (x: number, y: number, z: number) => number };
+  lights: { get: (x: number, y: number, z: number) => number };
+  // biome tints at the corners of the columns, x-major
+  tints: number[];
+};
+
+const DATA_PADDING = 1;
+const UNTINTED = 0xffffff;
+const PLANT_SHRINK = 0.6;
+
+// same faces as the server's mesher, so both build the exact same meshes
+const BLOCK_FACES = [
+  {
+    dir: [-1, 0, 0],
+    mat3: 'side',
+    mat6: 'nx',
+    corners: [
+      { pos: [0, 1, 0], uv: [0, 1], side1: 1, side2: 3, corner: 0 },
+      { pos: [0, 0, 0], uv: [0, 0], side1: 3, side2: 6, corner: 5 },
+      { pos: [0, 1, 1], uv: [1, 1], side1: 1, side2: 4, corner: 2 },
+      { pos: [0, 0, 1], uv: [1, 0], side1: 4, side2: 6, corner: 7 },
+    ],
+    neighbors: [
+      [-1, 1, -1],
+      [-1, 1, 0],
+      [-1, 1, 1],
+      [-1, 0, -1],
+      [-1, 0, 1],
+      [-1, -1, -1],
+      [-1, -1, 0],
+      [-1, -1, 1],
+    ],
+  },
+  {
+    dir: [1, 0, 0],
+    mat3: 'side',
+    mat6: 'px',
+    corners: [
+      { pos: [1, 1, 1], uv: [0, 1], side1: 1, side2: 4, corner: 2 },
+      { pos: [1, 0, 1], uv: [0, 0], side1: 4, side2: 6, corner: 7 },
+      { pos: [1, 1, 0], uv: [1, 1], side1: 1, side2: 3, corner: 0 },
+      { pos: [1, 0, 0], uv: [1, 0], side1: 3, side2: 6, corner: 5 },
+    ],
+    neighbors: [
+      [1, 1, -1],
+      [1, 1, 0],
+      [1, 1, 1],
+      [1, 0, -1],
+      [1, 0, 1],
+      [1, -1, -1],
+      [1, -1, 0],
+      [1, -1, 1],
+    ],
+  },
+  {
+    dir: [0, -1, 0],
+    mat3: 'bottom',
+    mat6: 'ny',
+    corners: [
+      { pos: [1, 0, 1], uv: [1, 0], side1: 1, side2: 4, corner: 2 },
+      { pos: [0, 0, 1], uv: [0, 0], side1: 1, side2: 3, corner: 0 },
+      { pos: [1, 0, 0], uv: [1, 1], side1: 4, side2: 6, corner: 7 },
+      { pos: [0, 0, 0], uv: [0, 1], side1: 3, side2: 6, corner: 5 },
+    ],
+    neighbors: [
+      [-1, -1, 1],
+      [0, -1, 1],
+      [1, -1, 1],
+      [-1, -1, 0],
+      [1, -1, 0],
+      [-1, -1, -1],
+      [0, -1, -1],
+      [1, -1, -1],
+    ],
+  },
+  {
+    dir: [0, 1, 0],
+    mat3: 'top',
+    mat6: 'py',
+    corners: [
+      { pos: [0, 1, 1], uv: [1, 1], side1: 1, side2: 3, corner: 0 },
+      { pos: [1, 1, 1], uv: [0, 1], side1: 1, side2: 4, corner: 2 },
+      { pos: [0, 1, 0], uv: [1, 0], side1: 3, side2: 6, corner: 5 },
+      { pos: [1, 1, 0], uv: [0, 0], side1: 4, side2: 6, corner: 7 },
+    ],
+    neighbors: [
+      [-1, 1, 1],
+      [0, 1, 1],
+      [1, 1, 1],
+      [-1, 1, 0],
+      [1, 1, 0],
+      [-1, 1, -1],
+      [0, 1, -1],
+      [1, 1, -1],
+    ],
+  },
+  {
+    dir: [0, 0, -1],
+    mat3: 'side',
+    mat6: 'nz',
+    corners: [
+      { pos: [1, 0, 0], uv: [0, 0], side1: 3, side2: 6, corner: 5 },
+      { pos: [0, 0, 0], uv: [1, 0], side1: 4, side2: 6, corner: 7 },
+      { pos: [1, 1, 0], uv: [0, 1], side1: 1, side2: 3, corner: 0 },
+      { pos: [0, 1, 0], uv: [1, 1], side1: 1, side2: 4, corner: 2 },
+    ],
+    neighbors: [
+      [1, 1, -1],
+      [0, 1, -1],
+      [-1, 1, -1],
+      [1, 0, -1],
+      [-1, 0, -1],
+      [1, -1, -1],
+      [0, -1, -1],
+      [-1, -1, -1],
+    ],
+  },
+  {
+    dir: [0, 0, 1],
+    mat3: 'side',
+    mat6: 'pz',
+    corners: [
+      { pos: [0, 0, 1], uv: [0, 0], side1: 4, side2: 6, corner: 7 },
+      { pos: [1, 0, 1], uv: [1, 0], side1: 3, side2: 6, corner: 5 },
+      { pos: [0, 1, 1], uv: [0, 1], side1: 1, side2: 4, corner: 2 },
+      { pos: [1, 1, 1], uv: [1, 1], side1: 1, side2: 3, corner: 0 },
+    ],
+    neighbors: [
+      [1, 1, 1],
+      [0, 1, 1],
+      [-1, 1, 1],
+      [1, 0, 1],
+      [-1, 0, 1],
+      [1, -1, 1],
+      [0, -1, 1],
+      [-1, -1, 1],
+    ],
+  },
+];
+
+const PLANT_FACES = [
+  {
+    mat: 'one',
+    corners: [
+      { pos: [0, 1, 0], uv: [0, 1] },
+      { pos: [0, 0, 0], uv: [0, 0] },
+      { pos: [1, 1, 1], uv: [1, 1] },
+      { pos: [1, 0, 1], uv: [1, 0] },
+    ],
+  },
+  {
+    mat: 'two',
+    corners: [
+      { pos: [1, 1, 0], uv: [0, 1] },
+      { pos: [1, 0, 0], uv: [0, 0] },
+      { pos: [0, 1, 1], uv: [1, 1] },
+      { pos: [0, 0, 1], uv: [1, 0] },
+    ],
+  },
+];
+
+const emptyGeometry = (): MeshType => ({ positions: [], indices: [], uvs: [], aos: [], lights: [], tints: [] });
+
+// packed the same way as the lights of the chunks, sunlight on top
+const packLight = (sunlight: number, red: number, green: number, blue: number) =>
+  (sunlight << 12) | (red << 8) | (green << 4) | blue;
+
+const textureType = (textures: { [side: string]: string }) => {
+  const count = Object.keys(textures).length;
+  return count === 1 ? 'mat1' : count === 3 ? 'mat3' : count === 6 ? 'mat6' : 'x';
+};
+
+/**
+ * Meshes chunks the same way the server does, for clients that only get the voxels and lights
+ * of the chunks streamed to them.
+ */
+class Mesher {
+  /**
+   * Mesh both the opaque and transparent parts of a sub-chunk, empty if it can't be seen.
+   *
+   * @param {MesherChunk} chunk
+   * @param {number} subChunk
+   * @param {MesherRegistry} registry
+   * @returns {ServerMeshType}
+   */
+  public static meshSubChunk = (chunk: MesherChunk, subChunk: number, registry: MesherRegistry) => {
+    if (!Mesher.isVisible(chunk, subChunk, registry)) {
+      // still both geometries, so the sub-chunk's old meshes get replaced
+      return <ServerMeshType>{ subChunk, opaque: emptyGeometry(), transparent: emptyGeometry() };
+    }
+
+    return <ServerMeshType>{
+      subChunk,
+      opaque: Mesher.meshChunk(chunk, false, subChunk, registry),
+      transparent: Mesher.meshChunk(chunk, true, subChunk, registry),
+    };
+  };
+
+  /**
+   * Whether any voxel of a sub-chunk could be seen, which it can't if it's all air or if it's
+   * walled in by opaque blocks.
+   *
+   * @param {MesherChunk} chunk
+   * @param {number} subChunk
+   * @param {MesherRegistry} registry
+   * @returns {boolean}
+   */
+  public static isVisible = (chunk: MesherChunk, subChunk: number, registry: MesherRegistry) => {
+    const { min, max, maxHeight, subChunks } = chunk;
+    const unit = maxHeight / subChunks;
+    const startY = subChunk * unit;
+    const endY = startY + unit;
+
+    let empty = true;
+
+    for (let vx = min[0]; vx < max[0] && empty; vx++) {
+      for (let vz = min[2]; vz < max[2] && empty; vz++) {
+        for (let vy = startY; vy < endY; vy++) {
+          if (Mesher.getVoxel(chunk, vx, vy, vz) !== 0) {
+            empty = false;
+            break;
+          }
+        }
+      }
+    }
+
+    if (empty) return false;
+
+    for (let vx = min[0] - 1; vx <= max[0]; vx++) {
+      for (let vz = min[2] - 1; vz <= max[2]; vz++) {
+        for (let vy = startY - 1; vy <= endY; vy++) {
+          if (Mesher.getBlock(registry, Mesher.getVoxel(chunk, vx, vy, vz)).isTransparent) {
+            return true;
+          }
+        }
+      }
+    }
+
+    return false;
+  };
+
+  /**
+   * Mesh either the opaque or the transparent part of a sub-chunk.
+   *
+   * @param {MesherChunk} chunk
+   * @param {boolean} transparent
+   * @param {number} subChunk
+   * @param {MesherRegistry} registry
+   * @returns {MeshType}
+   */
+  public static meshChunk = (chunk: MesherChunk, transparent: boolean, subChunk: number, registry: MesherRegistry) => {
+    const { min, max, dimension, maxHeight, subChunks } = chunk;
+    const geometry = emptyGeometry();
+    const { positions, indices, uvs, aos, lights, tints } = geometry;
+
+    const [startX, , startZ] = min;
+    const [endX, , endZ] = max;
+    const depth = endZ - startZ + 1;
+
+    const tintAt = (tinted: boolean, cornerX: number, cornerZ: number) => {
+      if (!tinted || !chunk.tints || !chunk.tints.length) return UNTINTED;
+      return chunk.tints[(cornerX - startX) * depth + (cornerZ - startZ)];
+    };
+
+    const isTransparent = (id: number) => Mesher.getBlock(registry, id).isTransparent;
+
+    const vertexAO = (side1: number, side2: number, corner: number) => {
+      const numS1 = isTransparent(side1) ? 0 : 1;
+      const numS2 = isTransparent(side2) ? 0 : 1;
+      const numC = isTransparent(corner) ? 0 : 1;
+
+      if (numS1 === 1 && numS2 === 1) {
+        return 0;
+      }
+
+      return 3 - (numS1 + numS2 + numC);
+    };
+
+    const unit = maxHeight / subChunks;
+
+    for (let vx = startX; vx < endX; vx++) {
+      for (let vz = startZ; vz < endZ; vz++) {
+        for (let vy = subChunk * unit; vy < (subChunk + 1) * unit; vy++) {
+          const voxelId = Mesher.getVoxel(chunk, vx, vy, vz);
+          const block = Mesher.getBlock(registry, voxelId);
+          const { isSolid, isPlant, isBlock, textures } = block;
+          const tintedFaces = block.tintedFaces || [];
+
+          if (!(isSolid || isPlant) || block.isTransparent !== transparent) {
+            continue;
+          }
+
+          if (isPlant) {
+            const offset = (1 - PLANT_SHRINK) / 2;
+            const light = Mesher.getLight(chunk, vx, vy, vz);
+
+            for (const { corners, mat } of PLANT_FACES) {
+              const tinted = tintedFaces.includes(mat);
+              const { startU, endU, startV, endV } = registry.ranges[textures[mat]];
+              const ndx = positions.length / 3;
+
+              for (const { pos, uv } of corners) {
+                positions.push((pos[0] * PLANT_SHRINK + offset + vx) * dimension);
+                positions.push((pos[1] + vy) * dimension);
+                positions.push((pos[2] * PLANT_SHRINK + offset + vz) * dimension);
+
+                uvs.push(uv[0] * (endU - startU) + startU);
+                uvs.push(uv[1] * (startV - endV) + endV);
+
+                lights.push(light);
+                aos.push(3);
+                tints.push(tintAt(tinted, vx + pos[0], vz + pos[2]));
+              }
+
+              indices.push(ndx, ndx + 1, ndx + 2, ndx + 2, ndx + 1, ndx + 3);
+            }
+          } else if (isBlock) {
+            const type = textureType(textures);
+
+            for (const { dir, mat3, mat6, corners, neighbors } of BLOCK_FACES) {
+              const neighborId = Mesher.getVoxel(chunk, vx + dir[0], vy + dir[1], vz + dir[2]);
+              const neighbor = Mesher.getBlock(registry, neighborId);
+
+              if (
+                !neighbor.isTransparent ||
+                !(
+                  !transparent ||
+                  neighbor.isEmpty ||
+                  neighborId !== voxelId ||
+                  (neighbor.transparentStandalone && dir[0] + dir[1] + dir[2] >= 1)
+                )
+              ) {
+                continue;
+              }
+
+              const nearVoxels = neighbors.map(([a, b, c]) => Mesher.getVoxel(chunk, vx + a, vy + b, vz + c));
+
+              const side = type === 'mat1' ? 'all' : type === 'mat3' ? mat3 : mat6;
+              const tinted = tintedFaces.includes(side);
+              const { startU, endU, startV, endV } = registry.ranges[textures[side]];
+
+              const ndx = positions.length / 3;
+              const faceAOs: number[] = [];
+              const fourSunlights: number[] = [];
+              const fourRedLights: number[] = [];
+              const fourGreenLights: number[] = [];
+              const fourBlueLights: number[] = [];
+
+              for (const { pos, uv, side1, side2, corner } of corners) {
+                const posX = pos[0] + vx;
+                const posY = pos[1] + vy;
+                const posZ = pos[2] + vz;
+
+                positions.push(posX * dimension, posY * dimension, posZ * dimension);
+
+                uvs.push(uv[0] * (endU - startU) + startU);
+                uvs.push(uv[1] * (startV - endV) + endV);
+                tints.push(tintAt(tinted, posX, posZ));
+                faceAOs.push(vertexAO(nearVoxels[side1], nearVoxels[side2], nearVoxels[corner]));
+
+                // averaging the lights of the transparent voxels among the 8 around the vertex
+                const dx = pos[0] === 0 ? -1 : 1;
+                const dy = pos[1] === 0 ? -1 : 1;
+                const dz = pos[2] === 0 ? -1 : 1;
+
+                const sums = [0, 0, 0, 0];
+                let count = 0;
+
+                for (const [ox, oy, oz] of [
+                  [0, 0, 0],
+                  [0, 0, dz],
+                  [0, dy, 0],
+                  [0, dy, dz],
+                  [dx, 0, 0],
+                  [dx, 0, dz],
+                  [dx, dy, 0],
+                  [dx, dy, dz],
+                ]) {
+                  const nx = vx + ox;
+                  const ny = vy + oy;
+                  const nz = vz + oz;
+
+                  if (!isTransparent(Mesher.getVoxel(chunk, nx, ny, nz))) continue;
+
+                  const light = Mesher.getLight(chunk, nx, ny, nz);
+                  sums[0] += (light >> 12) & 0xf;
+                  sums[1] += (light >> 8) & 0xf;
+                  sums[2] += (light >> 4) & 0xf;
+                  sums[3] += light & 0xf;
+                  count++;
+                }
+
+                // the server averages as floats and truncates, NaN turning into zero
+                const average = (sum: number) => (count ? Math.trunc(sum / count) : 0);
+
+                fourSunlights.push(average(sums[0]));
+                fourRedLights.push(average(sums[1]));
+                fourGreenLights.push(average(sums[2]));
+                fourBlueLights.push(average(sums[3]));
+              }
+
+              const [aRt, bRt, cRt, dRt] = fourRedLights;
+              const [aGt, bGt, cGt, dGt] = fourGreenLights;
+              const [aBt, bBt, cBt, dBt] = fourBlueLights;
+
+              const threshold = 0;
+
+              // at least one zero
+              const oneTr0 = aRt <= threshold || bRt <= threshold || cRt <= threshold || dRt <= threshold;
+              const oneTg0 = aGt <= threshold || bGt <= threshold || cGt <= threshold || dGt <= threshold;
+              const oneTb0 = aBt <= threshold || bBt <= threshold || cBt <= threshold || dBt <= threshold;
+              // one is zero, and ao rule, but only for zero AO's
+              const fequals = faceAOs[0] + faceAOs[3] === faceAOs[1] + faceAOs[2];
+              const ozaoR = aRt + dRt < bRt + cRt && fequals;
+              const ozaoG = aGt + dGt < bGt + cGt && fequals;
+              const ozaoB = aBt + dBt < bBt + cBt && fequals;
+              // all not zero, 4 parts
+              const anzp1R =
+                (bRt > (aRt + dRt) / 2 && (aRt + dRt) / 2 > cRt) || (cRt > (aRt + dRt) / 2 && (aRt + dRt) / 2 > bRt);
+              const anzp1G =
+                (bGt > (aGt + dGt) / 2 && (aGt + dGt) / 2 > cGt) || (cGt > (aGt + dGt) / 2 && (aGt + dGt) / 2 > bGt);
+              const anzp1B =
+                (bBt > (aBt + dBt) / 2 && (aBt + dBt) / 2 > cBt) || (cBt > (aBt + dBt) / 2 && (aBt + dBt) / 2 > bBt);
+              // fixed two light sources colliding
+              const anzR = oneTr0 && anzp1R;
+              const anzG = oneTg0 && anzp1G;
+              const anzB = oneTb0 && anzp1B;
+
+              indices.push(ndx, ndx + 1);
+
+              if (
+                faceAOs[0] + faceAOs[3] > faceAOs[1] + faceAOs[2] ||
+                ozaoR ||
+                ozaoG ||
+                ozaoB ||
+                anzR ||
+                anzG ||
+                anzB
+              ) {
+                // generate flipped quad
+                indices.push(ndx + 3, ndx + 3, ndx + 2, ndx);
+              } else {
+                indices.push(ndx + 2, ndx + 2, ndx + 1, ndx + 3);
+              }
+
+              aos.push(...faceAOs);
+              for (let i = 0; i < 4; i++) {
+                lights.push(packLight(fourSunlights[i], fourRedLights[i], fourGreenLights[i], fourBlueLights[i]));
+              }
+            }
+          }
+        }
+      }
+    }
+
+    return geometry;
+  };
+
+  private static getBlock = (registry: MesherRegistry, id: number) => {
+    return registry.blocks[id] || registry.blocks[0];
+  };
+
+  // voxels out of the chunk and its padding are air, like on the server
+  private static getVoxel = (chunk: MesherChunk, vx: number, vy: number, vz: number) => {
+    const [lx, ly, lz] = Mesher.toLocal(chunk, vx, vy, vz);
+    if (!Mesher.containsLocal(chunk, lx, ly, lz)) return 0;
+    return chunk.voxels.get(lx, ly, lz);
+  };
+
+  private static getLight = (chunk: MesherChunk, vx: number, vy: number, vz: number) => {
+    const [lx, ly, lz] = Mesher.toLocal(chunk, vx, vy, vz);
+    if (!Mesher.containsLocal(chunk, lx, ly, lz)) return 0;
+    return chunk.lights.get(lx, ly, lz);
+  };
+
+  private static toLocal = (chunk: MesherChunk, vx: number, vy: number, vz: number): Coords3 => {
+    return [vx - chunk.min[0] + DATA_PADDING, vy, vz - chunk.min[2] + DATA_PADDING];
+  };
+
+  private static containsLocal = (chunk: MesherChunk, lx: number, ly: number, lz: number) => {
+    const width = chunk.size + DATA_PADDING * 2;
+    return lx >= 0 && lx < width && ly >= 0 && ly < chunk.maxHeight && lz >= 0 && lz < width;
+  };
+}
+
+export { Mesher, MesherChunk, MesherRegistry };
@@ -7,6 +7,12 @@ import { Engine } from './engine';
 
 const { Message, ChatMessage } = protocol;
 
+// version of messages.proto, bumped whenever a change breaks older servers or clients
+const PROTOCOL_VERSION = 3;
+
+// session token from logging in, which the server wants to connect
+const LOCAL_STORAGE_TOKEN = 'mine.js-token';
+
 type CustomWebSocket = WebSocket & {
   sendEvent: (event) => void;
   serverURL: string;
@@ -14,6 +20,8 @@ type CustomWebSocket = WebSocket & {
 
 type NetworkOptionsType = {
   reconnectTimeout: number;
+  // ask for the voxels and lights of chunks to mesh them here, instead of their meshes
+  clientMeshing: boolean;
 };
 
 class Network {
@@ -21,6 +29,16 @@ class Network {
 
   public url = Helper.getServerURL({ path: '/ws/' });
   public connected = false;
+  // capabilities agreed on with the server in the handshake
+  public capabilities: {
+    version: number;
+    compression: boolean;
+    meshEncoding: number;
+    clientMeshing: boolean;
+    renderRadius: number;
+  };
+  // set when the server refuses this client, as reconnecting wouldn't help
+  public refused = false;
 
   private reconnection: NodeJS.Timeout;
 
@@ -46,6 +64,7 @@ class Network {
     socket.protocol = socket.protocol.replace(/http/, 'ws');
     socket.hash = '';
     socket.searchParams.set('world', this.engine.world.name);
+    socket.searchParams.set('token', Network.token || '');
 
     const server = new WebSocket(socket.toString()) as CustomWebSocket;
     server.binaryType = 'arraybuffer';
@@ -54,8 +73,19 @@ class Network {
       server.send(encoded);
     };
     server.onopen = () => {
+      server.sendEvent({
+        type: 'HANDSHAKE',
+        handshake: {
+          version: PROTOCOL_VERSION,
+          compression: true,
+          meshEncoding: 0,
+          clientMeshing: this.options.clientMeshing,
+          // the server streams every chunk within this radius
+          renderRadius: this.engine.world.options.requestRadius,
+        },
+      });
+
       this.engine.emit('connected');
-      this.engine.world.handleReconnection();
       this.connected = true;
 
       clearTimeout(this.reconnection);
@@ -66,6 +96,8 @@ class Network {
       this.engine.emit('disconnected');
       this.connected = false;
 
+      if (this.refused) return;
+
       this.reconnection = setTimeout(() => {
         this.connect();
       }, this.options.reconnectTimeout);
@@ -83,30 +115,62 @@ class Network {
     const { world, player, peers, chat } = engine;
 
     switch (type) {
+      case 'HANDSHAKE': {
+        this.capabilities = event.handshake;
+        break;
+      }
+
+      case 'ERROR': {
+        const { text } = event;
+        this.refused = true;
+        chat.add({ type: 'ERROR', body: text });
+        break;
+      }
+
       case 'INIT': {
         const {
-          json: { id, time, tickSpeed, spawn, passables },
+          init: { id, time, tickSpeed, spawn, position, rotation, passables, canFly, name },
         } = event;
         player.id = id;
+        player.setName(name);
+        player.setCanFly(!!canFly);
         world.setTime(time, false);
         world.setBlockData({ passables });
         engine.setTick(tickSpeed, false);
         player.teleport(spawn);
+        // returning players are put back exactly where they were looking from
+        if (position && position.length === 3) {
+          const [px, py, pz] = position;
+          player.setPosition([px, py, pz]);
+        }
+        if (rotation && rotation.length === 4) {
+          const [qx, qy, qz, qw] = rotation;
+          player.object.quaternion.set(qx, qy, qz, qw);
+        }
         engine.emit('init');
         break;
       }
 
       case 'CONFIG': {
+        // only the settings that changed are set
+        const { config } = event;
+        if (config.maybeTime === 'time') world.setTime(config.time, false);
+        if (config.maybeTickSpeed === 'tickSpeed') engine.setTick(config.tickSpeed, false);
+        if (config.maybeCanFly === 'canFly') player.setCanFly(config.canFly);
+        break;
+      }
+
+      case 'TELEPORT': {
+        // the server couldn't get the player to where they said they were
         const {
-          json: { time, tickSpeed },
+          peers: [{ px, py, pz }],
         } = event;
-        if (Helper.isNumber(time)) world.setTime(time, false);
-        if (Helper.isNumber(tickSpeed)) engine.setTick(tickSpeed, false);
+        player.setPosition([px || 0, py || 0, pz || 0]);
         break;
       }
 
       case 'UPDATE': {
-        const { updates, chunks } = event;
+        const { updates, chunks, lights } = event;
 
         for (const chunkData of chunks) {
           world.handleServerChunk(chunkData, type === 'UPDATE');
@@ -115,6 +179,13 @@ class Network {
         const mapped = updates.map((u) => ({ voxel: [u.vx, u.vy, u.vz], type: u.type }));
         world.setManyVoxels(mapped, false);
 
+        if (this.capabilities?.clientMeshing) {
+          // the server only sends what changed, so the meshes are rebuilt here
+          const mappedLights = lights.map((l) => ({ voxel: [l.vx || 0, l.vy || 0, l.vz || 0], level: l.level || 0 }));
+          world.setManyLights(mappedLights);
+          world.remeshAround([...mapped, ...mappedLights].map(({ voxel }) => voxel));
+        }
+
         break;
       }
 
@@ -126,6 +197,12 @@ class Network {
         break;
       }
 
+      case 'UNLOAD': {
+        const { unloads } = event;
+        world.unloadChunks(unloads.map(({ x, z }) => [x || 0, z || 0]));
+        break;
+      }
+
       case 'JOIN': {
         const { text: id } = event;
         peers.join(id);
@@ -155,6 +232,39 @@ class Network {
       case 'MESSAGE': {
         const { message } = event;
         chat.add(message);
+        break;
+      }
+
+      case 'REJECT': {
+        // the server refused some of our edits, so put back what it has instead
+        const { rejections } = event;
+        const rolledBack = rejections.map(({ update, current }) => ({
+          voxel: [update.vx || 0, update.vy || 0, update.vz || 0],
+          type: current || 0,
+        }));
+        world.setManyVoxels(rolledBack, false);
+        engine.emit('rejected', rejections);
+        break;
+      }
+
+      case 'LIGHTS': {
+        const { lights, fullLights } = event;
+        const mapped = lights.map((l) => ({ voxel: [l.vx || 0, l.vy || 0, l.vz || 0], level: l.level || 0 }));
+        world.setDynamicLights(mapped, !!fullLights);
+        break;
+      }
+
+      case 'REGISTRY': {
+        // blocks or textures were reloaded on the server
+        this.fetchData('world', { world: world.name }).then((worldData) => {
+          engine.load(worldData);
+          engine.registry.reloadAtlas();
+
+          if (this.capabilities?.clientMeshing) {
+            world.remeshChunks();
+          }
+        });
+        break;
       }
     }
   };
@@ -170,6 +280,18 @@ class Network {
     this.onEvent(event);
   };
 
+  // follow areas away from the player, streaming their chunks, peers and block updates
+  subscribe = (areas: { x: number; z: number; radius: number }[]) => {
+    if (!this.connected || !areas.length) return;
+    this.server.sendEvent({ type: 'SUBSCRIBE', areas });
+  };
+
+  // stop following the given areas, or every area if none are given
+  unsubscribe = (areas: { x: number; z: number; radius: number }[] = []) => {
+    if (!this.connected) return;
+    this.server.sendEvent({ type: 'UNSUBSCRIBE', areas });
+  };
+
   fetchData = async (path: string, args: { [key: string]: any } = {}) => {
     const url = Helper.getServerURL();
     url.path = `/${path.replace('/', '')}`;
@@ -183,6 +305,33 @@ class Network {
     return url.clearQuery().toString();
   }
 
+  static get token() {
+    return localStorage.getItem(LOCAL_STORAGE_TOKEN);
+  }
+
+  // log into an account, or create it first, keeping the token to connect with
+  static authenticate = async (username: string, password: string, register = false) => {
+    const url = Helper.getServerURL({ path: register ? '/register' : '/login' });
+    const response = await fetch(url.toString(), {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ username, password }),
+    });
+
+    if (!response.ok) {
+      throw new Error(await response.text());
+    }
+
+    const session = await response.json();
+    localStorage.setItem(LOCAL_STORAGE_TOKEN, session.token);
+
+    return session as { id: number; username: string; token: string };
+  };
+
+  static logout = () => {
+    localStorage.removeItem(LOCAL_STORAGE_TOKEN);
+  };
+
   static decode(buffer) {
     if (buffer[0] === 0x78 && buffer[1] === 0x9c) {
       buffer = Pako.inflate(buffer);
@@ -190,9 +339,6 @@ class Network {
     const message = Message.decode(buffer);
     // @ts-ignore
     message.type = Message.Type[message.type];
-    if (message.json) {
-      message.json = JSON.parse(message.json);
-    }
     if (message.message) {
       // @ts-ignore
       message.message.type = ChatMessage.Type[message.message.type];
@@ -201,9 +347,6 @@ class Network {
   }
 
   static encode(message) {
-    if (message.json) {
-      message.json = JSON.stringify(message.json);
-    }
     message.type = Message.Type[message.type];
     if (message.message) {
       message.message.type = ChatMessage.Type[message.message.type];
@@ -105,7 +105,9 @@ class Particles {
     geometry.setAttribute('lights', new Float32BufferAttribute(lights, 4));
     geometry.attributes.uv.needsUpdate = true;
 
-    const { countPerSide, textureSize } = this.engine.config.registry;
+    const { countPerSide, textureSize, uvPadding } = this.engine.config.registry;
+    // each tile only spans the inside of its padded cell
+    const repeat = textureSize / (countPerSide * (textureSize + 2 * uvPadding));
     const material = new ShaderMaterial({
       uniforms: {
         uTexture: { value: this.engine.registry.atlasUniform.value },
@@ -113,7 +115,7 @@ class Particles {
           value: (window.innerHeight / (2.0 * Math.tan((0.5 * 60.0 * Math.PI) / 180.0))) * PARTICLE_SCALE,
         },
         uRepeat: {
-          value: new Vector2(1 / countPerSide - (0.1 / textureSize) * 2, 1 / countPerSide - (0.1 / textureSize) * 2),
+          value: new Vector2(repeat, repeat),
         },
         uScale: {
           value: 1,
@@ -54,6 +54,7 @@ class Peers {
               qy,
               qz,
               qw,
+              held: engine.inventory.hand,
             },
           ],
         });
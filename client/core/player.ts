@@ -31,6 +31,8 @@ class Player {
   public id: string;
   public name: string;
   public godMode = true;
+  // whether the server lets the player fly and go through blocks
+  public canFly = true;
 
   public controls: PointerLockControls;
 
@@ -362,7 +364,39 @@ class Player {
     return newPosition;
   };
 
+  // put the player's eyes somewhere, such as where the server corrected them to
+  setPosition = (eye: Coords3) => {
+    const {
+      config: {
+        world: { dimension },
+        player: { bodyWidth, distToGround },
+      },
+    } = this.engine;
+
+    const [x, y, z] = eye;
+
+    if (this.godMode) {
+      this.controls.getObject().position.set(x, y, z);
+    } else {
+      this.entity.body.setPosition([
+        x - (bodyWidth / 2) * dimension,
+        y - distToGround * dimension,
+        z - (bodyWidth / 2) * dimension,
+      ]);
+    }
+  };
+
+  setCanFly = (canFly: boolean) => {
+    this.canFly = canFly;
+
+    if (!canFly && this.godMode) {
+      this.toggleGodMode();
+    }
+  };
+
   toggleGodMode = () => {
+    if (!this.godMode && !this.canFly) return;
+
     this.godMode = !this.godMode;
     this.shadowMesh.visible = !this.godMode;
     if (this.godMode) {
@@ -391,6 +425,7 @@ class Player {
     this.entity.body.applyImpulse([0, 4, 0]);
   };
 
+  // names come from the player's account, as the server tells
   setName = (name: string) => {
     this.name = name || ' ';
     localStorage.setItem(LOCAL_STORAGE_PLAYER_NAME, this.name);
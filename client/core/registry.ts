@@ -4,6 +4,7 @@ import {
   DoubleSide,
   Float32BufferAttribute,
   FrontSide,
+  ImageLoader,
   Mesh,
   MeshBasicMaterial,
   NearestFilter,
@@ -38,7 +39,14 @@ type Block = {
   isTransparent: boolean;
   name: string;
   textures: { [key: string]: string };
+  // faces tinted by the biome, such as the top of grass
+  tintedFaces?: string[];
   transparentStandalone: boolean;
+  lightAttenuation: { sunlight: number; red: number; green: number; blue: number };
+  hardness: number;
+  tool?: 'pickaxe' | 'axe' | 'shovel' | 'shears';
+  drops?: { block: string; min: number; max: number; probability: number }[];
+  soundGroup?: string;
 };
 
 type Range = {
@@ -57,6 +65,7 @@ type RegistryOptionsType = {
   resolution: number;
   countPerSide?: number;
   textureSize?: number;
+  uvPadding?: number;
   blocks?: Block[];
   ranges?: Range[];
 };
@@ -159,7 +168,7 @@ class Registry {
 
     engine.on('ready', () => {
       this.atlasUniform = {
-        value: new TextureLoader().load(`${engine.network.cleanURL}atlas`, () => {
+        value: new TextureLoader().load(`${engine.network.cleanURL}atlas?world=${engine.config.world.name}`, () => {
           engine.emit('texture-loaded');
         }),
       };
@@ -192,6 +201,21 @@ class Registry {
     });
   }
 
+  reloadAtlas = () => {
+    const { engine } = this;
+
+    // cache-busted, so the browser doesn't hand back the old atlas
+    const url = `${engine.network.cleanURL}atlas?world=${engine.config.world.name}&t=${Date.now()}`;
+
+    new ImageLoader().load(url, (image) => {
+      const atlas = this.atlasUniform.value;
+      atlas.image = image;
+      atlas.needsUpdate = true;
+
+      engine.emit('texture-loaded');
+    });
+  };
+
   focus = (id: number) => {
     const { isBlock, isPlant } = this.options.blocks[id];
     if (isBlock) {
@@ -375,6 +399,7 @@ uniform float uSunlightIntensity;
 
 varying float vAO;
 varying vec4 vLight; 
+varying vec3 vTint;
 `,
         )
         .replace(
@@ -386,6 +411,7 @@ float scale = 1.0;
 outgoingLight.rgb *= vec3(s + pow(vLight.r, scale), s + pow(vLight.g, scale), s + pow(vLight.b, scale));
 // outgoingLight.rgb *= vec3(s + scale / sqrt(vLight.r), s + scale / sqrt(vLight.g), s + scale / sqrt(vLight.b));
 outgoingLight *= 0.88 * vAO;
+outgoingLight.rgb *= vTint;
 `,
         )
         .replace(
@@ -402,9 +428,11 @@ gl_FragColor.rgb = mix(gl_FragColor.rgb, mix(uFogNearColor, uFogColor, fogFactor
           `
 attribute int ao;
 attribute int light;
+attribute int tint;
 
 varying float vAO;
 varying vec4 vLight;
+varying vec3 vTint;
 
 uniform vec4 uAOTable;
 
@@ -428,6 +456,7 @@ vAO = ((ao == 0) ? uAOTable.x :
     (ao == 1) ? uAOTable.y :
     (ao == 2) ? uAOTable.z : uAOTable.w) / 255.0; 
 vLight = unpackLight(light);
+vTint = vec3(float((tint >> 16) & 0xFF), float((tint >> 8) & 0xFF), float(tint & 0xFF)) / 255.0;
 `,
         ),
 
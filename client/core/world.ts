@@ -6,6 +6,7 @@ import { Helper } from '../utils';
 
 import { Chunk } from './chunk';
 import { Engine } from './engine';
+import { Mesher } from './mesher';
 
 type WorldOptionsType = {
   name?: string;
@@ -39,10 +40,10 @@ class World extends EventEmitter {
   private camChunkName: string;
   private camChunkPos: Coords2;
 
-  private pendingChunks: Coords2[] = [];
-  private requestedChunks: Set<string> = new Set();
   private receivedChunks: ServerChunkType[] = [];
   private chunks: Map<string, Chunk> = new Map();
+  // torch lights from players and entities carrying them, by voxel
+  private dynamicLights: Map<string, number> = new Map();
 
   constructor(public engine: Engine, public options: WorldOptionsType) {
     super();
@@ -78,7 +79,6 @@ class World extends EventEmitter {
 
   tick = () => {
     this.checkCamChunk();
-    this.requestChunks();
     this.meshChunks();
     this.animateSky();
   };
@@ -158,17 +158,21 @@ class World extends EventEmitter {
 
   getRedLight = (vCoords: Coords3) => {
     const chunk = this.getChunkByVoxel(vCoords);
-    return chunk?.getRedLight(...vCoords) || 0;
+    return Math.max(chunk?.getRedLight(...vCoords) || 0, (this.getDynamicLight(vCoords) >> 8) & 0xf);
   };
 
   getGreenLight = (vCoords: Coords3) => {
     const chunk = this.getChunkByVoxel(vCoords);
-    return chunk?.getGreenLight(...vCoords) || 0;
+    return Math.max(chunk?.getGreenLight(...vCoords) || 0, (this.getDynamicLight(vCoords) >> 4) & 0xf);
   };
 
   getBlueLight = (vCoords: Coords3) => {
     const chunk = this.getChunkByVoxel(vCoords);
-    return chunk?.getBlueLight(...vCoords) || 0;
+    return Math.max(chunk?.getBlueLight(...vCoords) || 0, this.getDynamicLight(vCoords) & 0xf);
+  };
+
+  getDynamicLight = (vCoords: Coords3) => {
+    return this.dynamicLights.get(vCoords.join('|')) || 0;
   };
 
   getSunlight = (vCoords: Coords3) => {
@@ -179,18 +183,31 @@ class World extends EventEmitter {
   handleServerChunk = (serverChunk: ServerChunkType, prioritized = false) => {
     serverChunk.x = serverChunk.x || 0;
     serverChunk.z = serverChunk.z || 0;
-    const { x: cx, z: cz } = serverChunk;
-    const coords = [cx, cz] as Coords2;
-    this.requestedChunks.delete(Helper.getChunkName(coords));
     if (prioritized) this.meshChunk(serverChunk);
     else this.receivedChunks.push(serverChunk);
   };
 
   setChunk = (chunk: Chunk) => {
-    // TODO: remove chunks that are too far away
     return this.chunks.set(chunk.name, chunk);
   };
 
+  // the server streams the chunks around, and tells which ones went out of range
+  unloadChunks = (coords: Coords2[]) => {
+    const names = new Set(coords.map((c) => Helper.getChunkName(c)));
+
+    // chunks still waiting to be meshed shouldn't come back
+    this.receivedChunks = this.receivedChunks.filter(({ x, z }) => !names.has(Helper.getChunkName([x, z])));
+
+    names.forEach((name) => {
+      const chunk = this.chunks.get(name);
+      if (!chunk) return;
+
+      chunk.removeFromScene();
+      chunk.dispose();
+      this.chunks.delete(name);
+    });
+  };
+
   setVoxel = (voxel: Coords3, type: number, sideEffects = true) => {
     const [vx, vy, vz] = voxel;
 
@@ -228,11 +245,46 @@ class World extends EventEmitter {
         { count: voxels.length > 3 ? 1 : 6 },
       );
       voxels.forEach(({ voxel, type }) => {
-        this.getChunkByVoxel(voxel)?.setVoxel(voxel[0], voxel[1], voxel[2], type);
+        this.getChunksContaining(voxel).forEach((chunk) => chunk.setVoxel(voxel[0], voxel[1], voxel[2], type));
       });
     }
   };
 
+  // lights that changed on the server, for chunks meshed on this side
+  setManyLights = (lights: { voxel: Coords3; level: number }[]) => {
+    lights.forEach(({ voxel, level }) => {
+      this.getChunksContaining(voxel).forEach((chunk) => chunk.setLight(voxel[0], voxel[1], voxel[2], level));
+    });
+  };
+
+  // remesh the sub-chunks whose meshes could show any of the voxels
+  remeshAround = (voxels: Coords3[]) => {
+    const { maxHeight, subChunks } = this.options;
+    const unit = maxHeight / subChunks;
+    const dirty: Map<Chunk, Set<number>> = new Map();
+
+    voxels.forEach((voxel) => {
+      const vy = voxel[1];
+      const subChunk = Math.floor(vy / unit);
+      // voxels on the edge of a sub-chunk show up in the meshes of the one next to it
+      const affected = [subChunk];
+      if (vy % unit === 0 && subChunk > 0) affected.push(subChunk - 1);
+      if (vy % unit === unit - 1 && subChunk < subChunks - 1) affected.push(subChunk + 1);
+
+      this.getChunksContaining(voxel).forEach((chunk) => {
+        if (!dirty.has(chunk)) dirty.set(chunk, new Set());
+        affected.forEach((i) => dirty.get(chunk).add(i));
+      });
+    });
+
+    dirty.forEach((indices, chunk) => this.remeshChunk(chunk, Array.from(indices)));
+  };
+
+  // remesh every chunk, such as when the blocks or textures get reloaded
+  remeshChunks = () => {
+    this.chunks.forEach((chunk) => this.remeshChunk(chunk));
+  };
+
   breakVoxel = () => {
     const voxel = this.engine.player.lookBlock;
     if (voxel) {
@@ -275,6 +327,19 @@ class World extends EventEmitter {
     this.surroundCamChunks();
   };
 
+  setDynamicLights = (lights: { voxel: Coords3; level: number }[], full = false) => {
+    // a full set replaces every dynamic light known so far
+    if (full) this.dynamicLights.clear();
+
+    lights.forEach(({ voxel, level }) => {
+      const name = voxel.join('|');
+      if (level) this.dynamicLights.set(name, level);
+      else this.dynamicLights.delete(name);
+    });
+
+    this.emit('dynamic-lights', lights);
+  };
+
   setTime = (time: number, sideEffect = true) => {
     this.sky.tracker.time = time % 2400;
 
@@ -287,7 +352,7 @@ class World extends EventEmitter {
     if (sideEffect) {
       this.engine.network.server.sendEvent({
         type: 'CONFIG',
-        json: {
+        config: {
           time: this.sky.tracker.time,
         },
       });
@@ -298,18 +363,6 @@ class World extends EventEmitter {
     if (passables && passables.length) this.blockData.passables = passables;
   };
 
-  sortPendingChunks = () => {
-    const [cx, cz] = this.camChunkPos;
-
-    this.pendingChunks.sort((a, b) => (cx - a[0]) ** 2 + (cz - a[1]) ** 2 - (cx - b[0]) ** 2 - (cz - b[1]) ** 2);
-  };
-
-  handleReconnection = () => {
-    // move requested chunks to pending
-    this.pendingChunks.push(...Array.from(this.requestedChunks).map((rc) => Helper.parseChunkName(rc) as Coords2));
-    this.sortPendingChunks();
-  };
-
   get chunksLoaded() {
     return this.chunks.size;
   }
@@ -373,64 +426,18 @@ class World extends EventEmitter {
   };
 
   private surroundCamChunks = () => {
-    const { renderRadius, requestRadius, chunkSize } = this.options;
-
-    const [cx, cz] = this.camChunkPos;
-
-    for (let x = cx - requestRadius; x <= cx + requestRadius; x++) {
-      for (let z = cz - requestRadius; z <= cz + requestRadius; z++) {
-        const dx = x - cx;
-        const dz = z - cz;
-        if (dx * dx + dz * dz > requestRadius * requestRadius) continue;
-
-        const chunk = this.getChunkByCPos([x, z]);
-
-        if (!chunk && !this.requestedChunks.has(Helper.getChunkName([x, z]))) {
-          this.pendingChunks.push([x, z]);
-        }
-      }
-    }
-
-    this.pendingChunks = Array.from(new Set(this.pendingChunks.map((pc) => Helper.getChunkName(pc)))).map(
-      (pcStr) => Helper.parseChunkName(pcStr) as Coords2,
-    );
+    const { renderRadius, chunkSize } = this.options;
 
-    // make pending chunks radiate from player, might have easier ways of doing so
-    this.sortPendingChunks();
-
-    // if the chunk is too far away, remove from scene.
+    // chunks are kept until the server unloads them, but the ones too far away are hidden
     const deleteDistance = renderRadius * chunkSize * 1.414;
-    const removeDistance = requestRadius * chunkSize * 1.414;
     for (const chunk of this.chunks.values()) {
       const dist = chunk.distTo(...this.engine.player.voxel);
       if (dist > deleteDistance) {
         chunk.removeFromScene();
       }
-
-      if (dist > removeDistance) {
-        chunk.dispose();
-        this.chunks.delete(chunk.name);
-      }
     }
   };
 
-  private requestChunks = () => {
-    // separate chunk request into frames to avoid clogging
-    if (this.pendingChunks.length === 0 || !this.engine.connected) return;
-
-    // don't clog up the server
-    const framePendingChunks = this.pendingChunks.splice(0, 2);
-    framePendingChunks.forEach(([cx, cz]) => {
-      const rep = Helper.getChunkName([cx, cz]);
-      if (this.requestedChunks.has(rep)) return;
-      this.engine.network.server.sendEvent({
-        type: 'REQUEST',
-        json: { x: cx, z: cz },
-      });
-      this.requestedChunks.add(rep);
-    });
-  };
-
   private meshChunks = () => {
     // separate chunk meshing into frames to avoid clogging
     if (this.receivedChunks.length === 0) return;
@@ -453,12 +460,28 @@ class World extends EventEmitter {
       this.setChunk(chunk);
     }
 
-    const { meshes, voxels, lights } = serverChunk;
-
-    chunk.setupMesh(meshes);
+    const { meshes, voxels, lights, tints } = serverChunk;
 
     if (voxels.length) chunk.voxels.data = serverChunk.voxels;
     if (lights.length) chunk.lights.data = serverChunk.lights;
+    if (tints && tints.length) chunk.tints = tints;
+
+    if (this.engine.network.capabilities?.clientMeshing && !meshes.length) {
+      this.remeshChunk(chunk);
+    } else {
+      chunk.setupMesh(meshes);
+    }
+  };
+
+  private remeshChunk = (chunk: Chunk, subChunks?: number[]) => {
+    const indices = subChunks || Array.from({ length: this.options.subChunks }, (_, i) => i);
+    const registry = this.engine.registry.options;
+    chunk.setupMesh(indices.map((i) => Mesher.meshSubChunk(chunk, i, registry)));
+  };
+
+  // the chunk a voxel is in, along with the neighbors that have it in their padding
+  private getChunksContaining = (voxel: Coords3) => {
+    return [this.getChunkByVoxel(voxel), ...this.getNeighborChunksByVoxel(voxel, 1)].filter(Boolean);
   };
 
   private animateSky = () => {
@@ -58,6 +58,8 @@ export type ServerChunkType = {
   meshes: ServerMeshType[];
   voxels: Uint8Array;
   lights: Uint8Array;
+  // biome tints at the corners of the columns, only for clients that mesh chunks themselves
+  tints: number[];
   json: { voxel: Coords3; type: number };
 };
 
@@ -67,14 +69,12 @@ export type Coords3 = [x: number, y: number, z: number];
 export type TypeMap = { [key: string]: number };
 
 export type MeshType = {
-  positions: Float32Array;
-  indices: Float32Array;
-  uvs: Float32Array;
-  aos: Float32Array;
-  sunlights: Int32Array;
-  redLights: Int32Array;
-  greenLights: Int32Array;
-  blueLights: Int32Array;
+  positions: number[];
+  indices: number[];
+  uvs: number[];
+  aos: number[];
+  lights: number[];
+  tints: number[];
 };
 
 export type MESSAGE_TYPE = 'ERROR' | 'SERVER' | 'PLAYER' | 'INFO';
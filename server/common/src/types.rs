@@ -15,6 +15,60 @@ pub struct UV {
     pub end_v: f32,
 }
 
+/// Tools that break some blocks faster than by hand
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Tool {
+    Pickaxe,
+    Axe,
+    Shovel,
+    Shears,
+}
+
+/// Between `min` and `max` of a block, yielded at a `probability` when a block is broken
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockDrop {
+    /// Name of the block dropped
+    pub block: String,
+    #[serde(default = "default_drop_count")]
+    pub min: u32,
+    #[serde(default = "default_drop_count")]
+    pub max: u32,
+    #[serde(default = "default_drop_probability")]
+    pub probability: f32,
+}
+
+fn default_drop_count() -> u32 {
+    1
+}
+
+fn default_drop_probability() -> f32 {
+    1.0
+}
+
+/// Levels taken off each light channel as light passes into a transparent block, on top of the
+/// usual level per block
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LightAttenuation {
+    #[serde(default)]
+    pub sunlight: u32,
+    #[serde(default)]
+    pub red: u32,
+    #[serde(default)]
+    pub green: u32,
+    #[serde(default)]
+    pub blue: u32,
+}
+
+impl LightAttenuation {
+    /// Whether light passes through untouched
+    pub fn is_clear(&self) -> bool {
+        *self == LightAttenuation::default()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Block {
@@ -31,18 +85,39 @@ pub struct Block {
     pub blue_light_level: u32,
     pub is_plantable: bool,
     pub transparent_standalone: bool,
+    /// How transparent blocks filter the light passing through, such as tinted glass
+    #[serde(default)]
+    pub light_attenuation: LightAttenuation,
 
     #[serde(default)]
     pub textures: HashMap<String, String>,
+
+    /// Texture sides (such as `top` or `all`) that are tinted by biome colors
+    #[serde(default)]
+    pub tinted_faces: Vec<String>,
+
+    /// Seconds it takes to break the block by hand
+    #[serde(default)]
+    pub hardness: f32,
+    /// Tool that breaks the block faster
+    #[serde(default)]
+    pub tool: Option<Tool>,
+    /// What breaking the block yields, the block itself if not given
+    #[serde(default)]
+    pub drops: Option<Vec<BlockDrop>>,
+    /// Sounds the block makes when stepped on, placed or broken
+    #[serde(default)]
+    pub sound_group: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct MeshType {
     pub positions: Vec<f32>,
     pub indices: Vec<i32>,
     pub uvs: Vec<f32>,
     pub aos: Vec<i32>,
     pub lights: Vec<i32>,
+    pub tints: Vec<i32>,
 }
 
 pub type GetVoxel<'a> = &'a dyn Fn(i32, i32, i32) -> bool;
@@ -0,0 +1,52 @@
+use specs::{Component, VecStorage};
+
+use server_common::{types::Block, vec::Vec3};
+
+/// A light source moving along with whatever holds it, like a player carrying a torch or a
+/// glowing mob. Entities with a `Phys` body carry their light along with them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicLight {
+    /// World position of the light
+    pub position: Vec3<f32>,
+    /// Strength of the red, green and blue channels, from 0 to 1
+    pub color: [f32; 3],
+    pub level: u32,
+}
+
+impl DynamicLight {
+    /// Light of a block carried around, if the block gives off any
+    pub fn of_block(block: &Block, position: Vec3<f32>) -> Option<Self> {
+        let levels = [
+            block.red_light_level,
+            block.green_light_level,
+            block.blue_light_level,
+        ];
+        let level = levels.iter().copied().max().unwrap_or(0);
+
+        if !block.is_light || level == 0 {
+            return None;
+        }
+
+        Some(Self {
+            position,
+            color: levels.map(|channel| channel as f32 / level as f32),
+            level,
+        })
+    }
+
+    /// Light levels of the red, green and blue channels
+    pub fn levels(&self, max_light_level: u32) -> [u32; 3] {
+        let mut levels = [0; 3];
+
+        for (level, strength) in levels.iter_mut().zip(self.color.iter()) {
+            *level = (self.level as f32 * strength.clamp(0.0, 1.0)).round() as u32;
+            *level = (*level).min(max_light_level);
+        }
+
+        levels
+    }
+}
+
+impl Component for DynamicLight {
+    type Storage = VecStorage<Self>;
+}
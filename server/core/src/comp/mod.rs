@@ -1 +1,2 @@
+pub mod light;
 pub mod phys;
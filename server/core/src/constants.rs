@@ -434,3 +434,9 @@ pub const CHUNK_HORIZONTAL_NEIGHBORS: [[i32; 2]; 4] = [[-1, 0], [1, 0], [0, -1],
 pub const DATA_PADDING: usize = 1;
 
 pub const WORLD_DATA_FILE: &str = "world.json";
+
+/// Folder in a world's storage where players are saved, one file per account
+pub const PLAYERS_FOLDER: &str = "players";
+
+/// Where player accounts are stored, relative to where the server runs
+pub const ACCOUNTS_FILE: &str = "data/accounts.json";
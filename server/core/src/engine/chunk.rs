@@ -35,6 +35,23 @@ pub struct Meshes {
     pub transparent: Option<MeshType>,
 }
 
+impl Meshes {
+    /// Meshes of a sub-chunk that was culled, holding no geometry at all.
+    pub fn culled(sub_chunk: u32) -> Self {
+        Self {
+            sub_chunk: sub_chunk as i32,
+            opaque: None,
+            transparent: None,
+        }
+    }
+
+    /// Whether any face was generated for this sub-chunk.
+    pub fn has_geometry(&self) -> bool {
+        let has = |mesh: &Option<MeshType>| matches!(mesh, Some(m) if !m.indices.is_empty());
+        has(&self.opaque) || has(&self.transparent)
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ChunkFileData {
@@ -71,6 +88,10 @@ pub struct Chunk {
     pub size: usize,
     pub dimension: usize,
     pub max_height: usize,
+    pub sub_chunks: usize,
+
+    /// Count of non-air voxels within the inner area of each sub-chunk
+    occupancy: Vec<u32>,
 
     pub meshes: Vec<Meshes>,
 
@@ -85,11 +106,13 @@ impl Chunk {
             chunk_size: size,
             dimension,
             max_height,
+            sub_chunks,
             save,
             ..
         } = config;
 
         let max_height = max_height as usize;
+        let sub_chunks = sub_chunks as usize;
 
         let name = convert::get_chunk_name(cx, cz);
 
@@ -154,6 +177,9 @@ impl Chunk {
             size,
             max_height,
             dimension,
+            sub_chunks,
+
+            occupancy: vec![0; sub_chunks],
 
             meshes: Vec::new(),
 
@@ -199,6 +225,8 @@ impl Chunk {
             self.lights.data = decode_base64(lights);
             self.voxels.data = decode_base64(voxels);
             self.height_map.data = decode_base64(height_map);
+
+            self.calc_occupancy();
         }
     }
 
@@ -243,7 +271,30 @@ impl Chunk {
         assert!(self.contains(vx, vy, vz,));
 
         let Vec3(lx, ly, lz) = self.to_local(vx, vy, vz);
-        self.voxels[&[lx as usize, ly as usize, lz as usize]] = id;
+        let voxel = &mut self.voxels[&[lx as usize, ly as usize, lz as usize]];
+        let old_id = *voxel;
+        *voxel = id;
+
+        if (old_id == 0) != (id == 0) && self.is_inner_local(lx, lz) {
+            let level = ly as usize / (self.max_height / self.sub_chunks);
+
+            if id == 0 {
+                self.occupancy[level] -= 1;
+            } else {
+                self.occupancy[level] += 1;
+            }
+        }
+    }
+
+    /// Every light channel of a voxel packed together, sunlight on top
+    #[inline]
+    pub fn get_light(&self, vx: i32, vy: i32, vz: i32) -> u32 {
+        if !self.contains(vx, vy, vz) {
+            return 0;
+        }
+
+        let Vec3(lx, ly, lz) = self.to_local(vx, vy, vz);
+        self.lights[&[lx as usize, ly as usize, lz as usize]]
     }
 
     #[inline]
@@ -364,6 +415,31 @@ impl Chunk {
     #[inline]
     pub fn set_voxels(&mut self, data: Ndarray<u32>) {
         self.voxels = data;
+        self.calc_occupancy();
+    }
+
+    /// Check if a sub-chunk has no voxels other than air within this chunk
+    #[inline]
+    pub fn is_sub_chunk_empty(&self, sub_chunk: u32) -> bool {
+        self.occupancy[sub_chunk as usize] == 0
+    }
+
+    /// Recount the non-air voxels of every sub-chunk from the voxel data
+    pub fn calc_occupancy(&mut self) {
+        let unit = self.max_height / self.sub_chunks;
+        let mut occupancy = vec![0; self.sub_chunks];
+
+        for lx in DATA_PADDING..self.size + DATA_PADDING {
+            for lz in DATA_PADDING..self.size + DATA_PADDING {
+                for ly in 0..self.max_height {
+                    if self.voxels[&[lx, ly, lz]] != 0 {
+                        occupancy[ly / unit] += 1;
+                    }
+                }
+            }
+        }
+
+        self.occupancy = occupancy;
     }
 
     #[inline]
@@ -412,10 +488,28 @@ impl Chunk {
             z: self.coords.1,
             meshes: if needs_meshes {
                 Some(match mesh {
-                    MeshLevel::All => self.meshes.to_owned(),
+                    // only sub-chunks with actual geometry are worth sending on load
+                    MeshLevel::All => self
+                        .meshes
+                        .iter()
+                        .filter(|m| m.has_geometry())
+                        .cloned()
+                        .collect(),
+                    // culled sub-chunks are still sent on updates, so stale meshes get cleared
                     MeshLevel::Levels(ls) => ls
                         .iter()
-                        .map(|&l| self.meshes[l as usize].to_owned())
+                        .map(|&l| {
+                            let meshes = &self.meshes[l as usize];
+                            if meshes.has_geometry() {
+                                meshes.to_owned()
+                            } else {
+                                Meshes {
+                                    sub_chunk: meshes.sub_chunk,
+                                    opaque: Some(MeshType::default()),
+                                    transparent: Some(MeshType::default()),
+                                }
+                            }
+                        })
                         .collect(),
                     _ => panic!("Mismatch of need"),
                 })
@@ -432,6 +526,7 @@ impl Chunk {
             } else {
                 None
             },
+            tints: None,
         }
     }
 
@@ -480,6 +575,14 @@ impl Chunk {
         Vec3(vx, vy, vz).sub(&self.min)
     }
 
+    #[inline]
+    fn is_inner_local(&self, lx: i32, lz: i32) -> bool {
+        let padding = DATA_PADDING as i32;
+        let size = self.size as i32;
+
+        lx >= padding && lx < size + padding && lz >= padding && lz < size + padding
+    }
+
     #[inline]
     fn contains(&self, vx: i32, vy: i32, vz: i32) -> bool {
         let size = self.size as i32;
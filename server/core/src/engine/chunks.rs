@@ -16,6 +16,7 @@ use super::super::{
     constants::{LEVEL_SEED, VOXEL_NEIGHBORS},
     engine::{
         chunk::{Chunk, Meshes},
+        jobs::{Interests, JobQueue, JobStats},
         registry::Registry,
         space::Space,
         world::WorldConfig,
@@ -29,6 +30,7 @@ use super::super::{
 };
 
 use server_common::{
+    ndarray::Ndarray,
     noise::Noise,
     types::Block,
     vec::{Vec2, Vec3},
@@ -37,6 +39,16 @@ use server_utils::convert::{
     get_chunk_name, map_voxel_to_chunk, map_voxel_to_chunk_local, map_world_to_voxel,
 };
 
+/// Chunks around a chunk that have to be decorated before it's ready to be sent
+pub const READY_MARGIN: i16 = 2;
+
+/// Extra chunks around a player's render radius within which their chunk jobs are kept, as far
+/// as the terrain generated for the chunks to be ready
+const JOB_RANGE_MARGIN: i16 = READY_MARGIN + 3;
+
+/// Most voxel edits kept for a chunk while its lights aren't propagated yet
+const MAX_PENDING_EDITS: usize = 4096;
+
 /// Light data of a single vertex
 struct VertexLight {
     count: u32,
@@ -44,6 +56,14 @@ struct VertexLight {
     sunlight: u32,
 }
 
+/// Lights and meshes worked out on the thread pool, merged back into the chunk they came from
+#[derive(Debug)]
+struct Remeshed {
+    coords: Vec2<i32>,
+    lights: Option<Ndarray<u32>>,
+    meshes: Vec<Meshes>,
+}
+
 #[derive(Clone, Debug)]
 pub enum MeshLevel {
     All,
@@ -58,29 +78,47 @@ pub struct Chunks {
     pub chunk_folder: PathBuf,
 
     pub chunk_cache: HashSet<Vec2<i32>>,
-    pub to_generate: Vec<Chunk>,
-    pub to_mesh: VecDeque<Vec2<i32>>,
+    /// Voxels whose lights changed while caching, sent to clients that mesh chunks themselves
+    pub light_cache: HashSet<Vec3<i32>>,
+    pub to_generate: JobQueue<Chunk>,
+    /// Whether the chunk needs meshes, or only its lights propagated for client-meshed players
+    pub to_mesh: JobQueue<bool>,
 
     pub config: Arc<WorldConfig>,
     pub registry: Arc<Registry>,
     pub builder: Arc<Builder>,
+    /// Noise the terrains are generated from, also sampled for biome tints when meshing
+    pub noise: Arc<Noise>,
 
     caching: bool,
     chunks: HashMap<String, Chunk>,
     update_queue: HashMap<Vec2<i32>, Vec<VoxelUpdate>>,
-    noise: Noise,
+    /// Voxel edits by players waiting on the lights of their chunks to be propagated
+    pending_edits: HashMap<Vec2<i32>, Vec<(Vec3<i32>, u32)>>,
 
-    is_generating: bool,
-    gen_sender: Arc<Sender<Vec<Chunk>>>,
-    gen_receiver: Arc<Receiver<Vec<Chunk>>>,
+    gen_sender: Arc<Sender<Chunk>>,
+    gen_receiver: Arc<Receiver<Chunk>>,
 
-    is_meshing: bool,
-    mesh_sender: Arc<Sender<Vec<Chunk>>>,
-    mesh_receiver: Arc<Receiver<Vec<Chunk>>>,
+    mesh_sender: Arc<Sender<Remeshed>>,
+    mesh_receiver: Arc<Receiver<Remeshed>>,
+
+    /// Sub-chunks waiting to be remeshed from voxel updates
+    remesh_queue: HashMap<Vec2<i32>, HashSet<u32>>,
+    remeshing: HashSet<Vec2<i32>>,
+    remeshed: Vec<(Vec2<i32>, HashSet<u32>)>,
+    remesh_sender: Arc<Sender<Remeshed>>,
+    remesh_receiver: Arc<Receiver<Remeshed>>,
 
     max_per_thread: usize,
 }
 
+/// Whether swapping between two transparent blocks changes how light is filtered, like tinted glass
+fn filter_changed(current_type: &Block, updated_type: &Block) -> bool {
+    current_type.is_transparent
+        && updated_type.is_transparent
+        && current_type.light_attenuation != updated_type.light_attenuation
+}
+
 /**
  * THIS CODE IS REALLY REALLY BAD
  * NEED REFACTOR ASAP
@@ -95,6 +133,10 @@ impl Chunks {
         let mesh_sender = Arc::new(mesh_sender);
         let mesh_receiver = Arc::new(mesh_receiver);
 
+        let (remesh_sender, remesh_receiver) = unbounded();
+        let remesh_sender = Arc::new(remesh_sender);
+        let remesh_receiver = Arc::new(remesh_receiver);
+
         let mut root_folder = PathBuf::from(&config.chunk_root);
         root_folder.push(world_name);
 
@@ -115,132 +157,132 @@ impl Chunks {
             root_folder,
             chunk_folder,
             chunk_cache: HashSet::new(),
+            light_cache: HashSet::new(),
 
             config: Arc::new(config),
             registry: Arc::new(registry.to_owned()),
             builder: Arc::new(Builder::new(registry, Noise::new(LEVEL_SEED))),
+            noise: Arc::new(Noise::new(LEVEL_SEED)),
 
-            to_generate: vec![],
-            to_mesh: VecDeque::new(),
+            to_generate: JobQueue::new(),
+            to_mesh: JobQueue::new(),
 
             caching: false,
             chunks: HashMap::new(),
             update_queue: HashMap::new(),
-            noise: Noise::new(LEVEL_SEED),
+            pending_edits: HashMap::new(),
 
-            is_generating: false,
             gen_sender,
             gen_receiver,
 
-            is_meshing: false,
             mesh_sender,
             mesh_receiver,
 
+            remesh_queue: HashMap::new(),
+            remeshing: HashSet::new(),
+            remeshed: Vec::new(),
+            remesh_sender,
+            remesh_receiver,
+
             max_per_thread: num_cpus::get(),
         }
     }
 
-    pub fn tick(&mut self) {
-        if !self.is_meshing && !self.to_mesh.is_empty() {
-            let to_mesh = self
-                .to_mesh
-                .drain(0..self.max_per_thread.min(self.to_mesh.len()))
-                .collect::<Vec<_>>();
-            let to_mesh: Vec<(Chunk, Space)> = to_mesh
-                .iter()
-                .map(|coords| {
-                    (
-                        self.get_chunk(coords).unwrap().clone(),
-                        Space::new(self, &coords, self.config.max_light_level as usize),
-                    )
-                })
-                .collect();
+    /// Dispatch the most urgent chunk jobs to the thread pool, and collect the finished ones.
+    pub fn tick(&mut self, interests: &Interests) {
+        self.dispatch_remeshes();
 
-            let sender = self.mesh_sender.clone();
-            let config = self.config.clone();
-            let registry = self.registry.clone();
+        self.to_mesh.prioritize(interests, JOB_RANGE_MARGIN);
+        self.to_generate.prioritize(interests, JOB_RANGE_MARGIN);
 
-            rayon::spawn(move || {
-                let meshed = to_mesh
-                    .into_iter()
-                    .map(|(mut chunk, space)| {
-                        if chunk.needs_propagation {
-                            let lights = Lights::calc_light(&space, &registry, &config);
-                            chunk.needs_propagation = false;
-                            chunk.needs_saving = true;
-                            chunk.set_lights(lights);
-                        }
-
-                        let sub_chunks = config.sub_chunks;
+        let free = self.max_per_thread.saturating_sub(self.to_mesh.in_flight());
 
-                        chunk.meshes = Vec::new();
+        for (coords, meshing) in self.to_mesh.pop(free) {
+            let space = Space::new(self, &coords, self.config.max_light_level as usize);
 
-                        for sub_chunk in 0..sub_chunks {
-                            let opaque =
-                                Mesher::mesh_chunk(&chunk, false, sub_chunk, &config, &registry);
-                            let transparent =
-                                Mesher::mesh_chunk(&chunk, true, sub_chunk, &config, &registry);
+            // the chunk is worked on as a copy, so anything dirtying it again in the meantime
+            // gets it meshed once more
+            let live = self.get_chunk_mut(&coords).unwrap();
+            let chunk = live.clone();
 
-                            chunk.meshes.push(Meshes {
-                                opaque,
-                                transparent,
-                                sub_chunk: sub_chunk as i32,
-                            });
+            if meshing {
+                live.is_dirty = false;
+            }
 
-                            chunk.is_dirty = false;
-                        }
+            let sender = self.mesh_sender.clone();
+            let config = self.config.clone();
+            let registry = self.registry.clone();
+            let noise = self.noise.clone();
 
-                        chunk
+            rayon::spawn(move || {
+                let mut chunk = chunk;
+
+                let lights = if chunk.needs_propagation {
+                    let lights = Lights::calc_light(&space, &registry, &config);
+                    chunk.set_lights(lights.clone());
+                    Some(lights)
+                } else {
+                    None
+                };
+
+                let meshes = if meshing {
+                    (0..config.sub_chunks)
+                        .map(|sub_chunk| {
+                            Mesher::mesh_sub_chunk(&chunk, sub_chunk, &config, &registry, &noise)
+                        })
+                        .collect()
+                } else {
+                    vec![]
+                };
+
+                sender
+                    .send(Remeshed {
+                        coords,
+                        lights,
+                        meshes,
                     })
-                    .collect();
-
-                sender.send(meshed).unwrap();
+                    .unwrap();
             });
+        }
 
-            self.is_meshing = true;
-        } else if !self.is_generating && !self.to_generate.is_empty() {
-            let chunks = self
-                .to_generate
-                .drain(0..self.max_per_thread.min(self.to_generate.len()))
-                .collect::<Vec<_>>();
+        let free = self
+            .max_per_thread
+            .saturating_sub(self.to_generate.in_flight());
 
+        for (_, mut chunk) in self.to_generate.pop(free) {
             let sender = self.gen_sender.clone();
             let config = self.config.clone();
             let registry = self.registry.clone();
 
             rayon::spawn(move || {
-                let chunks: Vec<Chunk> = chunks
-                    .into_iter()
-                    .map(|mut chunk| {
-                        Generator::generate_chunk(&mut chunk, &registry, &config);
-                        Generator::generate_chunk_height_map(&mut chunk, &registry, &config);
-                        chunk
-                    })
-                    .collect();
-                sender.send(chunks).unwrap();
+                Generator::generate_chunk(&mut chunk, &registry, &config);
+                Generator::generate_chunk_height_map(&mut chunk, &registry, &config);
+                sender.send(chunk).unwrap();
             });
-
-            self.is_generating = true;
         }
 
-        if self.is_meshing {
-            if let Ok(chunks) = self.mesh_receiver.try_recv() {
-                chunks.into_iter().for_each(|c| {
-                    self.add_chunk(c);
-                });
+        while let Ok(remeshed) = self.remesh_receiver.try_recv() {
+            self.merge_remeshed(remeshed);
+        }
 
-                self.is_meshing = false;
-            }
+        while let Ok(meshed) = self.mesh_receiver.try_recv() {
+            self.to_mesh.finish(&meshed.coords);
+            self.merge_meshed(meshed);
         }
 
-        if self.is_generating {
-            if let Ok(chunks) = self.gen_receiver.try_recv() {
-                chunks.into_iter().for_each(|c| {
-                    self.add_chunk(c);
-                });
+        while let Ok(chunk) = self.gen_receiver.try_recv() {
+            self.to_generate.finish(&chunk.coords);
+            self.add_chunk(chunk);
+        }
+    }
 
-                self.is_generating = false;
-            }
+    /// Depths of the generation and meshing queues
+    pub fn job_stats(&self) -> JobStats {
+        JobStats {
+            to_generate: self.to_generate.len(),
+            generating: self.to_generate.in_flight(),
+            to_mesh: self.to_mesh.len(),
+            meshing: self.to_mesh.in_flight(),
         }
     }
 
@@ -259,51 +301,340 @@ impl Chunks {
     }
 
     /// Return a chunk references only if chunk is fully initialized (generated and decorated)
+    /// and meshed. Otherwise, the chunk is sent to other threads to mesh.
     pub fn get(
         &mut self,
         coords: &Vec2<i32>,
-        remesh_level: &MeshLevel,
-        // if it's not urgent, then will be sent to other thread to mesh
-        urgent: bool,
+        // the player waiting on this chunk, if any
+        requester: Option<usize>,
     ) -> Option<&Chunk> {
-        let chunk = self.get_chunk(coords);
-        let neighbors = self.neighbors(coords);
+        if !self.is_ready(coords) {
+            return None;
+        }
+
+        // remeshes from voxel updates would be sent anyways
+        if self.is_remeshing(coords) || self.to_mesh.is_in_flight(coords) {
+            return None;
+        }
 
-        match chunk {
-            None => {
-                return None;
+        let chunk = self.get_chunk(coords).unwrap();
+        if chunk.is_dirty {
+            let coords = chunk.coords.to_owned();
+            if let Some(meshing) = self.to_mesh.push(coords, true, requester) {
+                *meshing = true;
             }
+            return None;
+        }
+
+        self.get_chunk(coords)
+    }
+
+    /// Return a chunk reference once it's fully initialized and its lights are propagated,
+    /// regardless of its meshes. Used for players that mesh the chunks themselves.
+    pub fn get_unmeshed(
+        &mut self,
+        coords: &Vec2<i32>,
+        // the player waiting on this chunk, if any
+        requester: Option<usize>,
+    ) -> Option<&Chunk> {
+        if !self.is_ready(coords) {
+            return None;
+        }
+
+        let chunk = self.get_chunk(coords).unwrap();
+        if chunk.needs_propagation {
+            let coords = chunk.coords.to_owned();
+            self.to_mesh.push(coords, false, requester);
+            return None;
+        }
+
+        self.get_chunk(coords)
+    }
+
+    /// Generate, decorate and light up a chunk right away on the current thread, for tools that
+    /// need chunks at once such as exporting. Returns false if the chunk is still being worked on
+    /// in the thread pool.
+    pub fn load_now(&mut self, coords: &Vec2<i32>) -> bool {
+        // terrains generated in one pass only get decorated on the next
+        for _ in 0..2 {
+            if !self.is_ready(coords) {
+                self.generate(coords, 2, true, None);
+            }
+        }
+
+        if !self.is_ready(coords) || self.to_mesh.contains(coords) || self.is_remeshing(coords) {
+            return false;
+        }
+
+        if self.get_chunk(coords).unwrap().needs_propagation {
+            self.propagate_chunk(coords);
+        }
+
+        true
+    }
+
+    /// Whether a chunk is generated, decorated and lit up. If not, it's queued up to be in the
+    /// thread pool, to be checked on again later.
+    pub fn load_later(&mut self, coords: &Vec2<i32>) -> bool {
+        if !self.is_ready(coords) {
+            self.generate(coords, 2, false, None);
+            return false;
+        }
+
+        self.get_unmeshed(coords, None).is_some()
+    }
+
+    /// Queue up the dirty sub-chunks of a chunk to be remeshed in the thread pool. Bursts of
+    /// updates to the same sub-chunks are remeshed once.
+    pub fn remesh(&mut self, coords: &Vec2<i32>) {
+        if let Some(chunk) = self.get_chunk_mut(coords) {
+            let levels = std::mem::take(&mut chunk.dirty_levels);
+
+            self.remesh_queue
+                .entry(coords.to_owned())
+                .or_default()
+                .extend(levels);
+        }
+    }
+
+    /// Keep a voxel edit to apply once the lights of its chunk are propagated. Returns false if
+    /// too many edits are waiting on the chunk already.
+    pub fn queue_edit(&mut self, voxel: Vec3<i32>, id: u32) -> bool {
+        let Vec3(vx, vy, vz) = voxel;
+        let coords = map_voxel_to_chunk(vx, vy, vz, self.config.chunk_size);
+
+        let edits = self.pending_edits.entry(coords).or_default();
+
+        if edits.len() >= MAX_PENDING_EDITS {
+            return false;
+        }
+
+        edits.push((voxel, id));
+
+        true
+    }
+
+    /// Take the edits of the chunks that have their lights propagated now, in the order they came.
+    pub fn drain_ready_edits(&mut self) -> Vec<(Vec3<i32>, u32)> {
+        let ready: Vec<Vec2<i32>> = self
+            .pending_edits
+            .keys()
+            .filter(|coords| {
+                self.get_chunk(coords)
+                    .is_some_and(|chunk| !chunk.needs_propagation)
+            })
+            .cloned()
+            .collect();
+
+        ready
+            .iter()
+            .flat_map(|coords| self.pending_edits.remove(coords).unwrap())
+            .collect()
+    }
+
+    /// Swap in a reloaded registry, and queue every meshed chunk to be remeshed with it.
+    pub fn set_registry(&mut self, registry: Registry) {
+        self.builder = Arc::new(Builder::new(registry.to_owned(), Noise::new(LEVEL_SEED)));
+        self.registry = Arc::new(registry);
+
+        let sub_chunks = self.config.sub_chunks;
+        let meshed: Vec<Vec2<i32>> = self
+            .chunks
+            .values()
+            .filter(|chunk| !chunk.meshes.is_empty())
+            .map(|chunk| chunk.coords.to_owned())
+            .collect();
+
+        for coords in meshed {
+            self.remesh_queue
+                .entry(coords)
+                .or_default()
+                .extend(0..sub_chunks);
+        }
+    }
+
+    /// Take the sub-chunks that have been remeshed since last time, ready to be sent.
+    pub fn drain_remeshed(&mut self) -> Vec<(Vec2<i32>, MeshLevel)> {
+        self.remeshed
+            .drain(..)
+            .map(|(coords, levels)| (coords, MeshLevel::Levels(levels)))
+            .collect()
+    }
+
+    /// Whether a chunk has remeshing from voxel updates pending or in progress
+    pub fn is_remeshing(&self, coords: &Vec2<i32>) -> bool {
+        self.remesh_queue.contains_key(coords) || self.remeshing.contains(coords)
+    }
+
+    /// Whether a chunk and its neighbors are generated and decorated
+    fn is_ready(&self, coords: &Vec2<i32>) -> bool {
+        let neighbors = self.neighbors(coords);
+
+        match self.get_chunk(coords) {
+            None => false,
             Some(chunk) => {
-                if chunk.needs_terrain
+                !(chunk.needs_terrain
                     || chunk.needs_decoration
                     || neighbors.iter().any(|&c| c.is_none())
-                    || neighbors.iter().any(|&c| c.unwrap().needs_decoration)
-                {
-                    return None;
-                }
-                chunk
+                    || neighbors.iter().any(|&c| c.unwrap().needs_decoration))
             }
+        }
+    }
+
+    /// Send the queued remeshes to the thread pool, as many at a time as the other jobs, leaving
+    /// the rest for the next ticks. Chunks already being remeshed or meshed as a whole wait for
+    /// their previous results, so the meshes go out in the order of the updates.
+    fn dispatch_remeshes(&mut self) {
+        let free = self.max_per_thread.saturating_sub(self.remeshing.len());
+
+        let coords_list: Vec<Vec2<i32>> = self
+            .remesh_queue
+            .keys()
+            .filter(|coords| {
+                !self.remeshing.contains(coords)
+                    && !self.to_mesh.is_in_flight(coords)
+                    && self.is_ready(coords)
+            })
+            .take(free)
+            .cloned()
+            .collect();
+
+        for coords in coords_list {
+            let mut levels = self.remesh_queue.remove(&coords).unwrap();
+            let chunk = self.get_chunk(&coords).unwrap().clone();
+
+            // never been meshed as a whole, so mesh everything
+            if chunk.meshes.len() != self.config.sub_chunks as usize {
+                levels = (0..self.config.sub_chunks).collect();
+            }
+
+            let space = if chunk.needs_propagation {
+                Some(Space::new(
+                    self,
+                    &coords,
+                    self.config.max_light_level as usize,
+                ))
+            } else {
+                None
+            };
+
+            let sender = self.remesh_sender.clone();
+            let config = self.config.clone();
+            let registry = self.registry.clone();
+            let noise = self.noise.clone();
+
+            self.remeshing.insert(coords.to_owned());
+
+            rayon::spawn(move || {
+                let mut chunk = chunk;
+
+                let lights = space.map(|space| {
+                    let lights = Lights::calc_light(&space, &registry, &config);
+                    chunk.set_lights(lights.clone());
+                    lights
+                });
+
+                let meshes = levels
+                    .into_iter()
+                    .map(|sub_chunk| {
+                        Mesher::mesh_sub_chunk(&chunk, sub_chunk, &config, &registry, &noise)
+                    })
+                    .collect();
+
+                sender
+                    .send(Remeshed {
+                        coords,
+                        lights,
+                        meshes,
+                    })
+                    .unwrap();
+            });
+        }
+    }
+
+    /// Merge the lights and meshes of a whole chunk worked out in other threads back into the
+    /// chunk, leaving whatever changed in the meantime as is.
+    fn merge_meshed(&mut self, meshed: Remeshed) {
+        let Remeshed {
+            coords,
+            lights,
+            meshes,
+        } = meshed;
+
+        let chunk = match self.get_chunk_mut(&coords) {
+            Some(chunk) => chunk,
+            None => return,
         };
 
-        if urgent {
-            self.remesh_chunk(coords, remesh_level);
-        } else {
-            let chunk = chunk.unwrap();
-            if chunk.is_dirty {
-                let coords = chunk.coords.to_owned();
-                if !self.to_mesh.contains(&coords) {
-                    self.to_mesh.push_back(coords);
-                }
-                return None;
+        if let Some(lights) = lights {
+            if chunk.needs_propagation {
+                chunk.needs_propagation = false;
+                chunk.needs_saving = true;
+                chunk.set_lights(lights);
             }
         }
 
-        self.get_chunk(coords)
+        // updates since are remeshed on top once this is done
+        if !meshes.is_empty() {
+            chunk.meshes = meshes;
+        }
+    }
+
+    /// Merge the meshes remeshed in other threads back into the chunk.
+    fn merge_remeshed(&mut self, remeshed: Remeshed) {
+        let Remeshed {
+            coords,
+            lights,
+            meshes,
+        } = remeshed;
+
+        self.remeshing.remove(&coords);
+
+        // sub-chunks updated again in the meantime would be remeshed and sent again anyways
+        let requeued = self.remesh_queue.get(&coords).cloned().unwrap_or_default();
+        let sub_chunks = self.config.sub_chunks;
+
+        let chunk = self.get_chunk_mut(&coords).unwrap();
+
+        if let Some(lights) = lights {
+            if chunk.needs_propagation {
+                chunk.needs_propagation = false;
+                chunk.needs_saving = true;
+                chunk.set_lights(lights);
+            }
+        }
+
+        if chunk.meshes.len() != sub_chunks as usize {
+            chunk.meshes = (0..sub_chunks).map(Meshes::culled).collect();
+        }
+
+        let mut levels = HashSet::new();
+
+        for meshes in meshes {
+            let level = meshes.sub_chunk as u32;
+
+            if requeued.contains(&level) {
+                continue;
+            }
+
+            chunk.meshes[level as usize] = meshes;
+            levels.insert(level);
+        }
+
+        if levels.is_empty() {
+            return;
+        }
+
+        if requeued.is_empty() {
+            chunk.is_dirty = false;
+        }
+
+        self.remeshed.push((coords, levels));
     }
 
     /// To preload chunks surrounding 0,0
     pub fn preload(&mut self, width: i16) {
-        self.generate(&Vec2(0, 0), width, true);
+        self.generate(&Vec2(0, 0), width, true, None);
     }
 
     pub fn start_caching(&mut self) {
@@ -316,6 +647,7 @@ impl Chunks {
 
     pub fn clear_cache(&mut self) {
         self.chunk_cache.clear();
+        self.light_cache.clear();
     }
 
     pub fn save(&self) {
@@ -332,78 +664,19 @@ impl Chunks {
         todo!()
     }
 
-    /// Remesh a chunk, propagating itself and its neighbors then mesh.
-    pub fn remesh_chunk(&mut self, coords: &Vec2<i32>, level: &MeshLevel) {
-        // let start = Instant::now();
-        // propagate light first
-        let chunk = self.get_chunk(coords).unwrap();
-
-        // let start = Instant::now();
-        if chunk.needs_propagation {
-            self.propagate_chunk(coords);
-        }
-
-        let sub_chunks = self.config.sub_chunks;
-
-        let config = self.config.clone();
-        let registry = self.registry.clone();
-
-        match level {
-            MeshLevel::All => {
-                let chunk = self.get_chunk_mut(coords).unwrap();
-                chunk.meshes = Vec::new();
-
-                for sub_chunk in 0..sub_chunks {
-                    let chunk = self.get_chunk(coords).unwrap();
-
-                    let opaque = Mesher::mesh_chunk(chunk, false, sub_chunk, &config, &registry);
-                    let transparent =
-                        Mesher::mesh_chunk(chunk, true, sub_chunk, &config, &registry);
-
-                    // borrow again in mutable form
-                    let chunk = self.get_chunk_mut(coords).unwrap();
-
-                    chunk.meshes.push(Meshes {
-                        opaque,
-                        transparent,
-                        sub_chunk: sub_chunk as i32,
-                    });
-
-                    chunk.is_dirty = false;
-                }
-            }
-            MeshLevel::Levels(ls) => {
-                for &sub_chunk in ls {
-                    let chunk = self.get_chunk_mut(coords).unwrap();
-
-                    let opaque = Mesher::mesh_chunk(chunk, false, sub_chunk, &config, &registry);
-                    let transparent =
-                        Mesher::mesh_chunk(chunk, true, sub_chunk, &config, &registry);
-
-                    let chunk = self.get_chunk_mut(coords).unwrap();
-
-                    chunk.meshes[sub_chunk as usize] = Meshes {
-                        opaque,
-                        transparent,
-                        sub_chunk: sub_chunk as i32,
-                    };
-
-                    chunk.is_dirty = false;
-                }
-            }
-            _ => {}
-        };
-
-        // debug!("Meshing took a total of {:?}", start.elapsed());
-    }
-
     /// Load in chunks in two steps:
     ///
     /// 1. Generate the terrain within `terrain_radius`
     /// 2. Populate the terrains within `decorate_radius` with decoration
     ///
     /// Note: `decorate_radius` should always be less than `terrain_radius`
-    pub fn generate(&mut self, coords: &Vec2<i32>, render_radius: i16, is_preload: bool) {
+    pub fn generate(
+        &mut self,
+        coords: &Vec2<i32>,
+        render_radius: i16,
+        is_preload: bool,
+        requester: Option<usize>,
+    ) {
         let Vec2(cx, cz) = coords;
 
         let mut to_generate: Vec<Chunk> = Vec::new();
@@ -423,7 +696,7 @@ impl Chunks {
                 let coords = Vec2(cx + x as i32, cz + z as i32);
                 let chunk = self.get_chunk(&coords);
 
-                if chunk.is_none() {
+                if chunk.is_none() && !self.to_generate.request(&coords, requester) {
                     let mut new_chunk =
                         Chunk::new(coords.to_owned(), &self.config, &self.chunk_folder);
 
@@ -450,7 +723,10 @@ impl Chunks {
 
         if !is_preload {
             // let the multithreading begin!
-            self.to_generate.append(&mut to_generate);
+            for chunk in to_generate {
+                self.to_generate
+                    .push(chunk.coords.to_owned(), chunk, requester);
+            }
         } else {
             to_generate.par_iter_mut().for_each(|new_chunk| {
                 Generator::generate_chunk(new_chunk, &self.registry, &self.config);
@@ -645,6 +921,17 @@ impl Chunks {
         })
     }
 
+    /// Get every light channel at a voxel coordinate packed together
+    #[inline]
+    pub fn get_light_by_voxel(&self, vx: i32, vy: i32, vz: i32) -> u32 {
+        let chunk = self.get_chunk_by_voxel(vx, vy, vz);
+        if let Some(chunk) = chunk {
+            chunk.get_light(vx, vy, vz)
+        } else {
+            0
+        }
+    }
+
     /// Get the sunlight level at a voxel coordinate
     #[inline]
     pub fn get_sunlight(&self, vx: i32, vy: i32, vz: i32) -> u32 {
@@ -674,6 +961,10 @@ impl Chunks {
         chunk.calc_dirty_levels(vy, max_height, sub_chunks);
         chunk.is_dirty = true;
 
+        if self.caching {
+            self.light_cache.insert(Vec3(vx, vy, vz));
+        }
+
         let neighbors = self.get_neighbor_chunk_coords(vx, vy, vz);
         neighbors.iter().for_each(|c| {
             let n_chunk = self.get_chunk_mut(c).unwrap();
@@ -711,6 +1002,10 @@ impl Chunks {
         chunk.calc_dirty_levels(vy, max_height, sub_chunks);
         chunk.is_dirty = true;
 
+        if self.caching {
+            self.light_cache.insert(Vec3(vx, vy, vz));
+        }
+
         let neighbors = self.get_neighbor_chunk_coords(vx, vy, vz);
         neighbors.iter().for_each(|c| {
             let n_chunk = self.get_chunk_mut(c).unwrap();
@@ -828,199 +1123,171 @@ impl Chunks {
 
     /// Update a voxel to a new type
     pub fn update(&mut self, vx: i32, vy: i32, vz: i32, id: u32) {
-        // TODO: fix this code (might have better way)
-        self.get_chunk_by_voxel_mut(vx, vy, vz)
-            .unwrap()
-            .needs_saving = true;
-        let needs_propagation = self
-            .get_chunk_by_voxel(vx, vy, vz)
-            .unwrap()
-            .needs_propagation;
+        self.update_many(&[(Vec3(vx, vy, vz), id)]);
+    }
 
+    /// Update many voxels to new types, then fix the lights around all of them with a single
+    /// removal and flood pass per light channel. The lights end up the same as updating the
+    /// voxels one at a time.
+    pub fn update_many(&mut self, updates: &[(Vec3<i32>, u32)]) {
         let max_height = self.config.max_height as i32;
         let max_light_level = self.config.max_light_level;
 
-        let height = self.get_max_height(vx, vz);
+        // types of the voxels before the first update to each
+        let mut previous: Vec<(Vec3<i32>, Block)> = vec![];
+        let mut seen = HashSet::new();
 
-        // TODO: better way? RefCell?
-        let current_type = self.get_block_by_voxel(vx, vy, vz).clone();
-        let updated_type = self.get_block_by_id(id).clone();
+        for (voxel, id) in updates {
+            let &Vec3(vx, vy, vz) = voxel;
+            let id = *id;
+
+            self.mark_saving_from_voxel(vx, vy, vz);
+
+            if seen.insert(voxel.to_owned()) {
+                let current_type = self.get_block_by_voxel(vx, vy, vz).clone();
+                previous.push((voxel.to_owned(), current_type));
+            }
 
-        let voxel = Vec3(vx, vy, vz);
+            let height = self.get_max_height(vx, vz);
 
-        // updating the new block
-        self.set_voxel_by_voxel(vx, vy, vz, id);
+            // updating the new block
+            self.set_voxel_by_voxel(vx, vy, vz, id);
 
-        // updating the height map
-        if self.registry.is_air(id) {
-            if vy == height as i32 {
-                // on max height, should set max height to lower
-                for y in (0..vy).rev() {
-                    if y == 0 || !self.registry.is_air(self.get_voxel_by_voxel(vx, y, vz)) {
-                        self.set_max_height(vx, vz, y as u32);
-                        break;
+            // updating the height map
+            if self.registry.is_air(id) {
+                if vy == height as i32 {
+                    // on max height, should set max height to lower
+                    for y in (0..vy).rev() {
+                        if y == 0 || !self.registry.is_air(self.get_voxel_by_voxel(vx, y, vz)) {
+                            self.set_max_height(vx, vz, y as u32);
+                            break;
+                        }
                     }
                 }
+            } else if height < vy as u32 {
+                self.set_max_height(vx, vz, vy as u32);
             }
-        } else if height < vy as u32 {
-            self.set_max_height(vx, vz, vy as u32);
-        }
-
-        const RED: LightColor = LightColor::Red;
-        const GREEN: LightColor = LightColor::Green;
-        const BLUE: LightColor = LightColor::Blue;
-        const NONE: LightColor = LightColor::None;
-
-        // update light levels
-        if !needs_propagation {
-            if current_type.is_light {
-                // remove leftover light
-                Lights::global_remove_light(self, vx, vy, vz, false, &RED);
-                Lights::global_remove_light(self, vx, vy, vz, false, &GREEN);
-                Lights::global_remove_light(self, vx, vy, vz, false, &BLUE);
-            } else if current_type.is_transparent && !updated_type.is_transparent {
-                // remove light if solid block is placed
-                [false, true].iter().for_each(|&is_sunlight| {
-                    if is_sunlight {
-                        if self.get_sunlight(vx, vy, vz) != 0 {
-                            Lights::global_remove_light(self, vx, vy, vz, is_sunlight, &NONE);
-                        }
-                    } else {
-                        if self.get_torch_light(vx, vy, vz, &RED) != 0 {
-                            Lights::global_remove_light(self, vx, vy, vz, is_sunlight, &RED);
-                        }
-                        if self.get_torch_light(vx, vy, vz, &GREEN) != 0 {
-                            Lights::global_remove_light(self, vx, vy, vz, is_sunlight, &GREEN);
-                        }
-                        if self.get_torch_light(vx, vy, vz, &BLUE) != 0 {
-                            Lights::global_remove_light(self, vx, vy, vz, is_sunlight, &BLUE);
-                        }
-                    };
-                });
-            }
+        }
 
-            if updated_type.is_light {
-                // placing a light
+        // chunks awaiting propagation get all their lights at once later on
+        let changes: Vec<(Vec3<i32>, Block, Block)> = previous
+            .into_iter()
+            .filter(|(Vec3(vx, vy, vz), _)| {
+                !self
+                    .get_chunk_by_voxel(*vx, *vy, *vz)
+                    .unwrap()
+                    .needs_propagation
+            })
+            .map(|(voxel, current_type)| {
+                let Vec3(vx, vy, vz) = voxel;
+                let updated_type = self.get_block_by_voxel(vx, vy, vz).clone();
+                (voxel, current_type, updated_type)
+            })
+            .collect();
 
-                if updated_type.red_light_level > 0 {
-                    self.set_torch_light(vx, vy, vz, updated_type.red_light_level, &RED);
+        if changes.is_empty() {
+            return;
+        }
 
-                    Lights::global_flood_light(
-                        self,
-                        VecDeque::from(vec![LightNode {
-                            voxel: voxel.clone(),
-                            level: updated_type.red_light_level,
-                        }]),
-                        false,
-                        &RED,
-                    );
-                }
+        let channels = [
+            (true, LightColor::None),
+            (false, LightColor::Red),
+            (false, LightColor::Green),
+            (false, LightColor::Blue),
+        ];
+
+        for (is_sunlight, color) in channels.iter() {
+            let is_sunlight = *is_sunlight;
 
-                if updated_type.green_light_level > 0 {
-                    self.set_torch_light(vx, vy, vz, updated_type.green_light_level, &GREEN);
+            // remove leftover light, light blocked by a solid block, or light to refilter
+            let removed: Vec<Vec3<i32>> = changes
+                .iter()
+                .filter(|(_, current_type, updated_type)| {
+                    (!is_sunlight && current_type.is_light)
+                        || (current_type.is_transparent
+                            && (!updated_type.is_transparent
+                                || filter_changed(current_type, updated_type)))
+                })
+                .map(|(voxel, _, _)| voxel.to_owned())
+                .collect();
+
+            Lights::global_remove_light(self, &removed, is_sunlight, color);
+
+            let mut queue = VecDeque::<LightNode>::new();
+
+            for (voxel, current_type, updated_type) in changes.iter() {
+                let &Vec3(vx, vy, vz) = voxel;
+
+                // placing a light
+                let emitted = Lights::get_emission(updated_type, is_sunlight, color);
 
-                    Lights::global_flood_light(
+                if emitted != 0 {
+                    let level = emitted.max(Lights::get_global_light(
                         self,
-                        VecDeque::from(vec![LightNode {
-                            voxel: voxel.clone(),
-                            level: updated_type.green_light_level,
-                        }]),
-                        false,
-                        &GREEN,
-                    );
+                        vx,
+                        vy,
+                        vz,
+                        is_sunlight,
+                        color,
+                    ));
+
+                    Lights::set_global_light(self, vx, vy, vz, level, is_sunlight, color);
+                    queue.push_back(LightNode {
+                        voxel: voxel.to_owned(),
+                        level,
+                    });
                 }
 
-                if updated_type.blue_light_level > 0 {
-                    self.set_torch_light(vx, vy, vz, updated_type.blue_light_level, &BLUE);
+                // solid block removed, or light let back in through a new filter
+                if !updated_type.is_transparent
+                    || (current_type.is_transparent && !filter_changed(current_type, updated_type))
+                {
+                    continue;
+                }
 
-                    Lights::global_flood_light(
-                        self,
-                        VecDeque::from(vec![LightNode {
-                            voxel,
-                            level: updated_type.blue_light_level,
-                        }]),
-                        false,
-                        &BLUE,
-                    );
+                if is_sunlight && vy == max_height - 1 {
+                    // propagate sunlight down
+                    let level =
+                        max_light_level.saturating_sub(updated_type.light_attenuation.sunlight);
+
+                    self.set_sunlight(vx, vy, vz, level);
+                    queue.push_back(LightNode {
+                        voxel: voxel.to_owned(),
+                        level,
+                    });
+
+                    continue;
                 }
-            } else if updated_type.is_transparent && !current_type.is_transparent {
-                // solid block removed
-                [false, true].iter().for_each(|&is_sunlight| {
-                    let mut queue = VecDeque::<LightNode>::new();
-                    let mut red_queue = VecDeque::<LightNode>::new();
-                    let mut green_queue = VecDeque::<LightNode>::new();
-                    let mut blue_queue = VecDeque::<LightNode>::new();
-
-                    if is_sunlight && vy == max_height - 1 {
-                        // propagate sunlight down
-                        self.set_sunlight(vx, vy, vz, max_light_level);
-                        queue.push_back(LightNode {
-                            voxel: voxel.clone(),
-                            level: max_light_level,
-                        })
-                    } else {
-                        for [ox, oy, oz] in VOXEL_NEIGHBORS.iter() {
-                            let nvy = vy + oy;
-
-                            if nvy < 0 || nvy >= max_height {
-                                return;
-                            }
-
-                            let nvx = vx + ox;
-                            let nvz = vz + oz;
-                            let n_voxel = Vec3(nvx, nvy, nvz);
-                            let &Block {
-                                is_light,
-                                is_transparent,
-                                ..
-                            } = self.get_block_by_voxel(nvx, nvy, nvz);
-
-                            // need propagation after solid block removed
-                            if is_sunlight {
-                                let level = self.get_sunlight(nvx, nvy, nvz);
-                                if level != 0 && is_transparent {
-                                    queue.push_back(LightNode {
-                                        voxel: n_voxel,
-                                        level,
-                                    })
-                                }
-                            } else {
-                                let red_level = self.get_torch_light(nvx, nvy, nvz, &RED);
-                                if red_level != 0 && (is_transparent || is_light) {
-                                    red_queue.push_back(LightNode {
-                                        voxel: n_voxel.clone(),
-                                        level: red_level,
-                                    })
-                                }
-
-                                let green_level = self.get_torch_light(nvx, nvy, nvz, &GREEN);
-                                if green_level != 0 && (is_transparent || is_light) {
-                                    green_queue.push_back(LightNode {
-                                        voxel: n_voxel.clone(),
-                                        level: green_level,
-                                    })
-                                }
-
-                                let blue_level = self.get_torch_light(nvx, nvy, nvz, &BLUE);
-                                if blue_level != 0 && (is_transparent || is_light) {
-                                    blue_queue.push_back(LightNode {
-                                        voxel: n_voxel,
-                                        level: blue_level,
-                                    })
-                                }
-                            }
-                        }
+
+                for [ox, oy, oz] in VOXEL_NEIGHBORS.iter() {
+                    let nvy = vy + oy;
+
+                    if nvy < 0 || nvy >= max_height {
+                        continue;
                     }
 
-                    if is_sunlight {
-                        Lights::global_flood_light(self, queue, is_sunlight, &NONE);
-                    } else {
-                        Lights::global_flood_light(self, red_queue, is_sunlight, &RED);
-                        Lights::global_flood_light(self, green_queue, is_sunlight, &GREEN);
-                        Lights::global_flood_light(self, blue_queue, is_sunlight, &BLUE);
+                    let nvx = vx + ox;
+                    let nvz = vz + oz;
+
+                    let &Block {
+                        is_light,
+                        is_transparent,
+                        ..
+                    } = self.get_block_by_voxel(nvx, nvy, nvz);
+
+                    let level = Lights::get_global_light(self, nvx, nvy, nvz, is_sunlight, color);
+
+                    // need propagation after solid block removed
+                    if level != 0 && (is_transparent || (!is_sunlight && is_light)) {
+                        queue.push_back(LightNode {
+                            voxel: Vec3(nvx, nvy, nvz),
+                            level,
+                        })
                     }
-                })
+                }
             }
+
+            Lights::global_flood_light(self, queue, is_sunlight, color);
         }
     }
 
@@ -1048,3 +1315,274 @@ impl Chunks {
         chunk.set_lights(lights);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::super::registry::test_block;
+    use super::*;
+
+    const SIZE: i32 = 3 * 8;
+    const MAX_HEIGHT: i32 = 16;
+
+    const STONE: u32 = 2;
+    const TORCH: u32 = 3;
+    const LAMP: u32 = 4;
+    const GLASS: u32 = 5;
+
+    /// A world of 3x3 open chunks lit by the sun
+    fn open_world() -> Chunks {
+        let registry = Registry::from_blocks(vec![
+            test_block(serde_json::json!({ "name": "Void" })),
+            test_block(
+                serde_json::json!({ "name": "Air", "isEmpty": true, "isSolid": false, "isTransparent": true }),
+            ),
+            test_block(serde_json::json!({ "name": "Stone" })),
+            test_block(serde_json::json!({
+                "name": "Torch", "isSolid": false, "isTransparent": true, "isLight": true,
+                "redLightLevel": 13, "greenLightLevel": 6
+            })),
+            test_block(
+                serde_json::json!({ "name": "Lamp", "isLight": true, "blueLightLevel": 11 }),
+            ),
+            test_block(serde_json::json!({
+                "name": "Glass", "isTransparent": true,
+                "lightAttenuation": { "sunlight": 2, "red": 4 }
+            })),
+        ]);
+
+        let config = WorldConfig {
+            chunk_size: 8,
+            dimension: 1,
+            max_height: MAX_HEIGHT as u32,
+            max_light_level: 15,
+            save: false,
+            chunk_root: "data".to_owned(),
+            render_radius: 1,
+            max_loaded_chunks: 25,
+            sub_chunks: 2,
+            generation: "flat".to_owned(),
+            allow_flight: false,
+        };
+
+        let mut chunks = Chunks::new("test", config.clone(), registry);
+
+        // the solid ring of chunks around keeps the light in
+        for cx in -1..4 {
+            for cz in -1..4 {
+                let mut chunk = Chunk::new(Vec2(cx, cz), &config, &chunks.chunk_folder);
+                chunk.needs_propagation = false;
+                chunks.add_chunk(chunk);
+            }
+        }
+
+        for vx in 0..SIZE {
+            for vz in 0..SIZE {
+                for vy in 0..MAX_HEIGHT - 1 {
+                    chunks.set_voxel_by_voxel(vx, vy, vz, 1);
+                }
+
+                chunks.set_voxel_by_voxel(vx, MAX_HEIGHT - 1, vz, STONE);
+            }
+        }
+
+        // opening up the sky lets the sunlight in
+        let sky: Vec<(Vec3<i32>, u32)> = (0..SIZE * SIZE)
+            .map(|i| (Vec3(i / SIZE, MAX_HEIGHT - 1, i % SIZE), 1))
+            .collect();
+        chunks.update_many(&sky);
+
+        chunks
+    }
+
+    fn lights(chunks: &Chunks) -> Vec<u32> {
+        let mut lights = vec![];
+
+        for vx in 0..SIZE {
+            for vz in 0..SIZE {
+                for vy in 0..MAX_HEIGHT {
+                    let sunlight = chunks.get_sunlight(vx, vy, vz);
+                    let red = chunks.get_torch_light(vx, vy, vz, &LightColor::Red);
+                    let green = chunks.get_torch_light(vx, vy, vz, &LightColor::Green);
+                    let blue = chunks.get_torch_light(vx, vy, vz, &LightColor::Blue);
+
+                    lights.push((sunlight << 12) | (red << 8) | (green << 4) | blue);
+                }
+            }
+        }
+
+        lights
+    }
+
+    #[test]
+    fn opens_the_sky() {
+        let chunks = open_world();
+
+        assert_eq!(chunks.get_sunlight(12, 0, 12), 15);
+        assert_eq!(chunks.get_torch_light(12, 0, 12, &LightColor::Red), 0);
+    }
+
+    #[test]
+    fn filters_light_through_tinted_glass() {
+        let mut chunks = open_world();
+        let red = |chunks: &Chunks| chunks.get_torch_light(14, 5, 12, &LightColor::Red);
+
+        chunks.update(12, 5, 12, TORCH);
+        assert_eq!(red(&chunks), 11);
+
+        // a wall of glass across the world, so the light can't go around it
+        let wall = |id: u32| -> Vec<(Vec3<i32>, u32)> {
+            (0..SIZE * MAX_HEIGHT)
+                .map(|i| (Vec3(13, i / SIZE, i % SIZE), id))
+                .collect()
+        };
+
+        chunks.update_many(&wall(GLASS));
+        assert_eq!(chunks.get_torch_light(13, 5, 12, &LightColor::Red), 8);
+        assert_eq!(red(&chunks), 7);
+        // the sunlight at the bottom of the wall comes in from the sides, filtered once
+        assert_eq!(chunks.get_sunlight(13, 0, 12), 12);
+
+        chunks.update_many(&wall(1));
+        assert_eq!(red(&chunks), 11);
+        assert_eq!(chunks.get_sunlight(13, 0, 12), 15);
+    }
+
+    #[test]
+    fn records_the_lights_that_changed() {
+        let mut chunks = open_world();
+        let voxels: Vec<Vec3<i32>> = (0..SIZE * MAX_HEIGHT * SIZE)
+            .map(|i| Vec3(i / (MAX_HEIGHT * SIZE), i / SIZE % MAX_HEIGHT, i % SIZE))
+            .collect();
+        let lights = |chunks: &Chunks| -> Vec<u32> {
+            voxels
+                .iter()
+                .map(|&Vec3(vx, vy, vz)| chunks.get_light_by_voxel(vx, vy, vz))
+                .collect()
+        };
+
+        let before = lights(&chunks);
+
+        chunks.start_caching();
+        chunks.update_many(&[(Vec3(12, 5, 12), TORCH), (Vec3(4, 9, 4), STONE)]);
+        chunks.stop_caching();
+
+        let after = lights(&chunks);
+        let changed: HashSet<Vec3<i32>> = voxels
+            .iter()
+            .zip(before.iter().zip(after.iter()))
+            .filter(|(_, (before, after))| before != after)
+            .map(|(voxel, _)| voxel.to_owned())
+            .collect();
+
+        assert!(!changed.is_empty());
+        assert!(changed.is_subset(&chunks.light_cache));
+
+        chunks.clear_cache();
+        assert!(chunks.light_cache.is_empty());
+    }
+
+    #[test]
+    fn keeps_edits_made_while_meshing() {
+        let mut chunks = open_world();
+        let coords = Vec2(1, 1);
+
+        for chunk in chunks.chunks.values_mut() {
+            chunk.needs_terrain = false;
+            chunk.needs_decoration = false;
+        }
+
+        // keep the thread pool busy, so the chunk is still being meshed when it's edited
+        let (release, wait) = unbounded::<()>();
+        for _ in 0..rayon::current_num_threads() {
+            let wait = wait.clone();
+            rayon::spawn(move || {
+                wait.recv().ok();
+            });
+        }
+
+        assert!(chunks.get(&coords, None).is_none());
+        chunks.tick(&Interests::new());
+        assert!(chunks.to_mesh.is_in_flight(&coords));
+
+        chunks.update(12, 5, 12, STONE);
+        drop(release);
+
+        while chunks.to_mesh.in_flight() > 0 {
+            thread::yield_now();
+            chunks.tick(&Interests::new());
+        }
+
+        let chunk = chunks.get_chunk(&coords).unwrap();
+        assert_eq!(chunk.get_voxel(12, 5, 12), STONE);
+        assert!(chunk.needs_saving && chunk.is_dirty);
+        assert_eq!(chunk.meshes.len(), 2);
+    }
+
+    #[test]
+    fn caps_remeshes_in_flight() {
+        let mut chunks = open_world();
+        chunks.max_per_thread = 2;
+
+        for chunk in chunks.chunks.values_mut() {
+            chunk.needs_terrain = false;
+            chunk.needs_decoration = false;
+            chunk.meshes = (0..2).map(Meshes::culled).collect();
+        }
+
+        // every meshed chunk gets remeshed, but the ones with all their neighbors come first
+        let registry = chunks.registry.as_ref().to_owned();
+        chunks.set_registry(registry);
+
+        let mut remeshed = HashSet::new();
+
+        while remeshed.len() < 9 || !chunks.remeshing.is_empty() {
+            chunks.tick(&Interests::new());
+            assert!(chunks.remeshing.len() <= 2);
+
+            remeshed.extend(
+                chunks
+                    .drain_remeshed()
+                    .into_iter()
+                    .map(|(coords, _)| coords),
+            );
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn batches_match_single_updates() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let types = [1, STONE, TORCH, LAMP, GLASS];
+
+        for round in 0..2 {
+            let updates: Vec<(Vec3<i32>, u32)> = (0..100)
+                .map(|_| {
+                    let voxel = Vec3(
+                        rng.gen_range(6..18),
+                        rng.gen_range(1..MAX_HEIGHT - 2),
+                        rng.gen_range(6..18),
+                    );
+
+                    (voxel, types[rng.gen_range(0..types.len())])
+                })
+                .collect();
+
+            let mut one_by_one = open_world();
+            let mut batched = open_world();
+
+            for (Vec3(vx, vy, vz), id) in updates.iter() {
+                one_by_one.update(*vx, *vy, *vz, *id);
+            }
+
+            batched.update_many(&updates);
+
+            assert!(
+                lights(&one_by_one) == lights(&batched),
+                "Lights differ in round {}",
+                round
+            );
+        }
+    }
+}
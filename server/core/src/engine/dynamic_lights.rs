@@ -0,0 +1,343 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use server_common::{types::LightAttenuation, vec::Vec3};
+
+use super::super::constants::VOXEL_NEIGHBORS;
+
+/// How light passes through a voxel, or none if it's blocked
+pub type LightFilter<'a> = &'a dyn Fn(i32, i32, i32) -> Option<LightAttenuation>;
+
+/// What a dynamic light is attached to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LightOwner {
+    Player(usize),
+    Entity(u32),
+}
+
+/// Where a dynamic light is, and the levels of its red, green and blue channels
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightSource {
+    pub voxel: Vec3<i32>,
+    pub levels: [u32; 3],
+}
+
+/// The light of a single source by voxel
+#[derive(Debug)]
+struct Footprint {
+    source: LightSource,
+    lights: HashMap<Vec3<i32>, u32>,
+    stale: bool,
+}
+
+/// A light layer over the chunk lights, lit by sources that move around. The layer is never
+/// saved, and only the voxels that a source lit before or lights now are recomputed as it moves.
+/// Levels are packed like the torch lights of chunks, without any sunlight.
+#[derive(Debug, Default)]
+pub struct DynamicLights {
+    footprints: HashMap<LightOwner, Footprint>,
+    /// The sources reaching each voxel
+    coverage: HashMap<Vec3<i32>, HashSet<LightOwner>>,
+    /// The brightest of the sources per channel
+    layer: HashMap<Vec3<i32>, u32>,
+}
+
+impl DynamicLights {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Level of the layer at a voxel
+    pub fn get(&self, voxel: &Vec3<i32>) -> u32 {
+        *self.layer.get(voxel).unwrap_or(&0)
+    }
+
+    /// Every voxel lit by the layer
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec3<i32>, &u32)> {
+        self.layer.iter()
+    }
+
+    /// Mark the sources around a changed voxel to be recomputed, as their light could now be
+    /// blocked or let through.
+    pub fn invalidate(&mut self, voxel: &Vec3<i32>) {
+        let &Vec3(vx, vy, vz) = voxel;
+
+        let around = VOXEL_NEIGHBORS
+            .iter()
+            .map(|[ox, oy, oz]| Vec3(vx + ox, vy + oy, vz + oz))
+            .chain(std::iter::once(voxel.to_owned()));
+
+        for voxel in around {
+            if let Some(owners) = self.coverage.get(&voxel) {
+                for owner in owners {
+                    self.footprints.get_mut(owner).unwrap().stale = true;
+                }
+            }
+        }
+    }
+
+    /// Move the sources to where they are now, removing the ones not given anymore. Returns the
+    /// voxels whose level changed, along with their new level.
+    pub fn update(
+        &mut self,
+        sources: HashMap<LightOwner, LightSource>,
+        filter: LightFilter,
+        max_height: i32,
+    ) -> HashMap<Vec3<i32>, u32> {
+        let mut touched = HashSet::new();
+
+        let outdated: Vec<LightOwner> = self
+            .footprints
+            .iter()
+            .filter(|(owner, footprint)| match sources.get(owner) {
+                Some(source) => footprint.stale || &footprint.source != source,
+                None => true,
+            })
+            .map(|(owner, _)| *owner)
+            .collect();
+
+        for owner in outdated {
+            let footprint = self.footprints.remove(&owner).unwrap();
+
+            for voxel in footprint.lights.into_keys() {
+                let owners = self.coverage.get_mut(&voxel).unwrap();
+                owners.remove(&owner);
+
+                if owners.is_empty() {
+                    self.coverage.remove(&voxel);
+                }
+
+                touched.insert(voxel);
+            }
+        }
+
+        for (owner, source) in sources {
+            if self.footprints.contains_key(&owner) {
+                continue;
+            }
+
+            let lights = flood(&source, filter, max_height);
+
+            for voxel in lights.keys() {
+                self.coverage
+                    .entry(voxel.to_owned())
+                    .or_default()
+                    .insert(owner);
+                touched.insert(voxel.to_owned());
+            }
+
+            self.footprints.insert(
+                owner,
+                Footprint {
+                    source,
+                    lights,
+                    stale: false,
+                },
+            );
+        }
+
+        let mut changed = HashMap::new();
+
+        for voxel in touched {
+            let level = self.combine(&voxel);
+
+            if level == self.get(&voxel) {
+                continue;
+            }
+
+            if level == 0 {
+                self.layer.remove(&voxel);
+            } else {
+                self.layer.insert(voxel.to_owned(), level);
+            }
+
+            changed.insert(voxel, level);
+        }
+
+        changed
+    }
+
+    /// The brightest of the sources reaching a voxel, per channel
+    fn combine(&self, voxel: &Vec3<i32>) -> u32 {
+        let mut combined = 0;
+
+        for owner in self.coverage.get(voxel).into_iter().flatten() {
+            let light = self.footprints[owner].lights[voxel];
+
+            for channel in 0..3 {
+                let level = get_channel(light, channel).max(get_channel(combined, channel));
+                combined = set_channel(combined, channel, level);
+            }
+        }
+
+        combined
+    }
+}
+
+#[inline]
+fn get_channel(light: u32, channel: usize) -> u32 {
+    (light >> (8 - channel * 4)) & 0xF
+}
+
+#[inline]
+fn set_channel(light: u32, channel: usize, level: u32) -> u32 {
+    let shift = 8 - channel * 4;
+    (light & !(0xF << shift)) | (level << shift)
+}
+
+/// Flood the light of a single source through the voxels that let it pass, one channel at a
+/// time like the torch lights of chunks.
+fn flood(source: &LightSource, filter: LightFilter, max_height: i32) -> HashMap<Vec3<i32>, u32> {
+    let mut lights: HashMap<Vec3<i32>, u32> = HashMap::new();
+
+    for (channel, &level) in source.levels.iter().enumerate() {
+        if level == 0 {
+            continue;
+        }
+
+        let light = lights.entry(source.voxel.to_owned()).or_insert(0);
+        *light = set_channel(*light, channel, level);
+
+        let mut queue = VecDeque::from(vec![(source.voxel.to_owned(), level)]);
+
+        while let Some((Vec3(vx, vy, vz), level)) = queue.pop_front() {
+            for [ox, oy, oz] in VOXEL_NEIGHBORS.iter() {
+                let nvy = vy + oy;
+
+                if nvy < 0 || nvy >= max_height {
+                    continue;
+                }
+
+                let nvx = vx + ox;
+                let nvz = vz + oz;
+
+                let attenuation = match filter(nvx, nvy, nvz) {
+                    Some(attenuation) => attenuation,
+                    None => continue,
+                };
+
+                let filtered = match channel {
+                    0 => attenuation.red,
+                    1 => attenuation.green,
+                    _ => attenuation.blue,
+                };
+
+                let nl = level.saturating_sub(1 + filtered);
+                let n_voxel = Vec3(nvx, nvy, nvz);
+                let light = lights.entry(n_voxel.to_owned()).or_insert(0);
+
+                if nl == 0 || get_channel(*light, channel) >= nl {
+                    continue;
+                }
+
+                *light = set_channel(*light, channel, nl);
+                queue.push_back((n_voxel, nl));
+            }
+        }
+    }
+
+    lights.retain(|_, light| *light != 0);
+
+    lights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_HEIGHT: i32 = 64;
+
+    fn open(_: i32, _: i32, _: i32) -> Option<LightAttenuation> {
+        Some(LightAttenuation::default())
+    }
+
+    fn source(vx: i32, levels: [u32; 3]) -> LightSource {
+        LightSource {
+            voxel: Vec3(vx, 10, 0),
+            levels,
+        }
+    }
+
+    #[test]
+    fn floods_each_channel() {
+        let lights = flood(&source(0, [3, 1, 0]), &open, MAX_HEIGHT);
+
+        assert_eq!(lights[&Vec3(0, 10, 0)], 0x310);
+        assert_eq!(lights[&Vec3(1, 10, 0)], 0x200);
+        assert_eq!(lights[&Vec3(1, 11, 0)], 0x100);
+        assert!(!lights.contains_key(&Vec3(3, 10, 0)));
+    }
+
+    #[test]
+    fn filters_and_blocks_light() {
+        // a wall at x = 2, and a red-absorbing pane at x = -1
+        let filter = |x: i32, _: i32, _: i32| match x {
+            2 => None,
+            -1 => Some(LightAttenuation {
+                red: 15,
+                ..Default::default()
+            }),
+            _ => Some(LightAttenuation::default()),
+        };
+
+        let lights = flood(&source(0, [5, 5, 0]), &filter, MAX_HEIGHT);
+
+        assert!(!lights.contains_key(&Vec3(2, 10, 0)));
+        assert_eq!(lights[&Vec3(-1, 10, 0)], 0x040);
+    }
+
+    #[test]
+    fn moves_and_combines_sources() {
+        let mut layer = DynamicLights::new();
+
+        let a = LightOwner::Player(0);
+        let b = LightOwner::Entity(0);
+
+        let both = vec![(a, source(0, [4, 0, 0])), (b, source(4, [0, 0, 4]))];
+        layer.update(both.into_iter().collect(), &open, MAX_HEIGHT);
+
+        assert_eq!(layer.get(&Vec3(2, 10, 0)), 0x202);
+
+        // moving `a` away clears where it used to be
+        let moved = vec![(a, source(-10, [4, 0, 0])), (b, source(4, [0, 0, 4]))];
+        let changed = layer.update(moved.into_iter().collect(), &open, MAX_HEIGHT);
+
+        assert_eq!(changed[&Vec3(0, 10, 0)], 0);
+        assert_eq!(changed[&Vec3(2, 10, 0)], 0x002);
+        assert_eq!(layer.get(&Vec3(-10, 10, 0)), 0x400);
+
+        // removing every source clears the whole layer
+        layer.update(HashMap::new(), &open, MAX_HEIGHT);
+
+        assert_eq!(layer.iter().count(), 0);
+    }
+
+    #[test]
+    fn recomputes_invalidated_sources() {
+        let mut layer = DynamicLights::new();
+        let sources: HashMap<_, _> = vec![(LightOwner::Player(0), source(0, [4, 0, 0]))]
+            .into_iter()
+            .collect();
+
+        layer.update(sources.clone(), &open, MAX_HEIGHT);
+        assert_eq!(layer.get(&Vec3(1, 10, 0)), 0x300);
+
+        let walled = |x: i32, _: i32, _: i32| {
+            if x == 1 {
+                None
+            } else {
+                Some(LightAttenuation::default())
+            }
+        };
+
+        // unchanged sources are left alone, unless something changed around them
+        assert!(layer
+            .update(sources.clone(), &walled, MAX_HEIGHT)
+            .is_empty());
+
+        layer.invalidate(&Vec3(1, 10, 0));
+        let changed = layer.update(sources, &walled, MAX_HEIGHT);
+
+        assert_eq!(changed[&Vec3(1, 10, 0)], 0);
+        assert_eq!(layer.get(&Vec3(2, 10, 0)), 0);
+    }
+}
@@ -0,0 +1,776 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use serde_json::json;
+
+use super::super::{
+    engine::{chunk::Chunk, chunks::Chunks, registry::Registry, world::WorldConfig},
+    gen::{biomes::UNTINTED, lights::Lights, mesher::Mesher},
+};
+
+use server_common::{
+    noise::Noise,
+    types::MeshType,
+    vec::{Vec2, Vec3},
+};
+use server_utils::convert::map_voxel_to_chunk;
+
+/// Largest number of chunks a single export could span. Exports from a running world copy the
+/// chunks out of it, so this also bounds the memory an export takes.
+pub const MAX_EXPORT_CHUNKS: usize = 256;
+
+/// The shading levels of ambient occlusion, same as the client's defaults
+const AO_TABLE: [f32; 4] = [100.0, 170.0, 210.0, 255.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Obj,
+    Gltf,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "obj" => Ok(ExportFormat::Obj),
+            "gltf" => Ok(ExportFormat::Gltf),
+            _ => Err(format!("Unknown export format \"{}\", use obj or gltf.", s)),
+        }
+    }
+}
+
+/// An inclusive voxel bounding box to export
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub min: Vec3<i32>,
+    pub max: Vec3<i32>,
+}
+
+impl Region {
+    /// Parse a region out of two corners, either chunk coordinates `x,z` or voxel coordinates `x,y,z`.
+    pub fn parse(min: &str, max: &str, chunk_size: usize, max_height: u32) -> Result<Self, String> {
+        let parse_corner = |corner: &str| -> Result<Vec<i32>, String> {
+            corner
+                .split(',')
+                .map(|n| {
+                    n.trim()
+                        .parse::<i32>()
+                        .map_err(|_| format!("Invalid coordinates \"{}\".", corner))
+                })
+                .collect()
+        };
+
+        let min = parse_corner(min)?;
+        let max = parse_corner(max)?;
+
+        match (min.as_slice(), max.as_slice()) {
+            (&[x1, z1], &[x2, z2]) => Ok(Region::from_chunks(
+                &Vec2(x1, z1),
+                &Vec2(x2, z2),
+                chunk_size,
+                max_height,
+            )),
+            (&[x1, y1, z1], &[x2, y2, z2]) => {
+                Ok(Region::from_voxels(&Vec3(x1, y1, z1), &Vec3(x2, y2, z2)))
+            }
+            _ => {
+                Err("Corners should both be either \"x,z\" chunks or \"x,y,z\" voxels.".to_owned())
+            }
+        }
+    }
+
+    /// The full height of every chunk between two chunk corners
+    pub fn from_chunks(a: &Vec2<i32>, b: &Vec2<i32>, chunk_size: usize, max_height: u32) -> Self {
+        let size = chunk_size as i32;
+
+        Self {
+            min: Vec3(a.0.min(b.0) * size, 0, a.1.min(b.1) * size),
+            max: Vec3(
+                (a.0.max(b.0) + 1) * size - 1,
+                max_height as i32 - 1,
+                (a.1.max(b.1) + 1) * size - 1,
+            ),
+        }
+    }
+
+    pub fn from_voxels(a: &Vec3<i32>, b: &Vec3<i32>) -> Self {
+        Self {
+            min: Vec3(a.0.min(b.0), a.1.min(b.1), a.2.min(b.2)),
+            max: Vec3(a.0.max(b.0), a.1.max(b.1), a.2.max(b.2)),
+        }
+    }
+
+    /// Coordinates of every chunk touched by the region
+    pub fn chunk_coords(&self, chunk_size: usize) -> Vec<Vec2<i32>> {
+        let Vec2(min_x, min_z) = map_voxel_to_chunk(self.min.0, 0, self.min.2, chunk_size);
+        let Vec2(max_x, max_z) = map_voxel_to_chunk(self.max.0, 0, self.max.2, chunk_size);
+
+        let mut coords = vec![];
+
+        for cx in min_x..=max_x {
+            for cz in min_z..=max_z {
+                coords.push(Vec2(cx, cz));
+            }
+        }
+
+        coords
+    }
+
+    /// Whether a point in voxel space is within the region
+    fn contains(&self, x: f32, y: f32, z: f32) -> bool {
+        x >= self.min.0 as f32
+            && x <= (self.max.0 + 1) as f32
+            && y >= self.min.1 as f32
+            && y <= (self.max.1 + 1) as f32
+            && z >= self.min.2 as f32
+            && z <= (self.max.2 + 1) as f32
+    }
+}
+
+/// Triangles sharing the same material, with lights and AO baked into vertex colors
+#[derive(Debug, Default)]
+struct Part {
+    positions: Vec<f32>,
+    uvs: Vec<f32>,
+    colors: Vec<f32>,
+    indices: Vec<u32>,
+}
+
+impl Part {
+    /// Append the faces of a mesh that fall within the region. Faces are quads of four vertices,
+    /// each indexed by six consecutive indices.
+    fn append(&mut self, mesh: &MeshType, region: &Region, dimension: f32, max_light: f32) {
+        for face in mesh.indices.chunks(6) {
+            let first = match face.iter().min() {
+                Some(&first) => first as usize,
+                None => continue,
+            };
+
+            // the center of the face decides whether it's within the region
+            let mut center = [0.0; 3];
+            for vertex in first..first + 4 {
+                for (axis, sum) in center.iter_mut().enumerate() {
+                    *sum += mesh.positions[vertex * 3 + axis] / dimension / 4.0;
+                }
+            }
+
+            if !region.contains(center[0], center[1], center[2]) {
+                continue;
+            }
+
+            let offset = (self.positions.len() / 3) as u32;
+
+            for vertex in first..first + 4 {
+                self.positions
+                    .extend_from_slice(&mesh.positions[vertex * 3..vertex * 3 + 3]);
+                self.uvs
+                    .extend_from_slice(&mesh.uvs[vertex * 2..vertex * 2 + 2]);
+                self.colors.extend_from_slice(&vertex_color(
+                    mesh.lights[vertex] as u32,
+                    mesh.aos[vertex],
+                    mesh.tints.get(vertex).copied().unwrap_or(UNTINTED),
+                    max_light,
+                ));
+            }
+
+            self.indices
+                .extend(face.iter().map(|&i| i as u32 - first as u32 + offset));
+        }
+    }
+
+    fn vertex_count(&self) -> usize {
+        self.positions.len() / 3
+    }
+}
+
+/// Bake the lights, AO and biome tint of a vertex into a color, shading the same way the client does.
+fn vertex_color(light: u32, ao: i32, tint: i32, max_light: f32) -> [f32; 3] {
+    let sun = (Lights::extract_sunlight(light) as f32 / max_light * 0.8).max(0.02);
+    let ao = 0.88 * AO_TABLE[ao.clamp(0, 3) as usize] / 255.0;
+
+    let channel = |level: u32, shift: i32| {
+        let tint = ((tint >> shift) & 0xff) as f32 / 255.0;
+        ((sun + level as f32 / max_light) * ao).min(1.0) * tint
+    };
+
+    [
+        channel(Lights::extract_red_light(light), 16),
+        channel(Lights::extract_green_light(light), 8),
+        channel(Lights::extract_blue_light(light), 0),
+    ]
+}
+
+/// A file produced by an export
+#[derive(Debug)]
+pub struct ExportFile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Bundle the files of an export into a single zip archive, stored without compression, so that
+/// exports of several files could be downloaded at once.
+pub fn zip(files: &[ExportFile]) -> Vec<u8> {
+    // 1980-01-01, the earliest date zip archives could hold
+    const DOS_DATE: u16 = (1 << 5) | 1;
+
+    let mut archive = vec![];
+    let mut directory = vec![];
+
+    for ExportFile { name, data } in files {
+        let offset = archive.len() as u32;
+        let crc = crc32fast::hash(data);
+
+        // the parts the local header and the central directory entry have in common
+        let mut common = vec![];
+        common.write_u16::<LittleEndian>(20).unwrap();
+        common.write_u16::<LittleEndian>(0).unwrap();
+        common.write_u16::<LittleEndian>(0).unwrap();
+        common.write_u16::<LittleEndian>(0).unwrap();
+        common.write_u16::<LittleEndian>(DOS_DATE).unwrap();
+        common.write_u32::<LittleEndian>(crc).unwrap();
+        common.write_u32::<LittleEndian>(data.len() as u32).unwrap();
+        common.write_u32::<LittleEndian>(data.len() as u32).unwrap();
+        common.write_u16::<LittleEndian>(name.len() as u16).unwrap();
+        common.write_u16::<LittleEndian>(0).unwrap();
+
+        archive.write_u32::<LittleEndian>(0x0403_4b50).unwrap();
+        archive.extend(&common);
+        archive.extend(name.as_bytes());
+        archive.extend(data);
+
+        directory.write_u32::<LittleEndian>(0x0201_4b50).unwrap();
+        directory.write_u16::<LittleEndian>(20).unwrap();
+        directory.extend(&common);
+        // no comment, on the first disk, without attributes
+        directory.extend([0; 10].iter());
+        directory.write_u32::<LittleEndian>(offset).unwrap();
+        directory.extend(name.as_bytes());
+    }
+
+    let directory_offset = archive.len() as u32;
+    archive.extend(&directory);
+
+    archive.write_u32::<LittleEndian>(0x0605_4b50).unwrap();
+    archive.write_u32::<LittleEndian>(0).unwrap();
+    archive
+        .write_u16::<LittleEndian>(files.len() as u16)
+        .unwrap();
+    archive
+        .write_u16::<LittleEndian>(files.len() as u16)
+        .unwrap();
+    archive
+        .write_u32::<LittleEndian>(directory.len() as u32)
+        .unwrap();
+    archive.write_u32::<LittleEndian>(directory_offset).unwrap();
+    archive.write_u16::<LittleEndian>(0).unwrap();
+
+    archive
+}
+
+/// Chunks of a region copied out of a running world, so that the region can be meshed and written
+/// without holding up the world.
+pub struct Snapshot {
+    chunks: Vec<Chunk>,
+    config: Arc<WorldConfig>,
+    registry: Arc<Registry>,
+    noise: Arc<Noise>,
+    region: Region,
+}
+
+impl Snapshot {
+    /// Copy the chunks of a region, once they're all generated and lit. Chunks that aren't are
+    /// queued up in the thread pool like any other, to be tried again later.
+    pub fn take(chunks: &mut Chunks, region: Region) -> Result<Self, String> {
+        let coords_list = region.chunk_coords(chunks.config.chunk_size);
+        check_size(&coords_list)?;
+
+        let pending = coords_list
+            .iter()
+            .filter(|coords| !chunks.load_later(coords))
+            .count();
+
+        if pending > 0 {
+            return Err(format!(
+                "{} chunks are still being generated, try again later.",
+                pending
+            ));
+        }
+
+        Ok(Self {
+            chunks: coords_list
+                .iter()
+                .map(|coords| chunks.raw(coords).unwrap().clone())
+                .collect(),
+            config: chunks.config.clone(),
+            registry: chunks.registry.clone(),
+            noise: chunks.noise.clone(),
+            region,
+        })
+    }
+
+    /// Mesh the copied region and write it out in the given format
+    pub fn export(&self, format: ExportFormat, name: &str) -> Result<Vec<ExportFile>, String> {
+        let chunks: Vec<&Chunk> = self.chunks.iter().collect();
+
+        Exporter::write(
+            &chunks,
+            &self.config,
+            &self.registry,
+            &self.noise,
+            &self.region,
+            format,
+            name,
+        )
+    }
+}
+
+fn check_size(coords_list: &[Vec2<i32>]) -> Result<(), String> {
+    if coords_list.len() > MAX_EXPORT_CHUNKS {
+        return Err(format!(
+            "Region spans {} chunks, at most {} could be exported at once.",
+            coords_list.len(),
+            MAX_EXPORT_CHUNKS
+        ));
+    }
+
+    Ok(())
+}
+
+/// Writes regions of a world as models for other software to use.
+pub struct Exporter;
+
+impl Exporter {
+    /// Mesh a region of the world and write it out in the given format. Chunks that aren't loaded
+    /// yet are generated and lit on the spot, so this is for worlds that aren't running.
+    pub fn export(
+        chunks: &mut Chunks,
+        region: &Region,
+        format: ExportFormat,
+        name: &str,
+    ) -> Result<Vec<ExportFile>, String> {
+        let coords_list = region.chunk_coords(chunks.config.chunk_size);
+        check_size(&coords_list)?;
+
+        let pending = coords_list
+            .iter()
+            .filter(|coords| !chunks.load_now(coords))
+            .count();
+
+        if pending > 0 {
+            return Err(format!(
+                "{} chunks are still being generated, try again later.",
+                pending
+            ));
+        }
+
+        let loaded: Vec<&Chunk> = coords_list
+            .iter()
+            .map(|coords| chunks.raw(coords).unwrap())
+            .collect();
+
+        Exporter::write(
+            &loaded,
+            &chunks.config,
+            &chunks.registry,
+            &chunks.noise,
+            region,
+            format,
+            name,
+        )
+    }
+
+    /// Mesh the sub-chunks of the chunks within the region, and write them out
+    fn write(
+        chunks: &[&Chunk],
+        config: &WorldConfig,
+        registry: &Registry,
+        noise: &Noise,
+        region: &Region,
+        format: ExportFormat,
+        name: &str,
+    ) -> Result<Vec<ExportFile>, String> {
+        let dimension = config.dimension as f32;
+        let max_light = config.max_light_level as f32;
+
+        let Region { min, max } = region;
+        let sub_chunk_unit = (config.max_height / config.sub_chunks) as i32;
+
+        let mut opaque = Part::default();
+        let mut transparent = Part::default();
+
+        for chunk in chunks {
+            for sub_chunk in 0..config.sub_chunks {
+                let start_y = sub_chunk as i32 * sub_chunk_unit;
+                if start_y > max.1 || start_y + sub_chunk_unit <= min.1 {
+                    continue;
+                }
+
+                let meshes = Mesher::mesh_sub_chunk(chunk, sub_chunk, config, registry, noise);
+
+                if let Some(mesh) = meshes.opaque {
+                    opaque.append(&mesh, region, dimension, max_light);
+                }
+
+                if let Some(mesh) = meshes.transparent {
+                    transparent.append(&mesh, region, dimension, max_light);
+                }
+            }
+        }
+
+        if opaque.indices.is_empty() && transparent.indices.is_empty() {
+            return Err("Nothing to export within the region.".to_owned());
+        }
+
+        let texture = Exporter::encode_atlas(registry)?;
+
+        Ok(match format {
+            ExportFormat::Obj => Exporter::write_obj(name, &opaque, &transparent, texture),
+            ExportFormat::Gltf => Exporter::write_gltf(name, &opaque, &transparent, texture),
+        })
+    }
+
+    fn encode_atlas(registry: &Registry) -> Result<Vec<u8>, String> {
+        registry
+            .pngs
+            .first()
+            .map(|png| png.to_vec())
+            .ok_or_else(|| "The texture atlas isn't encoded".to_owned())
+    }
+
+    /// Wavefront OBJ with vertex colors appended to the positions, along with a material file
+    /// and the atlas as the texture.
+    fn write_obj(
+        name: &str,
+        opaque: &Part,
+        transparent: &Part,
+        texture: Vec<u8>,
+    ) -> Vec<ExportFile> {
+        let texture_name = format!("{}.png", name);
+
+        let mtl = format!(
+            "newmtl opaque\nKd 1 1 1\nmap_Kd {0}\n\nnewmtl transparent\nKd 1 1 1\nmap_Kd {0}\nmap_d {0}\n",
+            texture_name
+        );
+
+        let mut obj = format!("mtllib {}.mtl\no {}\n", name, name);
+        let mut offset = 1;
+
+        for (material, part) in [("opaque", opaque), ("transparent", transparent)].iter() {
+            if part.indices.is_empty() {
+                continue;
+            }
+
+            for (position, color) in part.positions.chunks(3).zip(part.colors.chunks(3)) {
+                obj.push_str(&format!(
+                    "v {} {} {} {} {} {}\n",
+                    position[0], position[1], position[2], color[0], color[1], color[2]
+                ));
+            }
+
+            for uv in part.uvs.chunks(2) {
+                obj.push_str(&format!("vt {} {}\n", uv[0], uv[1]));
+            }
+
+            obj.push_str(&format!("usemtl {}\n", material));
+
+            for triangle in part.indices.chunks(3) {
+                let a = triangle[0] as usize + offset;
+                let b = triangle[1] as usize + offset;
+                let c = triangle[2] as usize + offset;
+                obj.push_str(&format!("f {0}/{0} {1}/{1} {2}/{2}\n", a, b, c));
+            }
+
+            offset += part.vertex_count();
+        }
+
+        vec![
+            ExportFile {
+                name: format!("{}.obj", name),
+                data: obj.into_bytes(),
+            },
+            ExportFile {
+                name: format!("{}.mtl", name),
+                data: mtl.into_bytes(),
+            },
+            ExportFile {
+                name: texture_name,
+                data: texture,
+            },
+        ]
+    }
+
+    /// A self-contained glTF 2.0 file, with the buffer and the texture embedded as data URIs.
+    fn write_gltf(
+        name: &str,
+        opaque: &Part,
+        transparent: &Part,
+        texture: Vec<u8>,
+    ) -> Vec<ExportFile> {
+        const FLOAT: u32 = 5126;
+        const UNSIGNED_INT: u32 = 5125;
+        const ARRAY_BUFFER: u32 = 34962;
+        const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+        let mut buffer: Vec<u8> = vec![];
+        let mut buffer_views = vec![];
+        let mut accessors = vec![];
+        let mut primitives = vec![];
+
+        let mut push_view = |buffer: &mut Vec<u8>, bytes: Vec<u8>, target: u32| -> usize {
+            buffer_views.push(json!({
+                "buffer": 0,
+                "byteOffset": buffer.len(),
+                "byteLength": bytes.len(),
+                "target": target,
+            }));
+            buffer.extend(bytes);
+            buffer_views.len() - 1
+        };
+
+        let floats = |values: &[f32]| -> Vec<u8> {
+            values
+                .iter()
+                .flat_map(|v| v.to_le_bytes().to_vec())
+                .collect()
+        };
+
+        for (material, part) in [opaque, transparent].iter().enumerate() {
+            if part.indices.is_empty() {
+                continue;
+            }
+
+            let count = part.vertex_count();
+
+            let mut min = [f32::MAX; 3];
+            let mut max = [f32::MIN; 3];
+            for position in part.positions.chunks(3) {
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(position[axis]);
+                    max[axis] = max[axis].max(position[axis]);
+                }
+            }
+
+            // glTF texture coordinates start from the top left
+            let uvs: Vec<f32> = part
+                .uvs
+                .chunks(2)
+                .flat_map(|uv| vec![uv[0], 1.0 - uv[1]])
+                .collect();
+
+            let indices: Vec<u8> = part
+                .indices
+                .iter()
+                .flat_map(|i| i.to_le_bytes().to_vec())
+                .collect();
+
+            let position_view = push_view(&mut buffer, floats(&part.positions), ARRAY_BUFFER);
+            let uv_view = push_view(&mut buffer, floats(&uvs), ARRAY_BUFFER);
+            let color_view = push_view(&mut buffer, floats(&part.colors), ARRAY_BUFFER);
+            let index_view = push_view(&mut buffer, indices, ELEMENT_ARRAY_BUFFER);
+
+            let first = accessors.len();
+            accessors.push(json!({
+                "bufferView": position_view,
+                "componentType": FLOAT,
+                "count": count,
+                "type": "VEC3",
+                "min": min,
+                "max": max,
+            }));
+            accessors.push(json!({
+                "bufferView": uv_view,
+                "componentType": FLOAT,
+                "count": count,
+                "type": "VEC2",
+            }));
+            accessors.push(json!({
+                "bufferView": color_view,
+                "componentType": FLOAT,
+                "count": count,
+                "type": "VEC3",
+            }));
+            accessors.push(json!({
+                "bufferView": index_view,
+                "componentType": UNSIGNED_INT,
+                "count": part.indices.len(),
+                "type": "SCALAR",
+            }));
+
+            primitives.push(json!({
+                "attributes": {
+                    "POSITION": first,
+                    "TEXCOORD_0": first + 1,
+                    "COLOR_0": first + 2,
+                },
+                "indices": first + 3,
+                "material": material,
+            }));
+        }
+
+        let material = |name: &str, alpha_mode: &str| {
+            json!({
+                "name": name,
+                "pbrMetallicRoughness": {
+                    "baseColorTexture": { "index": 0 },
+                    "metallicFactor": 0.0,
+                    "roughnessFactor": 1.0,
+                },
+                "alphaMode": alpha_mode,
+                "doubleSided": true,
+            })
+        };
+
+        let gltf = json!({
+            "asset": { "version": "2.0", "generator": "mine.js" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "name": name, "mesh": 0 }],
+            "meshes": [{ "name": name, "primitives": primitives }],
+            "materials": [material("opaque", "MASK"), material("transparent", "BLEND")],
+            "textures": [{ "source": 0, "sampler": 0 }],
+            // nearest filtering to keep the pixelated look
+            "samplers": [{ "magFilter": 9728, "minFilter": 9728 }],
+            "images": [{
+                "uri": format!("data:image/png;base64,{}", base64::encode(texture)),
+            }],
+            "buffers": [{
+                "byteLength": buffer.len(),
+                "uri": format!("data:application/octet-stream;base64,{}", base64::encode(&buffer)),
+            }],
+            "bufferViews": buffer_views,
+            "accessors": accessors,
+        });
+
+        vec![ExportFile {
+            name: format!("{}.gltf", name),
+            data: gltf.to_string().into_bytes(),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A single upward face over the voxel at the origin
+    fn quad() -> Part {
+        Part {
+            positions: vec![0.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0],
+            uvs: vec![0.0, 1.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0],
+            colors: vec![1.0; 12],
+            indices: vec![0, 1, 2, 2, 1, 3],
+        }
+    }
+
+    #[test]
+    fn parses_regions() {
+        let region = Region::parse("1,-1", "0,2", 8, 64).unwrap();
+        assert_eq!(region.min, Vec3(0, 0, -8));
+        assert_eq!(region.max, Vec3(15, 63, 23));
+        assert_eq!(region.chunk_coords(8).len(), 8);
+
+        // inverted corners make the same region
+        let region = Region::parse("5, 10, -3", "-2,4,7", 8, 64).unwrap();
+        assert_eq!(region.min, Vec3(-2, 4, -3));
+        assert_eq!(region.max, Vec3(5, 10, 7));
+        assert_eq!(
+            region.chunk_coords(8),
+            vec![Vec2(-1, -1), Vec2(-1, 0), Vec2(0, -1), Vec2(0, 0)]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_regions() {
+        assert!(Region::parse("0,a", "1,1", 8, 64).is_err());
+        assert!(Region::parse("0,0", "1,1,1", 8, 64).is_err());
+        assert!(Region::parse("0", "1", 8, 64).is_err());
+        assert!(Region::parse("", "1,1", 8, 64).is_err());
+
+        // 16x16 chunks is as large as an export goes
+        let largest = Region::parse("0,0", "15,15", 8, 64).unwrap();
+        assert!(check_size(&largest.chunk_coords(8)).is_ok());
+        let too_large = Region::parse("0,0", "16,15", 8, 64).unwrap();
+        assert!(check_size(&too_large.chunk_coords(8)).is_err());
+    }
+
+    #[test]
+    fn writes_obj() {
+        let files = Exporter::write_obj("test", &quad(), &Part::default(), vec![1, 2, 3]);
+        let names: Vec<&str> = files.iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(names, vec!["test.obj", "test.mtl", "test.png"]);
+
+        let obj = String::from_utf8(files[0].data.to_owned()).unwrap();
+        let lines: Vec<&str> = obj.lines().collect();
+        assert_eq!(lines[0], "mtllib test.mtl");
+        assert_eq!(lines.iter().filter(|l| l.starts_with("v ")).count(), 4);
+        assert_eq!(lines.iter().filter(|l| l.starts_with("vt ")).count(), 4);
+        // indices are one-based, and there's no transparent material without faces
+        assert_eq!(lines[lines.len() - 2], "f 1/1 2/2 3/3");
+        assert!(!obj.contains("usemtl transparent"));
+
+        let mtl = String::from_utf8(files[1].data.to_owned()).unwrap();
+        assert!(mtl.contains("map_Kd test.png"));
+        assert_eq!(files[2].data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn writes_gltf() {
+        let files = Exporter::write_gltf("test", &Part::default(), &quad(), vec![1, 2, 3]);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "test.gltf");
+
+        let gltf: serde_json::Value = serde_json::from_slice(&files[0].data).unwrap();
+        let primitives = gltf["meshes"][0]["primitives"].as_array().unwrap();
+        assert_eq!(primitives.len(), 1);
+        assert_eq!(primitives[0]["material"], 1);
+
+        let accessors = gltf["accessors"].as_array().unwrap();
+        assert_eq!(accessors[0]["count"], 4);
+        assert_eq!(accessors[0]["min"], json!([0.0, 1.0, 0.0]));
+        assert_eq!(accessors[3]["count"], 6);
+
+        // 4 positions, uvs and colors of floats, and 6 indices
+        let length = (4 * 3 + 4 * 2 + 4 * 3) * 4 + 6 * 4;
+        assert_eq!(gltf["buffers"][0]["byteLength"], length);
+    }
+
+    #[test]
+    fn zips_files() {
+        let files = vec![
+            ExportFile {
+                name: "a.obj".to_owned(),
+                data: b"hello".to_vec(),
+            },
+            ExportFile {
+                name: "b.png".to_owned(),
+                data: vec![],
+            },
+        ];
+
+        let archive = zip(&files);
+        let u32_at = |at: usize| {
+            u32::from_le_bytes([
+                archive[at],
+                archive[at + 1],
+                archive[at + 2],
+                archive[at + 3],
+            ])
+        };
+
+        assert_eq!(u32_at(0), 0x0403_4b50);
+        assert_eq!(u32_at(14), crc32fast::hash(b"hello"));
+        assert_eq!(&archive[30..35], b"a.obj");
+        assert_eq!(&archive[35..40], b"hello");
+
+        // the end of the central directory points back at it
+        let end = archive.len() - 22;
+        assert_eq!(u32_at(end), 0x0605_4b50);
+        assert_eq!(archive[end + 10], 2);
+        let directory = u32_at(end + 16) as usize;
+        assert_eq!(u32_at(directory), 0x0201_4b50);
+        assert_eq!(&archive[directory + 46..directory + 51], b"a.obj");
+        assert_eq!(directory + u32_at(end + 12) as usize, end);
+    }
+}
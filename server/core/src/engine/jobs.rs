@@ -0,0 +1,294 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use server_common::vec::Vec2;
+
+/// Chunk area around a player that chunk jobs are being done for, or that a player follows
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interest {
+    pub center: Vec2<i32>,
+    pub radius: i16,
+}
+
+impl Interest {
+    /// Whether a chunk is within the area
+    pub fn contains(&self, coords: &Vec2<i32>) -> bool {
+        let radius = self.radius as i32;
+        distance_squared(&self.center, coords) <= radius * radius
+    }
+
+    /// Every chunk within the area, closest to the center first
+    pub fn chunks(&self) -> Vec<Vec2<i32>> {
+        let radius = self.radius as i32;
+        let Vec2(cx, cz) = self.center;
+
+        let mut chunks: Vec<Vec2<i32>> = (-radius..=radius)
+            .flat_map(|x| (-radius..=radius).map(move |z| Vec2(cx + x, cz + z)))
+            .filter(|coords| self.contains(coords))
+            .collect();
+        chunks.sort_by_key(|coords| distance_squared(&self.center, coords));
+
+        chunks
+    }
+
+    /// Squared distance in chunks from the center
+    pub fn distance_squared(&self, coords: &Vec2<i32>) -> i32 {
+        distance_squared(&self.center, coords)
+    }
+}
+
+pub type Interests = HashMap<usize, Interest>;
+
+/// Depths of the chunk job queues of a world
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStats {
+    pub to_generate: usize,
+    pub generating: usize,
+    pub to_mesh: usize,
+    pub meshing: usize,
+}
+
+#[derive(Debug)]
+struct Job<T> {
+    payload: T,
+    requesters: HashSet<usize>,
+    priority: i32,
+}
+
+/// A queue of chunk jobs, popped in the order of distance to the closest player.
+///
+/// Jobs requested by players are dropped once none of their requesters are in range anymore,
+/// whereas jobs without any requester (preloading) are always kept.
+#[derive(Debug)]
+pub struct JobQueue<T> {
+    jobs: HashMap<Vec2<i32>, Job<T>>,
+    in_flight: HashSet<Vec2<i32>>,
+}
+
+impl<T> Default for JobQueue<T> {
+    fn default() -> Self {
+        Self {
+            jobs: HashMap::new(),
+            in_flight: HashSet::new(),
+        }
+    }
+}
+
+impl<T> JobQueue<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue up a job for a chunk. If the chunk is already queued, only the requester is recorded.
+    /// Returns the payload of the queued job, or None if the chunk is already being worked on.
+    pub fn push(
+        &mut self,
+        coords: Vec2<i32>,
+        payload: T,
+        requester: Option<usize>,
+    ) -> Option<&mut T> {
+        if self.in_flight.contains(&coords) {
+            return None;
+        }
+
+        let job = self.jobs.entry(coords).or_insert(Job {
+            payload,
+            requesters: HashSet::new(),
+            priority: i32::MAX,
+        });
+
+        if let Some(requester) = requester {
+            job.requesters.insert(requester);
+        }
+
+        Some(&mut job.payload)
+    }
+
+    /// Whether a job of the chunk is either queued or being worked on
+    pub fn contains(&self, coords: &Vec2<i32>) -> bool {
+        self.jobs.contains_key(coords) || self.in_flight.contains(coords)
+    }
+
+    /// Whether a job of the chunk is being worked on
+    pub fn is_in_flight(&self, coords: &Vec2<i32>) -> bool {
+        self.in_flight.contains(coords)
+    }
+
+    /// Add a requester to a queued job, returns false if no such job is queued
+    pub fn request(&mut self, coords: &Vec2<i32>, requester: Option<usize>) -> bool {
+        match self.jobs.get_mut(coords) {
+            Some(job) => {
+                if let Some(requester) = requester {
+                    job.requesters.insert(requester);
+                }
+                true
+            }
+            None => self.in_flight.contains(coords),
+        }
+    }
+
+    /// Re-evaluate the priority of every queued job, dropping the ones nobody needs anymore.
+    /// Returns the number of jobs dropped.
+    pub fn prioritize(&mut self, interests: &Interests, margin: i16) -> usize {
+        let before = self.jobs.len();
+
+        self.jobs.retain(|coords, job| {
+            let had_requesters = !job.requesters.is_empty();
+
+            job.requesters.retain(|id| {
+                interests
+                    .get(id)
+                    .map(|interest| {
+                        let range = (interest.radius + margin) as i32;
+                        distance_squared(&interest.center, coords) <= range * range
+                    })
+                    .unwrap_or(false)
+            });
+
+            if had_requesters && job.requesters.is_empty() {
+                return false;
+            }
+
+            job.priority = interests
+                .values()
+                .map(|interest| distance_squared(&interest.center, coords))
+                .min()
+                .unwrap_or(i32::MAX);
+
+            true
+        });
+
+        before - self.jobs.len()
+    }
+
+    /// Take at most `count` of the most urgent jobs out of the queue, marking them as in flight.
+    pub fn pop(&mut self, count: usize) -> Vec<(Vec2<i32>, T)> {
+        if count == 0 || self.jobs.is_empty() {
+            return vec![];
+        }
+
+        let mut order: Vec<(i32, Vec2<i32>)> = self
+            .jobs
+            .iter()
+            .map(|(coords, job)| (job.priority, coords.to_owned()))
+            .collect();
+        order.sort_by_key(|(priority, _)| *priority);
+
+        order
+            .into_iter()
+            .take(count)
+            .map(|(_, coords)| {
+                let job = self.jobs.remove(&coords).unwrap();
+                self.in_flight.insert(coords.to_owned());
+                (coords, job.payload)
+            })
+            .collect()
+    }
+
+    /// Mark an in-flight job as done
+    pub fn finish(&mut self, coords: &Vec2<i32>) {
+        self.in_flight.remove(coords);
+    }
+
+    /// Number of jobs waiting in queue
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Number of jobs being worked on
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+}
+
+#[inline]
+fn distance_squared(a: &Vec2<i32>, b: &Vec2<i32>) -> i32 {
+    let dx = a.0 - b.0;
+    let dz = a.1 - b.1;
+    dx * dx + dz * dz
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interest_at(x: i32, z: i32, radius: i16) -> Interest {
+        Interest {
+            center: Vec2(x, z),
+            radius,
+        }
+    }
+
+    #[test]
+    fn contains_chunks_within_radius() {
+        let interest = interest_at(2, 2, 3);
+
+        assert!(interest.contains(&Vec2(2, 5)));
+        assert!(interest.contains(&Vec2(4, 4)));
+        assert!(!interest.contains(&Vec2(5, 5)));
+        assert!(!interest.contains(&Vec2(-2, 2)));
+    }
+
+    #[test]
+    fn lists_chunks_nearest_first() {
+        let chunks = interest_at(2, 2, 1).chunks();
+
+        assert_eq!(chunks.len(), 5);
+        assert_eq!(chunks[0], Vec2(2, 2));
+        assert!(chunks
+            .iter()
+            .all(|coords| interest_at(2, 2, 1).contains(coords)));
+    }
+
+    #[test]
+    fn pops_closest_first() {
+        let mut queue = JobQueue::new();
+        queue.push(Vec2(5, 5), (), None);
+        queue.push(Vec2(1, 0), (), None);
+        queue.push(Vec2(3, 0), (), None);
+
+        let mut interests = Interests::new();
+        interests.insert(0, interest_at(0, 0, 8));
+        queue.prioritize(&interests, 0);
+
+        let popped: Vec<Vec2<i32>> = queue.pop(2).into_iter().map(|(c, _)| c).collect();
+        assert_eq!(popped, vec![Vec2(1, 0), Vec2(3, 0)]);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.in_flight(), 2);
+
+        // in-flight jobs are not queued twice
+        queue.push(Vec2(1, 0), (), Some(0));
+        assert_eq!(queue.len(), 1);
+
+        queue.finish(&Vec2(1, 0));
+        assert_eq!(queue.in_flight(), 1);
+    }
+
+    #[test]
+    fn drops_jobs_out_of_range() {
+        let mut queue = JobQueue::new();
+        queue.push(Vec2(0, 0), (), Some(1));
+        queue.push(Vec2(20, 0), (), Some(1));
+        queue.push(Vec2(40, 0), (), None);
+
+        let mut interests = Interests::new();
+        interests.insert(1, interest_at(20, 0, 4));
+
+        // (0, 0) is out of player 1's range, the preload job stays
+        assert_eq!(queue.prioritize(&interests, 1), 1);
+        assert!(!queue.contains(&Vec2(0, 0)));
+        assert!(queue.contains(&Vec2(20, 0)));
+        assert!(queue.contains(&Vec2(40, 0)));
+
+        // requester left
+        interests.clear();
+        assert_eq!(queue.prioritize(&interests, 1), 1);
+        assert_eq!(queue.len(), 1);
+    }
+}
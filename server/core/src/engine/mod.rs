@@ -1,6 +1,10 @@
 pub mod chunk;
 pub mod chunks;
 pub mod clock;
+pub mod dynamic_lights;
+pub mod export;
+pub mod jobs;
+pub mod movement;
 pub mod players;
 pub mod registry;
 pub mod space;
@@ -0,0 +1,272 @@
+use std::time::Instant;
+
+use server_common::{aabb::Aabb, vec::Vec3};
+
+use server_libs::{
+    physics::{Physics, TestFunction},
+    rigidbody::RigidBody,
+};
+
+/// Size of a player's body in blocks
+pub const PLAYER_WIDTH: f32 = 0.6;
+pub const PLAYER_HEIGHT: f32 = 1.8;
+
+/// Height of a player's eyes above their feet in blocks, which is where clients report to be
+pub const EYE_HEIGHT: f32 = 1.6;
+
+/// How far in blocks a reported position can be from where the server got the player to, before
+/// the player is put back
+pub const POSITION_TOLERANCE: f32 = 1.0;
+
+/// Fastest a player can move horizontally in blocks per second, walking or flying
+const MAX_WALK_SPEED: f32 = 12.0;
+const MAX_FLY_SPEED: f32 = 32.0;
+
+/// Upwards speed in blocks per second of a jump off the ground
+const JUMP_SPEED: f32 = 10.0;
+
+/// Range of time in seconds simulated between two reports, so that a client going quiet for a
+/// while can't move arbitrarily far at once
+const MIN_STEP: f32 = 0.001;
+const MAX_STEP: f32 = 1.0;
+
+/// The body of a player as the server sees it. Clients move themselves and report where they are,
+/// and the body follows along only as far as the player could have gotten through the voxels.
+///
+/// The body lives in voxel coordinates, whereas positions in and out are in world units.
+#[derive(Debug, Clone)]
+pub struct Movement {
+    pub body: RigidBody,
+    /// When the body was last moved
+    pub moved_at: Instant,
+    /// Size of a block in world units
+    scale: f32,
+}
+
+impl Movement {
+    pub fn new(eye: &Vec3<f32>, dimension: usize) -> Self {
+        let scale = dimension as f32;
+
+        // gravity is accounted for by hand, as the client is the one moving
+        let mut body = RigidBody::new(
+            Aabb::new(
+                &Vec3::default(),
+                &Vec3(PLAYER_WIDTH, PLAYER_HEIGHT, PLAYER_WIDTH),
+            ),
+            1.0,
+            0.0,
+            0.0,
+            0.0,
+            true,
+        );
+        body.air_drag = 0.0;
+
+        let mut movement = Self {
+            body,
+            moved_at: Instant::now(),
+            scale,
+        };
+        movement.teleport(eye);
+
+        movement
+    }
+
+    /// Where the eyes of the body are
+    pub fn eye(&self) -> Vec3<f32> {
+        let Vec3(x, y, z) = self.body.get_position();
+        let half = PLAYER_WIDTH / 2.0;
+
+        Vec3(x + half, y + EYE_HEIGHT, z + half).scale(self.scale)
+    }
+
+    /// Put the body somewhere without checking if it could get there
+    pub fn teleport(&mut self, eye: &Vec3<f32>) {
+        let base = self.base_of(eye);
+
+        self.body.set_position(&base);
+        self.body.velocity.set(0.0, 0.0, 0.0);
+        self.moved_at = Instant::now();
+    }
+
+    /// Move the body towards where the client reported to be `dt` seconds after the last report.
+    /// Returns where the eyes of the body got to instead if that's too far off, or if the report
+    /// isn't a position at all.
+    pub fn advance(
+        &mut self,
+        physics: &Physics,
+        eye: &Vec3<f32>,
+        dt: f32,
+        can_fly: bool,
+        test_solid: TestFunction,
+    ) -> Result<(), Vec3<f32>> {
+        let Vec3(x, y, z) = *eye;
+
+        if !(x.is_finite() && y.is_finite() && z.is_finite()) {
+            return Err(self.eye());
+        }
+
+        let dt = dt.clamp(MIN_STEP, MAX_STEP);
+        let target = self.base_of(eye);
+
+        let mut velocity = target.sub(&self.body.get_position()).scale(1.0 / dt);
+
+        let max_speed = if can_fly {
+            MAX_FLY_SPEED
+        } else {
+            MAX_WALK_SPEED
+        };
+        let horizontal = (velocity.0 * velocity.0 + velocity.2 * velocity.2).sqrt();
+
+        if horizontal > max_speed {
+            velocity.0 *= max_speed / horizontal;
+            velocity.2 *= max_speed / horizontal;
+        }
+
+        if can_fly {
+            velocity.1 = velocity.1.clamp(-max_speed, max_speed);
+        } else {
+            // rising only as fast as a jump off the ground, or what's left of the last one
+            let gravity = -physics.options().gravity.1;
+
+            let ceiling = if self.is_grounded(test_solid) {
+                JUMP_SPEED
+            } else {
+                self.body.velocity.1 - gravity * dt
+            };
+
+            velocity.1 = velocity.1.min(ceiling);
+        }
+
+        self.body.velocity = velocity;
+        self.body.mark_active();
+
+        physics.iterate_body(&mut self.body, dt, test_solid, &|_, _, _| false);
+
+        self.moved_at = Instant::now();
+
+        let reached = self.body.get_position();
+
+        let off_by = reached.sub(&target).len();
+
+        if off_by.is_nan() || off_by > POSITION_TOLERANCE {
+            return Err(self.eye());
+        }
+
+        Ok(())
+    }
+
+    /// Whether the body stands on anything solid
+    fn is_grounded(&self, test_solid: TestFunction) -> bool {
+        let aabb = &self.body.aabb;
+        let below = (aabb.y0() - 0.05).floor() as i32;
+
+        let x0 = aabb.x0().floor() as i32;
+        let x1 = (aabb.x1() - f32::EPSILON).floor() as i32;
+        let z0 = aabb.z0().floor() as i32;
+        let z1 = (aabb.z1() - f32::EPSILON).floor() as i32;
+
+        (x0..=x1).any(|x| (z0..=z1).any(|z| test_solid(x, below, z)))
+    }
+
+    /// Corner of the body in voxel coordinates, with its eyes at a position in world units
+    fn base_of(&self, eye: &Vec3<f32>) -> Vec3<f32> {
+        let Vec3(x, y, z) = eye.scale(1.0 / self.scale);
+        let half = PLAYER_WIDTH / 2.0;
+
+        Vec3(x - half, y - EYE_HEIGHT, z - half)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use server_libs::physics::PhysicsOptions;
+
+    use super::*;
+
+    const STEP: f32 = 0.05;
+
+    fn physics() -> Physics {
+        Physics::new(PhysicsOptions {
+            gravity: Vec3(0.0, -24.0, 0.0),
+            ..Default::default()
+        })
+    }
+
+    /// A floor at y = 0, with a wall at x = 3
+    fn world(x: i32, y: i32, _: i32) -> bool {
+        y <= 0 || x == 3
+    }
+
+    fn standing_at(x: f32) -> Movement {
+        Movement::new(&Vec3(x, 1.0 + EYE_HEIGHT, 0.5), 1)
+    }
+
+    #[test]
+    fn follows_possible_movement() {
+        let physics = physics();
+        let mut movement = standing_at(0.5);
+
+        for i in 1..=5 {
+            let eye = Vec3(0.5 + i as f32 * 0.4, 1.0 + EYE_HEIGHT, 0.5);
+            assert!(movement
+                .advance(&physics, &eye, STEP, false, &world)
+                .is_ok());
+        }
+
+        assert!((movement.eye().0 - 2.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn corrects_going_through_walls() {
+        let physics = physics();
+        let mut movement = standing_at(2.0);
+
+        let through = Vec3(5.5, 1.0 + EYE_HEIGHT, 0.5);
+        let corrected = movement
+            .advance(&physics, &through, STEP, false, &world)
+            .unwrap_err();
+
+        assert!(corrected.0 < 3.0);
+    }
+
+    #[test]
+    fn flies_only_if_allowed() {
+        let physics = physics();
+        let mut walking = standing_at(0.5);
+        let mut flying = standing_at(0.5);
+
+        let mut walking_corrected = false;
+
+        for i in 1..=20 {
+            let eye = Vec3(0.5, 1.0 + EYE_HEIGHT + i as f32 * 0.2, 0.5);
+
+            walking_corrected |= walking
+                .advance(&physics, &eye, STEP, false, &world)
+                .is_err();
+            assert!(flying.advance(&physics, &eye, STEP, true, &world).is_ok());
+        }
+
+        assert!(walking_corrected);
+    }
+
+    #[test]
+    fn corrects_positions_that_are_not_numbers() {
+        let physics = physics();
+        let mut movement = standing_at(0.5);
+        let before = movement.eye();
+
+        for eye in [
+            Vec3(f32::NAN, 1.0 + EYE_HEIGHT, 0.5),
+            Vec3(0.5, f32::INFINITY, 0.5),
+            Vec3(0.5, 1.0 + EYE_HEIGHT, f32::NEG_INFINITY),
+        ] {
+            let corrected = movement
+                .advance(&physics, &eye, STEP, true, &world)
+                .unwrap_err();
+
+            assert_eq!(corrected, before);
+        }
+
+        assert_eq!(movement.body.get_position().0, 0.5 - PLAYER_WIDTH / 2.0);
+    }
+}
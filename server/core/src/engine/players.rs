@@ -1,37 +1,231 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use actix::Recipient;
 
-use super::super::network::{message, models::messages};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::super::{
+    comp::light::DynamicLight,
+    constants::PLAYERS_FOLDER,
+    engine::{
+        jobs::Interest,
+        movement::{Movement, EYE_HEIGHT},
+    },
+    network::{message, models::messages},
+};
 
 use server_common::{
     quaternion::Quaternion,
     vec::{Vec2, Vec3},
 };
 
+/// Most areas a player can subscribe to at once
+pub const MAX_SUBSCRIPTIONS: usize = 8;
+
+/// Largest radius in chunks of an area a player can subscribe to
+pub const MAX_SUBSCRIPTION_RADIUS: u32 = 8;
+
+/// Chunks past the render radius that players keep, so walking back and forth along a chunk
+/// border doesn't unload and resend the same chunks
+pub const UNLOAD_MARGIN: i16 = 2;
+
+/// What's remembered of a player between sessions, saved in the world's storage by account
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerData {
+    /// Where the player's eyes were, in world units
+    pub position: [f32; 3],
+    pub rotation: [f32; 4],
+    /// Anything else features need to remember about the player, such as an inventory
+    #[serde(default)]
+    pub data: Map<String, Value>,
+}
+
+impl PlayerData {
+    /// A player standing on top of a voxel, like clients put them at the spawn
+    pub fn standing_on(voxel: [i32; 3], dimension: usize) -> Self {
+        let [vx, vy, vz] = voxel;
+        let Vec3(px, py, pz) = Vec3(
+            vx as f32 + 0.5,
+            vy as f32 + 1.0 + EYE_HEIGHT,
+            vz as f32 + 0.5,
+        )
+        .scale(dimension as f32);
+
+        Self {
+            position: [px, py, pz],
+            ..Default::default()
+        }
+    }
+
+    /// Voxel the player is standing on, around which their chunks are loaded first. Feet are
+    /// rounded to the nearest voxel so float errors don't sink players into the ground.
+    pub fn spawn(&self, dimension: usize) -> [i32; 3] {
+        let [px, py, pz] = self.position;
+        let scale = dimension as f32;
+
+        [
+            (px / scale).floor() as i32,
+            (py / scale - EYE_HEIGHT).round() as i32 - 1,
+            (pz / scale).floor() as i32,
+        ]
+    }
+
+    /// What was saved of a player in a world's storage, if anything
+    pub fn load(root: &Path, id: usize) -> Option<Self> {
+        let file = File::open(Self::path(root, id)).ok()?;
+
+        serde_json::from_reader(file).ok()
+    }
+
+    pub fn save(&self, root: &Path, id: usize) {
+        let path = Self::path(root, id);
+
+        fs::create_dir_all(path.parent().unwrap()).expect("Could not create players folder.");
+
+        let j = serde_json::to_string(self).unwrap();
+        fs::write(path, j).expect("Unable to save player data");
+    }
+
+    fn path(root: &Path, id: usize) -> PathBuf {
+        let mut path = root.to_path_buf();
+        path.push(PLAYERS_FOLDER);
+        path.push(format!("{}.json", id));
+        path
+    }
+}
+
 #[derive(Debug)]
 pub struct Player {
-    pub name: Option<String>,
+    /// Username of the player's account
+    pub name: String,
+    /// Whether the others were told the player joined, once the player said where they are
+    pub announced: bool,
     pub addr: Recipient<message::Message>,
     pub position: Vec3<f32>,
     pub rotation: Quaternion,
     pub current_chunk: Option<Vec2<i32>>,
-    pub requested_chunks: VecDeque<Vec2<i32>>,
+    /// Chunks sent to the player that they still have
+    pub loaded_chunks: HashSet<Vec2<i32>>,
+    /// Bytes of chunks the player can still be sent, negative if the last chunk went over
+    pub stream_budget: i64,
     pub render_radius: i16,
+    /// Whether the client meshes chunks itself, receiving only voxels and lights
+    pub client_meshing: bool,
+    /// Light carried by the player, such as a held torch
+    pub light: Option<DynamicLight>,
+    /// Chunk the player was in when the dynamic lights around were last sent in full
+    pub lights_synced_at: Option<Vec2<i32>>,
+    /// Areas the player follows on top of the one around them
+    pub subscriptions: Vec<Interest>,
+    /// Other players this player was last told about
+    pub visible_peers: HashSet<usize>,
+    /// Body the reported positions of the player are checked against
+    pub movement: Movement,
+    /// Whether the player may fly, like in creative. Flying players still can't go through blocks.
+    pub can_fly: bool,
+    /// Saved along with the player, see `PlayerData::data`
+    pub data: Map<String, Value>,
+    /// Round trip time of the player's connection, once measured
+    pub latency: Option<Duration>,
+}
+
+impl Player {
+    /// What to save of the player
+    pub fn to_data(&self) -> PlayerData {
+        let Vec3(px, py, pz) = self.position;
+        let Quaternion(qx, qy, qz, qw) = self.rotation;
+
+        PlayerData {
+            position: [px, py, pz],
+            rotation: [qx, qy, qz, qw],
+            data: self.data.to_owned(),
+        }
+    }
+
+    /// Whether the player should hear about what happens in a chunk, being within their render
+    /// radius or an area they subscribed to.
+    pub fn is_interested(&self, coords: &Vec2<i32>) -> bool {
+        self.is_within(coords, 0)
+    }
+
+    /// Area within the render radius around the player, if they're anywhere yet
+    pub fn interest(&self) -> Option<Interest> {
+        self.current_chunk.as_ref().map(|center| Interest {
+            center: center.to_owned(),
+            radius: self.render_radius,
+        })
+    }
+
+    /// Chunks the player should have but doesn't, closest to the player first
+    pub fn missing_chunks(&self) -> Vec<Vec2<i32>> {
+        let interest = match self.interest() {
+            Some(interest) => interest,
+            None => return vec![],
+        };
+
+        let mut missing: Vec<Vec2<i32>> = interest
+            .chunks()
+            .into_iter()
+            .chain(self.subscriptions.iter().flat_map(|area| area.chunks()))
+            .filter(|coords| !self.loaded_chunks.contains(coords))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        missing.sort_by_key(|coords| interest.distance_squared(coords));
+
+        missing
+    }
+
+    /// Take the chunks out of range out of the loaded ones, returning them
+    pub fn unload_far_chunks(&mut self) -> Vec<Vec2<i32>> {
+        let far: Vec<Vec2<i32>> = self
+            .loaded_chunks
+            .iter()
+            .filter(|coords| !self.is_within(coords, UNLOAD_MARGIN))
+            .cloned()
+            .collect();
+
+        for coords in far.iter() {
+            self.loaded_chunks.remove(coords);
+        }
+
+        far
+    }
+
+    fn is_within(&self, coords: &Vec2<i32>, margin: i16) -> bool {
+        let around = self.interest().is_some_and(|mut interest| {
+            interest.radius += margin;
+            interest.contains(coords)
+        });
+
+        around || self.subscriptions.iter().any(|area| area.contains(coords))
+    }
 }
 
 pub type Players = HashMap<usize, Player>;
 
 pub trait BroadcastExt {
     fn broadcast(&mut self, msg: &messages::Message, exclude: Vec<usize>);
+    fn broadcast_if<F: Fn(usize, &Player) -> bool>(&mut self, msg: &messages::Message, filter: F);
+    fn send(&mut self, id: usize, msg: &messages::Message);
 }
 
 impl BroadcastExt for Players {
     fn broadcast(&mut self, msg: &messages::Message, exclude: Vec<usize>) {
+        self.broadcast_if(msg, |id, _| !exclude.contains(&id));
+    }
+
+    /// Send a message to the players that pass the filter
+    fn broadcast_if<F: Fn(usize, &Player) -> bool>(&mut self, msg: &messages::Message, filter: F) {
         let mut resting_players = vec![];
 
         for (id, player) in self.iter() {
-            if exclude.contains(id) {
+            if !filter(*id, player) {
                 continue;
             }
 
@@ -48,4 +242,54 @@ impl BroadcastExt for Players {
             self.remove(id);
         });
     }
+
+    fn send(&mut self, id: usize, msg: &messages::Message) {
+        let failed = match self.get(&id) {
+            Some(player) => player
+                .addr
+                .do_send(message::Message(msg.to_owned()))
+                .is_err(),
+            None => false,
+        };
+
+        if failed {
+            self.remove(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_and_restores_players() {
+        let root = std::env::temp_dir().join(format!("players-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let mut data = Map::new();
+        data.insert("hand".to_owned(), Value::from(3));
+
+        let saved = PlayerData {
+            position: [12.25, 40.6, -7.75],
+            rotation: [0.1, -0.2, 0.3, 0.9],
+            data,
+        };
+        saved.save(&root, 7);
+
+        // the exact position and rotation come back, not the voxel the player stood on
+        let loaded = PlayerData::load(&root, 7).unwrap();
+        assert_eq!(loaded, saved);
+        assert_eq!(loaded.spawn(1), [12, 38, -8]);
+        assert_eq!(PlayerData::load(&root, 8), None);
+
+        for &dimension in &[1, 2, 3] {
+            for &voxel in &[[0, 50, 0], [-3, 0, 17], [100, 254, -41]] {
+                let player = PlayerData::standing_on(voxel, dimension);
+                assert_eq!(player.spawn(dimension), voxel);
+            }
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
 }
@@ -1,104 +1,573 @@
 #![allow(dead_code)]
 
+use serde::{Deserialize, Serialize};
+
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
+use std::path::Path;
+
+use super::super::{
+    gen::textures::ProceduralTexture,
+    network::models::messages::{self, registry as protocol},
+};
 
-use server_common::types::{Block, TypeMap, UV};
+use actix_web::web::Bytes;
+use rand::Rng;
+
+use server_common::types::{Block, BlockDrop, Tool, TypeMap, UV};
 use server_utils::json;
 
 pub type Ranges = HashMap<String, UV>;
 pub type Blocks = HashMap<u32, Block>;
+pub type Animations = HashMap<String, Animation>;
+
+/// Shortest time a frame of an animated texture could stay on, about a frame at 60fps
+pub const MIN_FRAME_TIME: u32 = 16;
+/// Most frames an animated texture could have
+pub const MAX_ANIMATION_FRAMES: usize = 64;
+
+/// A texture cycling through frames, each of which is packed into the atlas as its own tile.
+/// Meshes are textured with the first frame, which is also the name of the animation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Animation {
+    pub frames: Vec<String>,
+    /// Milliseconds each frame stays on
+    pub frame_time: u32,
+}
+
+impl Animation {
+    fn validate(&self) -> Result<(), String> {
+        if self.frames.len() < 2 || self.frames.len() > MAX_ANIMATION_FRAMES {
+            return Err(format!(
+                "{} frames, should be within 2 to {}.",
+                self.frames.len(),
+                MAX_ANIMATION_FRAMES
+            ));
+        }
+
+        if self.frame_time < MIN_FRAME_TIME {
+            return Err(format!(
+                "frame time of {}ms, should be at least {}ms.",
+                self.frame_time, MIN_FRAME_TIME
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Index of the block files, mapping ids to files under `metadata/blocks`
+const BLOCKS_INDEX: &str = "metadata/blocks.json";
+
+/// Resolution of atlas tiles, unless a world asks otherwise
+pub const DEFAULT_TILE_SIZE: u32 = 64;
+/// Largest resolution of atlas tiles, past which the atlas would get too big to send
+pub const MAX_TILE_SIZE: u32 = 512;
+/// How many times faster a block breaks with its preferred tool
+pub const TOOL_SPEEDUP: f32 = 4.0;
+
+/// Highest light level blocks could emit, unless a world asks otherwise
+pub const DEFAULT_MAX_LIGHT_LEVEL: u32 = 15;
+
+/// How a registry is built, configured per world or for all worlds through the defaults of
+/// `worlds.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryOptions {
+    /// Texture pack under `textures/packs`, overriding textures of the same name
+    #[serde(default)]
+    pub texture_pack: Option<String>,
+    /// Resolution of each tile in the atlas
+    #[serde(default = "default_tile_size")]
+    pub tile_size: u32,
+    /// Light levels of blocks are checked against it
+    #[serde(default = "default_max_light_level")]
+    pub max_light_level: u32,
+}
+
+fn default_tile_size() -> u32 {
+    DEFAULT_TILE_SIZE
+}
+
+fn default_max_light_level() -> u32 {
+    DEFAULT_MAX_LIGHT_LEVEL
+}
+
+impl Default for RegistryOptions {
+    fn default() -> Self {
+        Self {
+            texture_pack: None,
+            tile_size: DEFAULT_TILE_SIZE,
+            max_light_level: DEFAULT_MAX_LIGHT_LEVEL,
+        }
+    }
+}
+
+impl RegistryOptions {
+    /// Registry options out of a world's configuration
+    pub fn from_config(json: &serde_json::Value) -> Result<Self, String> {
+        let options: Self = serde_json::from_value(json.to_owned())
+            .map_err(|e| format!("Bad registry options, {}", e))?;
+
+        if options.tile_size == 0 || options.tile_size > MAX_TILE_SIZE {
+            return Err(format!(
+                "Bad registry options, tile size of {} should be within 1 to {}.",
+                options.tile_size, MAX_TILE_SIZE
+            ));
+        }
+
+        Ok(options)
+    }
+}
+
+/// Something wrong with a file under `metadata` or `textures`
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub path: String,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(path: impl fmt::Display, message: impl fmt::Display) -> Self {
+        Self {
+            path: path.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Every problem found loading a registry, so they could all be fixed in one go
+#[derive(Debug, Clone)]
+pub struct RegistryError {
+    pub problems: Vec<Problem>,
+}
+
+impl From<Problem> for RegistryError {
+    fn from(problem: Problem) -> Self {
+        Self {
+            problems: vec![problem],
+        }
+    }
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Found {} problem(s) loading the registry:",
+            self.problems.len()
+        )?;
+
+        for Problem { path, message } in self.problems.iter() {
+            write!(f, "\n  {}: {}", path, message)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+/// Read a JSON file of the metadata.
+fn read_json(path: &str) -> Result<serde_json::Value, Problem> {
+    let file = File::open(path).map_err(|e| Problem::new(path, e))?;
+    serde_json::from_reader(file).map_err(|e| Problem::new(path, format!("Invalid JSON, {}", e)))
+}
+
+/// Load a texture, either an image under `textures/images` or a procedural texture, unless the
+/// texture pack has its own version of it.
+fn load_texture(
+    root: &Path,
+    source: &str,
+    options: &RegistryOptions,
+) -> Result<image::DynamicImage, Problem> {
+    let pack_path = options
+        .texture_pack
+        .as_ref()
+        .map(|pack| root.join("textures/packs").join(pack).join(source))
+        .filter(|path| path.exists());
+
+    if source.ends_with(".png") {
+        let path = pack_path.unwrap_or_else(|| root.join("textures/images").join(source));
+
+        image::open(&path).map_err(|e| Problem::new(path.display(), e))
+    } else {
+        let path = pack_path.unwrap_or_else(|| root.join("textures/procedural").join(source));
+
+        // texture data
+        let file = File::open(&path).map_err(|e| Problem::new(path.display(), e))?;
+        let bad_texture =
+            |e: String| Problem::new(path.display(), format!("Bad procedural texture, {}", e));
+        let texture: ProceduralTexture =
+            serde_json::from_reader(file).map_err(|e| bad_texture(e.to_string()))?;
+        texture.validate().map_err(bad_texture)?;
+
+        Ok(image::DynamicImage::ImageRgba8(texture.generate(source)))
+    }
+}
+
+/// Flags and light levels of a block that contradict each other or the world
+fn check_block(block: &Block, max_light_level: u32) -> Vec<String> {
+    let mut problems = vec![];
+
+    if block.is_plant && block.is_solid {
+        problems.push(format!(
+            "\"{}\" has both isPlant and isSolid, plants can't be solid.",
+            block.name
+        ));
+    }
+
+    let levels = [
+        ("redLightLevel", block.red_light_level),
+        ("greenLightLevel", block.green_light_level),
+        ("blueLightLevel", block.blue_light_level),
+    ];
+
+    for (channel, level) in levels.iter() {
+        if *level > max_light_level {
+            problems.push(format!(
+                "\"{}\" has a {} of {}, above the max light level of {}.",
+                block.name, channel, level, max_light_level
+            ));
+        }
+    }
+
+    if !block.light_attenuation.is_clear() && !block.is_transparent {
+        problems.push(format!(
+            "\"{}\" has a lightAttenuation but isn't transparent, so no light passes through.",
+            block.name
+        ));
+    }
+
+    if !block.hardness.is_finite() || block.hardness < 0.0 {
+        problems.push(format!(
+            "\"{}\" has a hardness of {}, should be 0 or above.",
+            block.name, block.hardness
+        ));
+    }
+
+    for drop in block.drops.iter().flatten() {
+        if drop.min > drop.max {
+            problems.push(format!(
+                "\"{}\" drops {} with a min of {} above its max of {}.",
+                block.name, drop.block, drop.min, drop.max
+            ));
+        }
+
+        if !(0.0..=1.0).contains(&drop.probability) {
+            problems.push(format!(
+                "\"{}\" drops {} at a probability of {}, should be within 0 to 1.",
+                block.name, drop.block, drop.probability
+            ));
+        }
+    }
+
+    problems
+}
+
+/// A solid, opaque block for tests, with the given fields on top
+#[cfg(test)]
+pub fn test_block(overrides: serde_json::Value) -> Block {
+    let mut block = serde_json::json!({
+        "name": "Test",
+        "isEmpty": false,
+        "isSolid": true,
+        "isFluid": false,
+        "isTransparent": false,
+        "isLight": false,
+        "isBlock": true,
+        "isPlant": false,
+        "isPlantable": false,
+        "redLightLevel": 0,
+        "greenLightLevel": 0,
+        "blueLightLevel": 0,
+        "transparentStandalone": false
+    });
+    json::merge(&mut block, &overrides, true);
+
+    serde_json::from_value(block).unwrap()
+}
 
 #[derive(Debug, Clone)]
 pub struct Registry {
+    /// Atlas of all the textures, with the tiles padded by their edges
     pub atlas: image::RgbaImage,
     pub ranges: Ranges,
+    pub animations: Animations,
     pub blocks: Blocks,
     pub uv_side_count: u32,
     pub uv_texture_size: u32,
+    pub uv_padding: u32,
+    /// PNGs of the atlas and then its mip levels, each half the size of the previous, encoded
+    /// once so they're served as is
+    pub pngs: Vec<Bytes>,
+    pub options: RegistryOptions,
 
     name_map: HashMap<String, u32>,
 }
 
 impl Registry {
-    pub fn new() -> Self {
-        let blocks_json: serde_json::Value =
-            serde_json::from_reader(File::open("metadata/blocks.json").unwrap()).unwrap();
+    /// A registry of the given blocks, with ids in the order given and no textures
+    #[cfg(test)]
+    pub fn from_blocks(blocks: Vec<Block>) -> Self {
+        let name_map = blocks
+            .iter()
+            .enumerate()
+            .map(|(id, block)| (block.name.to_owned(), id as u32))
+            .collect();
+
+        let atlas = image::RgbaImage::new(1, 1);
+        let pngs = vec![encode_png(&atlas).unwrap()];
+
+        Self {
+            atlas,
+            ranges: HashMap::new(),
+            animations: HashMap::new(),
+            blocks: blocks
+                .into_iter()
+                .enumerate()
+                .map(|(id, block)| (id as u32, block))
+                .collect(),
+            uv_side_count: 1,
+            uv_texture_size: 1,
+            uv_padding: 0,
+            pngs,
+            options: RegistryOptions::default(),
+            name_map,
+        }
+    }
+
+    /// Load the blocks under `metadata/blocks` and pack their textures into an atlas, collecting
+    /// the problems of every file instead of stopping at the first one.
+    pub fn load(options: &RegistryOptions) -> Result<Self, RegistryError> {
+        Self::load_from(Path::new(""), options)
+    }
+
+    /// Load the registry out of the `metadata` and `textures` under a root directory
+    fn load_from(root: &Path, options: &RegistryOptions) -> Result<Self, RegistryError> {
+        let in_root = |path: &str| root.join(path).display().to_string();
+
+        let index = in_root(BLOCKS_INDEX);
+        let blocks_json = read_json(&index)?;
+        let entries = blocks_json
+            .as_object()
+            .ok_or_else(|| Problem::new(&index, "Should map block ids to block files."))?;
 
-        let mut base_cache: HashMap<String, serde_json::Value> = HashMap::new();
-        let mut texture_map: HashMap<String, image::DynamicImage> = HashMap::new();
+        let mut problems = vec![];
+
+        let mut base_cache: HashMap<String, Option<serde_json::Value>> = HashMap::new();
+        let mut texture_map: HashMap<String, Option<image::DynamicImage>> = HashMap::new();
 
         let mut name_map = HashMap::new();
+        // files the names came from, to point duplicates to each other
+        let mut name_paths: HashMap<String, String> = HashMap::new();
 
         let mut blocks: Blocks = HashMap::new();
+        let mut animations: Animations = HashMap::new();
+
+        // in order of ids, so problems are always reported in the same order
+        let mut entries: Vec<_> = entries.iter().collect();
+        entries.sort_by_key(|(id, _)| id.parse::<u32>().unwrap_or(u32::MAX));
+
+        for (id, value) in entries {
+            let id = match id.parse::<u32>() {
+                Ok(id) => id,
+                Err(_) => {
+                    problems.push(Problem::new(
+                        &index,
+                        format!("Block id \"{}\" is not a number.", id),
+                    ));
+                    continue;
+                }
+            };
 
-        for (id, value) in blocks_json.as_object().unwrap() {
-            // remove first and last characters to remove the ""
-            let value_str = value.as_str().unwrap();
-            let path = format!("./metadata/blocks/{}", value_str);
-            let mut block_json: serde_json::Value =
-                serde_json::from_reader(File::open(path).unwrap()).unwrap();
-
-            let base = &block_json["base"];
-
-            let base = match base {
-                serde_json::Value::String(base_str) => {
-                    Some(base_cache.entry(base_str.to_owned()).or_insert_with(|| {
-                        serde_json::from_reader(
-                            File::open(format!("./metadata/blocks/{}", base_str).as_str()).unwrap(),
-                        )
-                        .unwrap()
-                    }))
+            let path = match value.as_str() {
+                Some(file) => in_root(&format!("metadata/blocks/{}", file)),
+                None => {
+                    problems.push(Problem::new(
+                        &index,
+                        format!("Block {} should be the name of a block file.", id),
+                    ));
+                    continue;
                 }
-                _ => None,
-            }
-            .unwrap();
+            };
 
-            json::merge(&mut block_json, base, false);
+            let mut block_json = match read_json(&path) {
+                Ok(block_json) if block_json.is_object() => block_json,
+                Ok(_) => {
+                    problems.push(Problem::new(&path, "Should be a JSON object."));
+                    continue;
+                }
+                Err(problem) => {
+                    problems.push(problem);
+                    continue;
+                }
+            };
 
-            let textures = &block_json["textures"];
+            if let Some(base) = block_json["base"].as_str().map(|base| base.to_owned()) {
+                let base = base_cache.entry(base).or_insert_with_key(|base| {
+                    read_json(&in_root(&format!("metadata/blocks/{}", base)))
+                        .map_err(|problem| problems.push(problem))
+                        .ok()
+                });
+
+                // the base's own problem is reported once
+                match base {
+                    Some(base) => json::merge(&mut block_json, base, false),
+                    None => continue,
+                }
+            }
+
+            // taken out, since animated textures aren't plain names
+            let textures = block_json
+                .as_object_mut()
+                .unwrap()
+                .remove("textures")
+                .unwrap_or(serde_json::Value::Null);
             let mut textures_hash = HashMap::new();
 
-            if !serde_json::Value::is_null(textures) {
-                for (side, img_src) in textures.as_object().unwrap().iter() {
-                    let img_src_str = img_src.as_str().unwrap();
+            let textures = match textures {
+                serde_json::Value::Null => serde_json::Map::new(),
+                serde_json::Value::Object(textures) => textures,
+                _ => {
+                    problems.push(Problem::new(
+                        &path,
+                        "Textures should map sides to textures.",
+                    ));
+                    continue;
+                }
+            };
 
-                    let image = if img_src_str.ends_with(".png") {
-                        image::open(&format!("textures/images/{}", img_src_str)).unwrap()
-                    } else {
-                        // texture data
-                        let texture_data: serde_json::Value = serde_json::from_reader(
-                            File::open(format!("textures/procedural/{}", img_src_str)).unwrap(),
-                        )
-                        .unwrap();
+            for (side, img_src) in textures.iter() {
+                let frames = match img_src {
+                    // animated textures, { "frames": [...], "frameTime": ms }
+                    serde_json::Value::Object(_) => {
+                        let animation = serde_json::from_value::<Animation>(img_src.clone())
+                            .map_err(|e| e.to_string())
+                            .and_then(|animation| animation.validate().map(|_| animation));
+
+                        let animation = match animation {
+                            Ok(animation) => animation,
+                            Err(e) => {
+                                problems.push(Problem::new(
+                                    &path,
+                                    format!("Bad animated texture on \"{}\", {}", side, e),
+                                ));
+                                continue;
+                            }
+                        };
+
+                        let name = animation.frames[0].to_owned();
+
+                        if let Some(existing) = animations.get(&name) {
+                            if existing != &animation {
+                                problems.push(Problem::new(
+                                    &path,
+                                    format!(
+                                        "Animated texture \"{}\" is declared differently elsewhere.",
+                                        name
+                                    ),
+                                ));
+                                continue;
+                            }
+                        }
+
+                        let frames = animation.frames.to_owned();
+
+                        animations.insert(name.to_owned(), animation);
+                        textures_hash.insert(side.to_owned(), name);
+
+                        frames
+                    }
+                    serde_json::Value::String(source) => {
+                        textures_hash.insert(side.to_owned(), source.to_owned());
+
+                        vec![source.to_owned()]
+                    }
+                    _ => {
+                        problems.push(Problem::new(
+                            &path,
+                            format!(
+                                "Texture on \"{}\" should be a file name or an animation.",
+                                side
+                            ),
+                        ));
+                        continue;
+                    }
+                };
+
+                // textures that fail to load are reported once
+                for source in frames {
+                    texture_map.entry(source).or_insert_with_key(|source| {
+                        load_texture(root, source, options)
+                            .map_err(|problem| problems.push(problem))
+                            .ok()
+                    });
+                }
+            }
 
-                        let color_vec = texture_data["color"].as_array().unwrap().as_slice();
+            let mut new_block: Block = match serde_json::from_value(block_json) {
+                Ok(block) => block,
+                Err(e) => {
+                    problems.push(Problem::new(&path, format!("Bad block, {}", e)));
+                    continue;
+                }
+            };
+            new_block.textures = textures_hash;
 
-                        let color_r = (color_vec[0].as_f64().unwrap() * 255.0) as u8;
-                        let color_g = (color_vec[1].as_f64().unwrap() * 255.0) as u8;
-                        let color_b = (color_vec[2].as_f64().unwrap() * 255.0) as u8;
+            problems.extend(
+                check_block(&new_block, options.max_light_level)
+                    .into_iter()
+                    .map(|message| Problem::new(&path, message)),
+            );
 
-                        let imgbuf = image::ImageBuffer::from_pixel(
-                            16,
-                            16,
-                            image::Rgb([color_r, color_g, color_b]),
-                        );
+            if let Some(other) = name_paths.get(&new_block.name) {
+                problems.push(Problem::new(
+                    &path,
+                    format!(
+                        "Block name \"{}\" is already taken by {}.",
+                        new_block.name, other
+                    ),
+                ));
+                continue;
+            }
 
-                        image::DynamicImage::ImageRgb8(imgbuf)
-                    };
+            name_paths.insert(new_block.name.clone(), path);
+            name_map.insert(new_block.name.clone(), id);
+            blocks.insert(id, new_block);
+        }
 
-                    texture_map.insert(img_src_str.to_owned(), image);
-                    textures_hash.insert(side.to_owned(), img_src_str.to_owned());
+        // drops could refer to blocks loaded after them
+        let mut unknown_drops = vec![];
+
+        for block in blocks.values() {
+            for drop in block.drops.iter().flatten() {
+                if !name_map.contains_key(&drop.block) {
+                    unknown_drops.push(Problem::new(
+                        &name_paths[&block.name],
+                        format!(
+                            "\"{}\" drops an unknown block \"{}\".",
+                            block.name, drop.block
+                        ),
+                    ));
                 }
             }
+        }
 
-            let mut new_block: Block = serde_json::from_value(block_json).unwrap();
-            new_block.textures = textures_hash;
-            let id = id.parse::<u32>().unwrap();
-            name_map.insert(new_block.name.clone(), id);
-            blocks.insert(id, new_block);
+        unknown_drops.sort_by(|a, b| a.path.cmp(&b.path));
+        problems.extend(unknown_drops);
+
+        if !problems.is_empty() {
+            return Err(RegistryError { problems });
         }
 
+        let texture_map: HashMap<String, image::DynamicImage> = texture_map
+            .into_iter()
+            .filter_map(|(source, image)| image.map(|image| (source, image)))
+            .collect();
+
         // OBTAINED TEXTURE MAP
         let map_size = texture_map.len() as f32;
         let mut shifts = 1;
@@ -107,9 +576,11 @@ impl Registry {
             shifts += 1;
         }
         let count_per_side = 1 << shifts;
-        let texture_dim = 64;
-        let atlas_width = count_per_side * texture_dim;
-        let atlas_height = count_per_side * texture_dim;
+        let tile_size = options.tile_size;
+        let padding = atlas_padding(tile_size);
+        let cell_size = tile_size + padding * 2;
+        let atlas_width = count_per_side * cell_size;
+        let atlas_height = count_per_side * cell_size;
 
         let mut atlas: image::RgbaImage = image::ImageBuffer::new(atlas_width, atlas_height);
 
@@ -127,17 +598,20 @@ impl Registry {
                 row += 1;
             }
 
-            let start_x = col * texture_dim;
-            let start_y = row * texture_dim;
+            let cell_x = col * cell_size;
+            let cell_y = row * cell_size;
+            let start_x = cell_x + padding;
+            let start_y = cell_y + padding;
 
             let resized = image::imageops::resize(
                 &image,
-                texture_dim,
-                texture_dim,
+                tile_size,
+                tile_size,
                 image::imageops::FilterType::CatmullRom,
             );
 
             image::imageops::overlay(&mut atlas, &resized, start_x, start_y);
+            extrude_edges(&mut atlas, cell_x, cell_y, tile_size, padding);
 
             let f_start_x = start_x as f32;
             let f_start_y = start_y as f32;
@@ -146,12 +620,9 @@ impl Registry {
             let f_atlas_height = atlas_height as f32;
 
             let start_u = f_start_x / f_atlas_width;
-            let end_u = (f_start_x + texture_dim as f32) / f_atlas_width;
+            let end_u = (f_start_x + tile_size as f32) / f_atlas_width;
             let start_v = 1.0 - f_start_y / f_atlas_height;
-            let end_v = 1.0 - (f_start_y + texture_dim as f32) / f_atlas_height;
-
-            let (start_u, start_v, end_u, end_v) =
-                fix_texture_bleeding((start_u, start_v, end_u, end_v), texture_dim);
+            let end_v = 1.0 - (f_start_y + tile_size as f32) / f_atlas_height;
 
             let uv = UV {
                 start_u,
@@ -165,18 +636,161 @@ impl Registry {
             col += 1;
         }
 
-        atlas.save("textures/atlas.png").unwrap();
+        let pngs = std::iter::once(&atlas)
+            .chain(build_mips(&atlas).iter())
+            .map(encode_png)
+            .collect::<Result<Vec<_>, _>>()?;
 
-        Self {
+        Ok(Self {
             atlas,
             ranges,
+            animations,
             blocks,
-            uv_texture_size: texture_dim,
+            pngs,
+            options: options.to_owned(),
+            uv_texture_size: tile_size,
             uv_side_count: count_per_side,
+            uv_padding: padding,
             name_map,
+        })
+    }
+
+    /// Save the atlas to `textures/atlas.png`, unless it's packed from a texture pack
+    pub fn save_atlas(&self) -> Result<(), String> {
+        if self.options.texture_pack.is_some() {
+            return Ok(());
+        }
+
+        self.atlas
+            .save("textures/atlas.png")
+            .map_err(|e| format!("textures/atlas.png: {}", e))
+    }
+
+    /// Blocks and layout of the atlas, for clients that mesh chunks themselves
+    pub fn get_protocol(&self) -> messages::Registry {
+        let blocks = self
+            .blocks
+            .iter()
+            .map(|(id, block)| {
+                let tool = match block.tool {
+                    None => protocol::Tool::Hand,
+                    Some(Tool::Pickaxe) => protocol::Tool::Pickaxe,
+                    Some(Tool::Axe) => protocol::Tool::Axe,
+                    Some(Tool::Shovel) => protocol::Tool::Shovel,
+                    Some(Tool::Shears) => protocol::Tool::Shears,
+                };
+
+                let drops = block.drops.as_ref().map(|drops| protocol::Drops {
+                    drops: drops
+                        .iter()
+                        .map(|drop| protocol::Drop {
+                            block: drop.block.to_owned(),
+                            min: drop.min,
+                            max: drop.max,
+                            probability: drop.probability,
+                        })
+                        .collect(),
+                });
+
+                let attenuation = &block.light_attenuation;
+
+                let block = protocol::Block {
+                    name: block.name.to_owned(),
+                    is_block: block.is_block,
+                    is_empty: block.is_empty,
+                    is_fluid: block.is_fluid,
+                    is_light: block.is_light,
+                    is_plant: block.is_plant,
+                    is_solid: block.is_solid,
+                    is_transparent: block.is_transparent,
+                    red_light_level: block.red_light_level,
+                    green_light_level: block.green_light_level,
+                    blue_light_level: block.blue_light_level,
+                    is_plantable: block.is_plantable,
+                    transparent_standalone: block.transparent_standalone,
+                    light_attenuation: Some(protocol::LightAttenuation {
+                        sunlight: attenuation.sunlight,
+                        red: attenuation.red,
+                        green: attenuation.green,
+                        blue: attenuation.blue,
+                    }),
+                    textures: block.textures.to_owned(),
+                    tinted_faces: block.tinted_faces.to_owned(),
+                    hardness: block.hardness,
+                    tool: tool as i32,
+                    drops,
+                    sound_group: block.sound_group.to_owned().unwrap_or_default(),
+                };
+
+                (*id, block)
+            })
+            .collect();
+
+        let ranges = self
+            .ranges
+            .iter()
+            .map(|(name, uv)| {
+                let range = protocol::Range {
+                    start_u: uv.start_u,
+                    end_u: uv.end_u,
+                    start_v: uv.start_v,
+                    end_v: uv.end_v,
+                };
+
+                (name.to_owned(), range)
+            })
+            .collect();
+
+        let animations = self
+            .animations
+            .iter()
+            .map(|(name, animation)| {
+                let animation = protocol::Animation {
+                    frames: animation.frames.to_owned(),
+                    frame_time: animation.frame_time,
+                };
+
+                (name.to_owned(), animation)
+            })
+            .collect();
+
+        messages::Registry {
+            blocks,
+            ranges,
+            animations,
+            uv_side_count: self.uv_side_count,
+            uv_texture_size: self.uv_texture_size,
+            uv_padding: self.uv_padding,
         }
     }
 
+    /// Rebuild the registry from `metadata/blocks` and `textures` with the same options.
+    /// Fails if the files can't be loaded, or if any block that exists now would lose its id,
+    /// since the chunks and clients refer to blocks by id.
+    pub fn reload(&self) -> Result<Registry, String> {
+        let registry = Registry::load(&self.options).map_err(|e| e.to_string())?;
+
+        let mut missing: Vec<String> = self
+            .blocks
+            .iter()
+            .filter(|(id, block)| {
+                registry
+                    .blocks
+                    .get(id)
+                    .map(|new_block| new_block.name != block.name)
+                    .unwrap_or(true)
+            })
+            .map(|(id, block)| format!("{} ({})", block.name, id))
+            .collect();
+
+        if !missing.is_empty() {
+            missing.sort();
+            return Err(format!("Block ids would vanish: {}", missing.join(", ")));
+        }
+
+        Ok(registry)
+    }
+
     #[inline]
     pub fn get_transparency_by_id(&self, id: u32) -> bool {
         self.get_block_by_id(id).is_transparent
@@ -315,6 +929,64 @@ impl Registry {
     pub fn has_type(&self, id: u32) -> bool {
         self.blocks.contains_key(&id)
     }
+
+    #[inline]
+    pub fn get_hardness(&self, id: u32) -> f32 {
+        self.get_block_by_id(id).hardness
+    }
+
+    #[inline]
+    pub fn get_tool(&self, id: u32) -> Option<Tool> {
+        self.get_block_by_id(id).tool
+    }
+
+    #[inline]
+    pub fn get_sound_group(&self, id: u32) -> Option<&str> {
+        self.get_block_by_id(id).sound_group.as_deref()
+    }
+
+    /// Seconds it takes to break a block, faster with the block's preferred tool
+    pub fn get_break_time(&self, id: u32, tool: Option<Tool>) -> f32 {
+        let block = self.get_block_by_id(id);
+
+        match (block.tool, tool) {
+            (Some(preferred), Some(tool)) if preferred == tool => block.hardness / TOOL_SPEEDUP,
+            _ => block.hardness,
+        }
+    }
+
+    /// Drop table of a block, which is the block itself if it has none
+    pub fn get_drops(&self, id: u32) -> Vec<BlockDrop> {
+        let block = self.get_block_by_id(id);
+
+        block.drops.to_owned().unwrap_or_else(|| {
+            vec![BlockDrop {
+                block: block.name.to_owned(),
+                min: 1,
+                max: 1,
+                probability: 1.0,
+            }]
+        })
+    }
+
+    /// Roll the drop table of a broken block, into the ids and counts of the blocks yielded.
+    pub fn roll_drops<R: Rng>(&self, id: u32, rng: &mut R) -> Vec<(u32, u32)> {
+        let mut yielded = vec![];
+
+        for drop in self.get_drops(id) {
+            if rng.gen::<f32>() >= drop.probability {
+                continue;
+            }
+
+            let count = rng.gen_range(drop.min..=drop.max);
+
+            if count > 0 {
+                yielded.push((*self.get_id_by_name(&drop.block), count));
+            }
+        }
+
+        yielded
+    }
 }
 
 pub fn get_texture_type(texture: &HashMap<String, String>) -> &str {
@@ -331,15 +1003,248 @@ pub fn get_texture_type(texture: &HashMap<String, String>) -> &str {
     }
 }
 
-fn fix_texture_bleeding(
-    (start_u, start_v, end_u, end_v): (f32, f32, f32, f32),
-    texture_size: u32,
-) -> (f32, f32, f32, f32) {
-    let offset = 0.1 / texture_size as f32;
-    (
-        start_u + offset,
-        start_v - offset,
-        end_u - offset,
-        end_v + offset,
-    )
+/// Gutter around each tile of the atlas, so sampling mip levels doesn't bleed into neighbors
+fn atlas_padding(tile_size: u32) -> u32 {
+    (tile_size / 8).max(1)
+}
+
+/// Fill the padding of a tile by stretching out the pixels on its edges.
+fn extrude_edges(atlas: &mut image::RgbaImage, cell_x: u32, cell_y: u32, tile: u32, padding: u32) {
+    let cell_size = tile + padding * 2;
+
+    for y in 0..cell_size {
+        for x in 0..cell_size {
+            let sx = x.clamp(padding, padding + tile - 1);
+            let sy = y.clamp(padding, padding + tile - 1);
+
+            if sx == x && sy == y {
+                continue;
+            }
+
+            let pixel = *atlas.get_pixel(cell_x + sx, cell_y + sy);
+            atlas.put_pixel(cell_x + x, cell_y + y, pixel);
+        }
+    }
+}
+
+/// Encode a level of the atlas as PNG
+fn encode_png(image: &image::RgbaImage) -> Result<Bytes, Problem> {
+    let mut data = vec![];
+
+    image::DynamicImage::ImageRgba8(image.to_owned())
+        .write_to(&mut data, image::ImageOutputFormat::Png)
+        .map_err(|e| Problem::new("atlas", format!("Could not encode as PNG, {}", e)))?;
+
+    Ok(Bytes::from(data))
+}
+
+/// Halve the atlas down to a single pixel, the levels after the atlas itself.
+fn build_mips(atlas: &image::RgbaImage) -> Vec<image::RgbaImage> {
+    let mut mips: Vec<image::RgbaImage> = vec![];
+    let (mut width, mut height) = atlas.dimensions();
+
+    while width > 1 || height > 1 {
+        width = (width / 2).max(1);
+        height = (height / 2).max(1);
+
+        let previous = mips.last().unwrap_or(atlas);
+        mips.push(image::imageops::resize(
+            previous,
+            width,
+            height,
+            image::imageops::FilterType::Triangle,
+        ));
+    }
+
+    mips
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_consistent_blocks() {
+        let torch = test_block(serde_json::json!({ "isLight": true, "redLightLevel": 15 }));
+
+        assert!(check_block(&torch, 15).is_empty());
+    }
+
+    #[test]
+    fn reports_solid_plants_and_bright_lights() {
+        let bad = test_block(serde_json::json!({
+            "isPlant": true,
+            "redLightLevel": 16,
+            "blueLightLevel": 20
+        }));
+
+        assert_eq!(check_block(&bad, 15).len(), 3);
+        assert_eq!(check_block(&bad, 20).len(), 1);
+    }
+
+    #[test]
+    fn reports_bad_drops_and_hardness() {
+        let bad = test_block(serde_json::json!({
+            "hardness": -1,
+            "drops": [
+                { "block": "Dirt", "min": 3, "max": 1 },
+                { "block": "Dirt", "probability": 1.5 },
+                { "block": "Dirt", "max": 2, "probability": 0.5 }
+            ]
+        }));
+
+        assert_eq!(check_block(&bad, 15).len(), 3);
+    }
+
+    /// A root of metadata and textures under the temporary directory, with a base block and a
+    /// stone texture on top of the given files
+    fn metadata_root(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+        let root = std::env::temp_dir().join(format!("registry-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+
+        std::fs::create_dir_all(root.join("metadata/blocks")).unwrap();
+        std::fs::create_dir_all(root.join("textures/images")).unwrap();
+
+        image::RgbaImage::new(4, 4)
+            .save(root.join("textures/images/stone.png"))
+            .unwrap();
+
+        let base = test_block(serde_json::json!({}));
+        let mut base = serde_json::to_value(&base).unwrap();
+        base.as_object_mut().unwrap().remove("name");
+        std::fs::write(root.join("metadata/blocks/base.json"), base.to_string()).unwrap();
+
+        for (path, contents) in files {
+            std::fs::write(root.join(path), contents).unwrap();
+        }
+
+        root
+    }
+
+    #[test]
+    fn loads_blocks_and_textures() {
+        let root = metadata_root(
+            "valid",
+            &[
+                ("metadata/blocks.json", r#"{ "1": "stone.json" }"#),
+                (
+                    "metadata/blocks/stone.json",
+                    r#"{ "base": "base.json", "name": "Stone", "textures": { "all": "stone.png" } }"#,
+                ),
+            ],
+        );
+
+        let registry = Registry::load_from(&root, &RegistryOptions::default()).unwrap();
+        assert_eq!(*registry.get_id_by_name("Stone"), 1);
+
+        // the atlas and its mip levels come encoded, down to a single pixel
+        let last = image::load_from_memory(registry.pngs.last().unwrap()).unwrap();
+        assert_eq!(image::GenericImageView::dimensions(&last), (1, 1));
+        let atlas = image::load_from_memory(&registry.pngs[0]).unwrap();
+        assert_eq!(atlas.to_rgba8(), registry.atlas);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn reports_problems_per_file() {
+        let root = metadata_root(
+            "invalid",
+            &[
+                (
+                    "metadata/blocks.json",
+                    r#"{
+                        "1": "stone.json",
+                        "2": "broken.json",
+                        "3": "rock.json",
+                        "4": "grass.json"
+                    }"#,
+                ),
+                (
+                    "metadata/blocks/stone.json",
+                    r#"{ "base": "base.json", "name": "Stone", "textures": { "all": "stone.png" } }"#,
+                ),
+                ("metadata/blocks/broken.json", r#"{ "base": "base.json", "#),
+                (
+                    "metadata/blocks/rock.json",
+                    r#"{ "base": "base.json", "name": "Stone" }"#,
+                ),
+                (
+                    "metadata/blocks/grass.json",
+                    r#"{ "base": "base.json", "name": "Grass", "textures": { "all": "grass.png" } }"#,
+                ),
+            ],
+        );
+
+        let problems = Registry::load_from(&root, &RegistryOptions::default())
+            .err()
+            .unwrap()
+            .problems;
+
+        let path = |file: &str| root.join(file).display().to_string();
+        let found: Vec<(String, &str)> = problems
+            .iter()
+            .map(|problem| (problem.path.to_owned(), problem.message.as_str()))
+            .collect();
+
+        assert_eq!(found.len(), 3);
+
+        assert_eq!(found[0].0, path("metadata/blocks/broken.json"));
+        assert!(found[0].1.starts_with("Invalid JSON"));
+
+        assert_eq!(found[1].0, path("metadata/blocks/rock.json"));
+        assert!(found[1].1.contains(&path("metadata/blocks/stone.json")));
+
+        assert_eq!(found[2].0, path("textures/images/grass.png"));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn describes_blocks_to_clients() {
+        let registry = Registry::from_blocks(vec![
+            test_block(serde_json::json!({ "name": "Air", "isEmpty": true, "drops": [] })),
+            test_block(serde_json::json!({
+                "name": "Torch", "isLight": true, "redLightLevel": 13, "tool": "pickaxe"
+            })),
+        ]);
+
+        let protocol = registry.get_protocol();
+
+        let air = &protocol.blocks[&0];
+        assert!(air.is_empty);
+        assert_eq!(air.drops.as_ref().map(|drops| drops.drops.len()), Some(0));
+
+        let torch = &protocol.blocks[&1];
+        assert_eq!(torch.red_light_level, 13);
+        assert_eq!(torch.tool(), protocol::Tool::Pickaxe);
+        assert!(torch.drops.is_none());
+    }
+
+    #[test]
+    fn validates_tile_size() {
+        let options = |tile_size: u32| {
+            RegistryOptions::from_config(&serde_json::json!({ "tileSize": tile_size }))
+        };
+
+        assert_eq!(options(32).unwrap().tile_size, 32);
+        assert!(options(0).is_err());
+        assert!(options(MAX_TILE_SIZE + 1).is_err());
+    }
+
+    #[test]
+    fn validates_animation_frames() {
+        let animation = |frames: usize, frame_time: u32| Animation {
+            frames: (0..frames).map(|i| format!("frame{}.png", i)).collect(),
+            frame_time,
+        };
+
+        assert!(animation(2, MIN_FRAME_TIME).validate().is_ok());
+        assert!(animation(MAX_ANIMATION_FRAMES, 500).validate().is_ok());
+
+        assert!(animation(0, 500).validate().is_err());
+        assert!(animation(1, 500).validate().is_err());
+        assert!(animation(MAX_ANIMATION_FRAMES + 1, 500).validate().is_err());
+        assert!(animation(2, MIN_FRAME_TIME - 1).validate().is_err());
+    }
 }
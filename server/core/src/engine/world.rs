@@ -5,24 +5,36 @@ use log::info;
 use ansi_term::Colour::Yellow;
 use specs::shred::{Fetch, FetchMut, Resource};
 
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Write;
 use std::time::Instant;
 
-use specs::{Builder, DispatcherBuilder, World as ECSWorld, WorldExt};
+use specs::{Builder, DispatcherBuilder, Entities, Join, ReadStorage, World as ECSWorld, WorldExt};
 
 use serde::{Deserialize, Serialize};
 
+use prost::Message;
+
 use super::super::{
-    comp::phys::Phys,
+    comp::{light::DynamicLight, phys::Phys},
     constants::WORLD_DATA_FILE,
-    engine::chunks::MeshLevel,
+    engine::chunks::{MeshLevel, READY_MARGIN},
+    gen::biomes::BiomeTints,
+    network::heartbeat::Heartbeat,
+    network::limits::Limits,
     network::models::{
         create_chat_message, create_message, create_of_type,
-        messages::{self, chat_message::Type as ChatType, message::Type as MessageType},
+        messages::{
+            self,
+            chat_message::Type as ChatType,
+            config::{MaybeCanFly, MaybeTickSpeed, MaybeTime},
+            message::Type as MessageType,
+            rejection::Reason as RejectReason,
+        },
         ChunkProtocol, MessageComponents,
     },
-    sys::PhysicsSystem,
+    sys::{LightSystem, PhysicsSystem},
 };
 
 use server_common::{
@@ -36,11 +48,27 @@ use server_libs::{
     rigidbody::RigidBody,
 };
 
+use server_utils::{
+    convert::{map_voxel_to_chunk, map_world_to_voxel},
+    json,
+};
+
 use super::chunks::Chunks;
 use super::clock::Clock;
-use super::players::{BroadcastExt, Players};
+use super::dynamic_lights::{DynamicLights, LightOwner, LightSource};
+use super::jobs::{Interest, Interests};
+use super::players::{BroadcastExt, Player, PlayerData, Players, MAX_SUBSCRIPTIONS};
 use super::registry::Registry;
 
+/// Configurations of the worlds to run
+const WORLDS_FILE: &str = "metadata/worlds.json";
+
+/// Bytes of chunks streamed to each player per chunking tick
+const STREAM_BUDGET: i64 = 128 * 1024;
+
+/// Most chunks streamed to each player per chunking tick, however small
+const MAX_STREAMED_CHUNKS: usize = 8;
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct WorldConfig {
@@ -54,6 +82,8 @@ pub struct WorldConfig {
     pub max_loaded_chunks: i32,
     pub sub_chunks: u32,
     pub generation: String,
+    /// Whether players may fly and go through blocks
+    pub allow_flight: bool,
 }
 
 #[derive(Deserialize)]
@@ -64,6 +94,10 @@ pub struct WorldMeta {
     preload: i16,
     tick_speed: f32,
     time: f32,
+    #[serde(default)]
+    limits: Limits,
+    #[serde(flatten)]
+    heartbeat: Heartbeat,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -79,6 +113,10 @@ pub struct World {
     pub name: String,
     pub preload: i16,
     pub description: String,
+    /// What each client in the world may send
+    pub limits: Limits,
+    /// How clients in the world are checked on
+    pub heartbeat: Heartbeat,
 }
 
 impl World {
@@ -89,6 +127,8 @@ impl World {
             preload,
             tick_speed,
             time,
+            limits,
+            heartbeat,
         } = serde_json::from_value(json.clone()).unwrap();
         let config: WorldConfig = serde_json::from_value(json).unwrap();
 
@@ -96,11 +136,13 @@ impl World {
 
         // ECS Components
         ecs.register::<Phys>();
+        ecs.register::<DynamicLight>();
 
         // ECS Resources
         ecs.insert(Chunks::new(&name, config.clone(), registry));
         ecs.insert(Clock::new(time, tick_speed));
         ecs.insert(Players::new());
+        ecs.insert(DynamicLights::new());
         ecs.insert(Physics::new(PhysicsOptions {
             gravity: Vec3(0.0, -24.0, 0.0),
             min_bounce_impulse: 0.1,
@@ -128,6 +170,8 @@ impl World {
             name,
             preload,
             description,
+            limits,
+            heartbeat,
         };
 
         if config.save {
@@ -138,6 +182,40 @@ impl World {
         new_world
     }
 
+    /// Configurations of every world in `worlds.json`, merged with the defaults. Fails on the
+    /// first world that couldn't be run off its configuration.
+    pub fn load_configs() -> Result<Vec<serde_json::Value>, String> {
+        let file = File::open(WORLDS_FILE).map_err(|e| format!("{}: {}", WORLDS_FILE, e))?;
+        let worlds_json: serde_json::Value = serde_json::from_reader(file)
+            .map_err(|e| format!("{}: Invalid JSON, {}", WORLDS_FILE, e))?;
+
+        let world_default = &worlds_json["default"];
+
+        let worlds = worlds_json["worlds"]
+            .as_array()
+            .ok_or(format!("{}: \"worlds\" should be a list.", WORLDS_FILE))?;
+
+        worlds
+            .iter()
+            .enumerate()
+            .map(|(index, world_json)| {
+                let mut world_json = world_json.clone();
+                json::merge(&mut world_json, world_default, false);
+
+                let bad_world =
+                    |e: serde_json::Error| format!("{}: Bad world #{}, {}", WORLDS_FILE, index, e);
+                let meta =
+                    serde_json::from_value::<WorldMeta>(world_json.clone()).map_err(bad_world)?;
+                meta.heartbeat
+                    .validate()
+                    .map_err(|e| format!("{}: Bad world #{}, {}", WORLDS_FILE, index, e))?;
+                serde_json::from_value::<WorldConfig>(world_json.clone()).map_err(bad_world)?;
+
+                Ok(world_json)
+            })
+            .collect()
+    }
+
     pub fn ecs(&self) -> &ECSWorld {
         &self.ecs
     }
@@ -177,52 +255,313 @@ impl World {
         self.write_resource::<Players>().broadcast(msg, exclude);
     }
 
+    pub fn send(&mut self, player_id: usize, msg: &messages::Message) {
+        self.write_resource::<Players>().send(player_id, msg);
+    }
+
+    /// Send a message only to the players with any of the chunks loaded
+    pub fn broadcast_around(
+        &mut self,
+        msg: &messages::Message,
+        coords: &[Vec2<i32>],
+        exclude: Vec<usize>,
+    ) {
+        self.write_resource::<Players>()
+            .broadcast_if(msg, |id, player| {
+                !exclude.contains(&id) && coords.iter().any(|c| player.loaded_chunks.contains(c))
+            });
+    }
+
+    /// Chunk that a world position is in
+    pub fn chunk_of(&self, position: &Vec3<f32>) -> Vec2<i32> {
+        let WorldConfig {
+            chunk_size,
+            dimension,
+            ..
+        } = *self.read_resource::<Chunks>().config;
+
+        let Vec3(px, py, pz) = *position;
+        let Vec3(vx, vy, vz) = map_world_to_voxel(px, py, pz, dimension);
+        map_voxel_to_chunk(vx, vy, vz, chunk_size)
+    }
+
+    /// Swap in a reloaded registry, remesh the loaded chunks with it and tell the players to
+    /// fetch the new blocks and atlas.
+    pub fn reload_registry(&mut self, registry: Registry) {
+        self.write_resource::<Chunks>().set_registry(registry);
+        self.broadcast(&create_of_type(MessageType::Registry), vec![]);
+    }
+
+    /// Players that mesh chunks themselves, which only care about voxel and light updates
+    pub fn client_meshing_players(&self) -> Vec<usize> {
+        self.read_resource::<Players>()
+            .iter()
+            .filter(|(_, player)| player.client_meshing)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Players that get their meshes from the server
+    pub fn server_meshing_players(&self) -> Vec<usize> {
+        self.read_resource::<Players>()
+            .iter()
+            .filter(|(_, player)| !player.client_meshing)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Chunks are streamed to players without asking, so a request is for chunks the client
+    /// lost track of. They're streamed again if still in range.
     pub fn on_chunk_request(&mut self, player_id: usize, msg: messages::Message) {
         let mut players = self.write_resource::<Players>();
 
-        let json = msg.parse_json().unwrap();
+        if let Some(player) = players.get_mut(&player_id) {
+            for messages::ChunkCoords { x, z } in msg.requests {
+                player.loaded_chunks.remove(&Vec2(x, z));
+            }
+        }
+    }
+
+    /// Stream the chunks missing around each player, closest first and within the budget of
+    /// each, and tell them to unload the chunks they went too far from.
+    pub fn stream_chunks(&mut self) {
+        let mut plans = vec![];
+        let mut players = self.write_resource::<Players>();
+
+        for (id, player) in players.iter_mut() {
+            if !player.announced || player.current_chunk.is_none() {
+                continue;
+            }
 
-        let cx = json["x"].as_i64().unwrap() as i32;
-        let cz = json["z"].as_i64().unwrap() as i32;
+            let unloads = player.unload_far_chunks();
 
-        if let Some(player) = players.get_mut(&player_id) {
-            player.requested_chunks.push_back(Vec2(cx, cz));
+            player.stream_budget = (player.stream_budget + STREAM_BUDGET).min(STREAM_BUDGET);
+            let missing = if player.stream_budget > 0 {
+                player.missing_chunks()
+            } else {
+                vec![]
+            };
+
+            plans.push((
+                *id,
+                player.client_meshing,
+                player.interest(),
+                player.subscriptions.clone(),
+                unloads,
+                missing,
+            ));
+        }
+
+        drop(players);
+
+        for (id, client_meshing, interest, subscriptions, unloads, missing) in plans {
+            if !unloads.is_empty() {
+                let mut new_message = create_of_type(MessageType::Unload);
+                new_message.unloads = unloads
+                    .into_iter()
+                    .map(|Vec2(x, z)| messages::ChunkCoords { x, z })
+                    .collect();
+                self.send(id, &new_message);
+            }
+
+            let mut budget = match self.read_resource::<Players>().get(&id) {
+                Some(player) => player.stream_budget,
+                None => continue,
+            };
+            let mut sent = vec![];
+            let mut waiting = false;
+
+            for coords in missing {
+                if budget <= 0 || sent.len() >= MAX_STREAMED_CHUNKS {
+                    break;
+                }
+
+                // jobs of chunks in subscribed areas far away shouldn't be dropped for being
+                // out of the player's range
+                let requester = match interest.as_ref() {
+                    Some(interest) if interest.contains(&coords) => Some(id),
+                    _ => None,
+                };
+
+                let mut chunks = self.write_resource::<Chunks>();
+
+                // clients meshing by themselves only need the voxels, lights and biome tints
+                let protocol = if client_meshing {
+                    let noise = chunks.noise.clone();
+
+                    chunks.get_unmeshed(&coords, requester).map(|chunk| {
+                        let &Vec3(min_x, _, min_z) = &chunk.min_inner;
+                        let &Vec3(max_x, _, max_z) = &chunk.max_inner;
+
+                        let mut protocol = chunk.get_protocol(false, true, true, MeshLevel::None);
+                        protocol.tints = Some(
+                            BiomeTints::new(min_x, min_z, max_x, max_z, &noise)
+                                .corners(min_x, min_z, max_x, max_z),
+                        );
+                        protocol
+                    })
+                } else {
+                    chunks
+                        .get(&coords, requester)
+                        .map(|chunk| chunk.get_protocol(true, true, true, MeshLevel::All))
+                };
+
+                drop(chunks);
+
+                // not ready yet, so closer chunks get sent as soon as they are
+                let protocol = match protocol {
+                    Some(protocol) => protocol,
+                    None => {
+                        waiting = true;
+                        continue;
+                    }
+                };
+
+                let mut component = MessageComponents::default_for(MessageType::Load);
+                component.chunks = Some(vec![protocol]);
+                let new_message = create_message(component);
+
+                budget -= new_message.encoded_len() as i64;
+                self.send(id, &new_message);
+                sent.push(coords);
+            }
+
+            let mut players = self.write_resource::<Players>();
+
+            if let Some(player) = players.get_mut(&id) {
+                player.stream_budget = budget;
+                player.loaded_chunks.extend(sent);
+            }
+
+            drop(players);
+
+            // chunks generated in the thread pool are only decorated once generated around again,
+            // which waits until the generation queue is done with the last round
+            let mut chunks = self.write_resource::<Chunks>();
+            let stats = chunks.job_stats();
+
+            if waiting && stats.to_generate == 0 && stats.generating == 0 {
+                let around = interest.into_iter().map(|area| (area, Some(id)));
+                let followed = subscriptions.into_iter().map(|area| (area, None));
+
+                for (area, requester) in around.chain(followed) {
+                    let radius = area.radius + READY_MARGIN;
+                    chunks.generate(&area.center, radius, false, requester);
+                }
+            }
         }
     }
 
     pub fn on_config(&mut self, _player_id: usize, msg: messages::Message) {
-        let mut clock = self.write_resource::<Clock>();
+        let config = msg.config.unwrap_or_default();
 
-        let json = msg.parse_json().unwrap();
-
-        let time = json["time"].as_f64();
-        let tick_speed = json["tickSpeed"].as_f64();
+        let mut clock = self.write_resource::<Clock>();
 
-        if let Some(time) = time {
-            clock.time = time as f32;
+        if let Some(MaybeTime::Time(time)) = config.maybe_time {
+            clock.time = time;
         }
 
-        if let Some(tick_speed) = tick_speed {
-            clock.tick_speed = tick_speed as f32;
+        if let Some(MaybeTickSpeed::TickSpeed(tick_speed)) = config.maybe_tick_speed {
+            clock.tick_speed = tick_speed;
         }
 
-        // damn?
         drop(clock);
 
         let mut new_message = create_of_type(MessageType::Config);
-        new_message.json = json.to_string();
+        new_message.config = Some(config);
 
         self.broadcast(&new_message, vec![]);
     }
 
-    pub fn on_update(&mut self, _player_id: usize, msg: messages::Message) {
+    /// Let a player fly and go through blocks or not, like when switching them to creative.
+    /// Returns false if there's no such player.
+    pub fn set_player_flight(&mut self, player_id: usize, allowed: bool) -> bool {
+        let mut players = self.write_resource::<Players>();
+
+        let player = match players.get_mut(&player_id) {
+            Some(player) => player,
+            None => return false,
+        };
+
+        player.can_fly = allowed;
+
+        let mut message = create_of_type(MessageType::Config);
+        message.config = Some(messages::Config {
+            maybe_can_fly: Some(MaybeCanFly::CanFly(allowed)),
+            ..Default::default()
+        });
+
+        players.send(player_id, &message);
+
+        true
+    }
+
+    pub fn on_update(&mut self, player_id: usize, msg: messages::Message) {
+        let mut chunks = self.write_resource::<Chunks>();
+        let max_height = chunks.config.max_height as i32;
+
+        let mut ready = vec![];
+        let mut rejections = vec![];
+
+        for update in msg.updates {
+            let messages::Update { vx, vy, vz, r#type } = update;
+
+            let reason = if vy < 0 || vy >= max_height {
+                RejectReason::OutOfRange
+            } else if !chunks.registry.has_type(r#type) {
+                RejectReason::UnknownType
+            } else {
+                match chunks.get_chunk_by_voxel(vx, vy, vz) {
+                    None => RejectReason::ChunkNotLoaded,
+                    // applied once the lights of the chunk are propagated
+                    Some(chunk) if chunk.needs_propagation => {
+                        if chunks.queue_edit(Vec3(vx, vy, vz), r#type) {
+                            continue;
+                        }
+
+                        RejectReason::TooManyPending
+                    }
+                    Some(_) => {
+                        ready.push(update);
+                        continue;
+                    }
+                }
+            };
+
+            rejections.push(messages::Rejection {
+                current: chunks.get_voxel_by_voxel(vx, vy, vz),
+                update: Some(update),
+                reason: reason as i32,
+            });
+        }
+
+        drop(chunks);
+
+        self.apply_updates(ready);
+
+        // let the player roll back what they predicted
+        if !rejections.is_empty() {
+            let mut new_message = create_of_type(MessageType::Reject);
+            new_message.rejections = rejections;
+
+            self.send(player_id, &new_message);
+        }
+    }
+
+    /// Apply voxel updates on chunks with their lights propagated, breaking the plants left
+    /// floating, and send them to the players with the chunks around.
+    pub fn apply_updates(&mut self, updates: Vec<messages::Update>) {
         let mut chunks = self.write_resource::<Chunks>();
 
         let &air = chunks.registry.get_id_by_name("Air");
 
-        let mut updates = msg.updates;
+        // popped in the order they came in
+        let mut updates: Vec<messages::Update> = updates.into_iter().rev().collect();
         let mut results = vec![];
 
+        // types of the voxels once the earlier updates are applied
+        let mut pending: HashMap<Vec3<i32>, u32> = HashMap::new();
+
         while !updates.is_empty() {
             let update = updates.pop().unwrap();
 
@@ -231,33 +570,17 @@ impl World {
             let vz = update.vz;
             let id = update.r#type;
 
-            if vy < 0 || vy >= chunks.config.max_height as i32 || !chunks.registry.has_type(id) {
-                continue;
-            }
-
-            let chunk = chunks.get_chunk_by_voxel(vx, vy, vz).unwrap();
-            if chunk.needs_propagation {
-                continue;
-            }
+            let get_voxel = |voxel: &Vec3<i32>| match pending.get(voxel) {
+                Some(&id) => id,
+                None => chunks.get_voxel_by_voxel(voxel.0, voxel.1, voxel.2),
+            };
 
-            let current_id = chunks.get_voxel_by_voxel(vx, vy, vz);
+            let current_id = get_voxel(&Vec3(vx, vy, vz));
             if chunks.registry.is_air(current_id) && chunks.registry.is_air(id) {
                 continue;
             }
 
-            chunks.start_caching();
-            chunks.update(vx, vy, vz, id);
-            chunks.stop_caching();
-
-            let neighbor_chunks = chunks.get_neighbor_chunk_coords(vx, vy, vz);
-            neighbor_chunks.into_iter().for_each(|c| {
-                chunks.chunk_cache.insert(c);
-            });
-
-            if chunks
-                .registry
-                .is_plant(chunks.get_voxel_by_voxel(vx, vy + 1, vz))
-            {
+            if chunks.registry.is_plant(get_voxel(&Vec3(vx, vy + 1, vz))) {
                 updates.push(messages::Update {
                     vx,
                     vy: vy + 1,
@@ -266,32 +589,48 @@ impl World {
                 });
             }
 
+            pending.insert(Vec3(vx, vy, vz), id);
             results.push(update);
         }
 
-        let cache = chunks.chunk_cache.clone();
-        chunks.clear_cache();
-
-        drop(chunks);
-
-        cache.iter().for_each(|coords| {
-            let mut chunks = self.write_resource::<Chunks>();
+        if results.is_empty() {
+            return;
+        }
 
-            let levels = chunks.raw(&coords).unwrap().dirty_levels.clone();
-            let mesh_level = MeshLevel::Levels(levels);
+        let voxels: Vec<(Vec3<i32>, u32)> = results
+            .iter()
+            .map(|update| (Vec3(update.vx, update.vy, update.vz), update.r#type))
+            .collect();
 
-            let chunk = chunks.get(&coords, &mesh_level, true).unwrap();
+        // lights are fixed up once for the whole batch
+        chunks.start_caching();
+        chunks.update_many(&voxels);
+        chunks.stop_caching();
 
-            let mut component = MessageComponents::default_for(MessageType::Update);
-            component.chunks = Some(vec![chunk.get_protocol(true, false, false, mesh_level)]);
+        for (Vec3(vx, vy, vz), _) in voxels.iter() {
+            let neighbor_chunks = chunks.get_neighbor_chunk_coords(*vx, *vy, *vz);
+            neighbor_chunks.into_iter().for_each(|c| {
+                chunks.chunk_cache.insert(c);
+            });
+        }
 
-            drop(chunks);
+        // clients meshing by themselves are only told which lights changed
+        let lights: Vec<messages::Light> = chunks
+            .light_cache
+            .iter()
+            .map(|&Vec3(vx, vy, vz)| messages::Light {
+                vx,
+                vy,
+                vz,
+                level: chunks.get_light_by_voxel(vx, vy, vz),
+            })
+            .collect();
 
-            let new_message = create_message(component);
-            self.broadcast(&new_message, vec![]);
-        });
+        let cache = chunks.chunk_cache.clone();
+        chunks.clear_cache();
 
-        let chunks = self.read_resource::<Chunks>();
+        // meshes are built in other threads, and sent after this update once they're done
+        cache.iter().for_each(|coords| chunks.remesh(coords));
 
         // First send the message, so borrow checker doesn't freak out
         let mut components = MessageComponents::default_for(MessageType::Update);
@@ -308,17 +647,31 @@ impl World {
         let mut new_message = create_message(components);
         new_message.updates = results;
 
+        let mut raw_message = create_of_type(MessageType::Update);
+        raw_message.updates = new_message.updates.clone();
+        raw_message.lights = lights;
+
         drop(chunks);
 
-        self.broadcast(&new_message, vec![]);
+        let mut dynamic_lights = self.write_resource::<DynamicLights>();
+        for update in new_message.updates.iter() {
+            dynamic_lights.invalidate(&Vec3(update.vx, update.vy, update.vz));
+        }
+        drop(dynamic_lights);
+
+        let cache: Vec<Vec2<i32>> = cache.into_iter().collect();
+        let client_meshing = self.client_meshing_players();
+        let server_meshing = self.server_meshing_players();
+
+        self.broadcast_around(&new_message, &cache, client_meshing);
+        self.broadcast_around(&raw_message, &cache, server_meshing);
     }
 
-    pub fn on_peer(&mut self, player_id: usize, msg: messages::Message) {
+    pub fn on_peer(&mut self, player_id: usize, mut msg: messages::Message) {
         let world_name = self.name.to_owned();
-        let mut players = self.write_resource::<Players>();
+        let mut players = self.ecs.write_resource::<Players>();
 
         let messages::Peer {
-            name,
             px,
             py,
             pz,
@@ -326,36 +679,75 @@ impl World {
             qy,
             qz,
             qw,
+            held,
             ..
-        } = &msg.peers[0];
+        } = match msg.peers.first() {
+            Some(peer) => peer.to_owned(),
+            None => return,
+        };
 
-        let player = players.get(&player_id);
+        let player = match players.get_mut(&player_id) {
+            Some(player) => player,
+            None => return,
+        };
 
-        if player.is_none() {
-            players.remove(&player_id);
-            return;
-        }
+        // players are who their account says, whatever they claim
+        let name = player.name.to_owned();
+        msg.peers[0].id = player_id.to_string();
+        msg.peers[0].name = name.to_owned();
 
-        let player = player.unwrap();
+        let freshly_joined = !player.announced;
+        player.announced = true;
 
-        let mut freshly_joined = false;
+        // the player only gets as far as their body could have, through the voxels
+        let chunks = self.ecs.read_resource::<Chunks>();
+        let physics = self.ecs.read_resource::<Physics>();
 
-        // TODO: fix this ambiguous logic
-        // means this player just joined.
-        if player.name.is_none() {
-            freshly_joined = true;
-        }
+        let test_solid = |vx: i32, vy: i32, vz: i32| {
+            let id = chunks.get_voxel_by_voxel(vx, vy, vz);
+            chunks.registry.get_solidity_by_id(id)
+        };
+
+        let reported = Vec3(px, py, pz);
+        let dt = player.movement.moved_at.elapsed().as_secs_f32();
 
-        // borrow the player again.
-        let player = players.get_mut(&player_id).unwrap();
+        let corrected = player
+            .movement
+            .advance(&physics, &reported, dt, player.can_fly, &test_solid)
+            .err();
 
-        player.name = Some(name.to_owned());
-        player.position = Vec3(*px, *py, *pz);
-        player.rotation = Quaternion(*qx, *qy, *qz, *qw);
+        player.position = corrected.clone().unwrap_or(reported);
+        player.rotation = Quaternion(qx, qy, qz, qw);
+
+        let position = player.position.to_owned();
+
+        // holding a light block lights up around the player, wherever they go
+        player.light = chunks
+            .registry
+            .blocks
+            .get(&held)
+            .and_then(|block| DynamicLight::of_block(block, position.to_owned()));
+
+        drop(physics);
+        drop(chunks);
+
+        if let Some(Vec3(cx, cy, cz)) = corrected {
+            let peer = &mut msg.peers[0];
+            peer.px = cx;
+            peer.py = cy;
+            peer.pz = cz;
+
+            let mut teleport = create_of_type(MessageType::Teleport);
+            teleport.peers = vec![peer.to_owned()];
+
+            players.send(player_id, &teleport);
+        }
 
         // ! will dropping be erroneous?
         drop(players);
 
+        let coords = self.chunk_of(&position);
+
         if freshly_joined {
             let message = format!("{} joined the world {}", name, world_name);
 
@@ -371,10 +763,97 @@ impl World {
             self.broadcast(&new_message, vec![]);
         }
 
-        self.broadcast(&msg, vec![player_id]);
+        // only the players that can see the peer hear of it, and the ones that just lost sight
+        // of it are told to remove it
+        let mut leave_message = create_of_type(MessageType::Leave);
+        leave_message.text = player_id.to_string();
+
+        let mut players = self.write_resource::<Players>();
+        let mut outgoing = vec![];
+
+        for (id, other) in players.iter_mut() {
+            if *id == player_id {
+                continue;
+            }
+
+            if other.is_interested(&coords) {
+                other.visible_peers.insert(player_id);
+                outgoing.push((*id, &msg));
+            } else if other.visible_peers.remove(&player_id) {
+                outgoing.push((*id, &leave_message));
+            }
+        }
+
+        for (id, message) in outgoing {
+            players.send(id, message);
+        }
+    }
+
+    /// Follow areas of the world on top of the one around the player, like a map or a camera
+    /// somewhere else. Areas already followed are skipped.
+    pub fn on_subscribe(&mut self, player_id: usize, msg: messages::Message) {
+        let mut players = self.write_resource::<Players>();
+
+        let player = match players.get_mut(&player_id) {
+            Some(player) => player,
+            None => return,
+        };
+
+        for area in msg.areas.iter() {
+            let interest = Interest {
+                center: Vec2(area.x, area.z),
+                radius: area.radius as i16,
+            };
+
+            if player.subscriptions.contains(&interest) {
+                continue;
+            }
+
+            if player.subscriptions.len() >= MAX_SUBSCRIPTIONS {
+                break;
+            }
+
+            player.subscriptions.push(interest);
+        }
+
+        // dynamic lights of the new areas are sent in full, and their chunks are streamed
+        player.lights_synced_at = None;
+    }
+
+    /// Stop following areas, or all of them if none are given.
+    pub fn on_unsubscribe(&mut self, player_id: usize, msg: messages::Message) {
+        let mut players = self.write_resource::<Players>();
+
+        let player = match players.get_mut(&player_id) {
+            Some(player) => player,
+            None => return,
+        };
+
+        if msg.areas.is_empty() {
+            player.subscriptions.clear();
+        } else {
+            player.subscriptions.retain(|interest| {
+                !msg.areas.iter().any(|area| {
+                    interest.center == Vec2(area.x, area.z) && interest.radius == area.radius as i16
+                })
+            });
+        }
+
+        player.lights_synced_at = None;
     }
 
-    pub fn on_chat_message(&mut self, _player_id: usize, msg: messages::Message) {
+    pub fn on_chat_message(&mut self, player_id: usize, mut msg: messages::Message) {
+        let name = match self.read_resource::<Players>().get(&player_id) {
+            Some(player) => player.name.to_owned(),
+            None => return,
+        };
+
+        // players can only speak for themselves
+        if let Some(message) = msg.message.as_mut() {
+            message.sender = name;
+            message.set_type(ChatType::Player);
+        }
+
         self.broadcast(&msg, vec![]);
     }
 
@@ -395,6 +874,23 @@ impl World {
         }
     }
 
+    /// What was saved of a player the last time they were in the world, if anything
+    pub fn load_player(&self, id: usize) -> Option<PlayerData> {
+        let chunks = self.read_resource::<Chunks>();
+
+        PlayerData::load(&chunks.root_folder, id)
+    }
+
+    /// Save a player for the next time they join, if the world is saved at all
+    pub fn save_player(&self, id: usize) {
+        let chunks = self.read_resource::<Chunks>();
+        let players = self.read_resource::<Players>();
+
+        if let (true, Some(player)) = (chunks.config.save, players.get(&id)) {
+            player.to_data().save(&chunks.root_folder, id);
+        }
+    }
+
     pub fn save(&self) {
         let chunks = self.read_resource::<Chunks>();
         let clock = self.read_resource::<Clock>();
@@ -419,6 +915,11 @@ impl World {
             file.write_all(j.as_bytes())
                 .expect("Unable to save world data");
 
+            // saving players, in case the server goes down before they leave
+            for (id, player) in self.read_resource::<Players>().iter() {
+                player.to_data().save(&chunks.root_folder, *id);
+            }
+
             // saving chunks
             chunks.save();
 
@@ -430,23 +931,196 @@ impl World {
         }
     }
 
+    /// Move the dynamic lights to where their owners are now, and send the players the changes
+    /// around them. Players who moved into another chunk get every dynamic light around instead.
+    fn tick_dynamic_lights(&mut self) {
+        let chunks = self.read_resource::<Chunks>();
+        let players = self.read_resource::<Players>();
+
+        let WorldConfig {
+            chunk_size,
+            dimension,
+            max_height,
+            max_light_level,
+            ..
+        } = *chunks.config;
+
+        let to_source = |light: &DynamicLight| {
+            let Vec3(x, y, z) = light.position;
+
+            LightSource {
+                voxel: map_world_to_voxel(x, y, z, dimension),
+                levels: light.levels(max_light_level),
+            }
+        };
+
+        let mut sources: HashMap<LightOwner, LightSource> = players
+            .iter()
+            .filter_map(|(id, player)| {
+                player
+                    .light
+                    .as_ref()
+                    .map(|light| (LightOwner::Player(*id), to_source(light)))
+            })
+            .collect();
+
+        let (entities, lights): (Entities, ReadStorage<DynamicLight>) = self.ecs.system_data();
+
+        for (entity, light) in (&entities, &lights).join() {
+            sources.insert(LightOwner::Entity(entity.id()), to_source(light));
+        }
+
+        let filter = |vx: i32, vy: i32, vz: i32| {
+            chunks.get_chunk_by_voxel(vx, vy, vz)?;
+
+            let block = chunks.get_block_by_voxel(vx, vy, vz);
+
+            if block.is_transparent {
+                Some(block.light_attenuation)
+            } else {
+                None
+            }
+        };
+
+        let mut dynamic_lights = self.ecs.write_resource::<DynamicLights>();
+        let changed = dynamic_lights.update(sources, &filter, max_height as i32);
+
+        let around = |player: &Player, voxel: &Vec3<i32>| {
+            let Vec3(vx, vy, vz) = *voxel;
+            player.is_interested(&map_voxel_to_chunk(vx, vy, vz, chunk_size))
+        };
+
+        let to_protocol = |(voxel, level): (&Vec3<i32>, &u32)| messages::Light {
+            vx: voxel.0,
+            vy: voxel.1,
+            vz: voxel.2,
+            level: *level,
+        };
+
+        let mut outgoing = vec![];
+
+        for (id, player) in players.iter() {
+            let center = match player.current_chunk.as_ref() {
+                Some(center) => center,
+                None => continue,
+            };
+
+            let full = player.lights_synced_at.as_ref() != Some(center);
+
+            let lights: Vec<messages::Light> = if full {
+                dynamic_lights
+                    .iter()
+                    .filter(|(voxel, _)| around(player, voxel))
+                    .map(to_protocol)
+                    .collect()
+            } else {
+                changed
+                    .iter()
+                    .filter(|(voxel, _)| around(player, voxel))
+                    .map(to_protocol)
+                    .collect()
+            };
+
+            if lights.is_empty() && !full {
+                continue;
+            }
+
+            let mut new_message = create_of_type(MessageType::Lights);
+            new_message.lights = lights;
+
+            new_message.full_lights = full;
+
+            outgoing.push((*id, center.to_owned(), full, new_message));
+        }
+
+        drop(dynamic_lights);
+        drop(entities);
+        drop(lights);
+        drop(players);
+        drop(chunks);
+
+        for (id, center, full, new_message) in outgoing {
+            if full {
+                let mut players = self.write_resource::<Players>();
+                players.get_mut(&id).unwrap().lights_synced_at = Some(center);
+            }
+
+            self.send(id, &new_message);
+        }
+    }
+
     pub fn tick(&mut self) {
         // TODO: make dispatchers
 
         // handle game tick
         self.write_resource::<Clock>().tick();
 
-        // handle chunk generation
-        self.write_resource::<Chunks>().tick();
+        // handle chunk generation, prioritized around the players
+        let interests: Interests = self
+            .read_resource::<Players>()
+            .iter()
+            .filter_map(|(id, player)| {
+                player.current_chunk.as_ref().map(|center| {
+                    (
+                        *id,
+                        Interest {
+                            center: center.to_owned(),
+                            radius: player.render_radius,
+                        },
+                    )
+                })
+            })
+            .collect();
+
+        self.write_resource::<Chunks>().tick(&interests);
+
+        // edits that waited on the lights of their chunks
+        let edits = self.write_resource::<Chunks>().drain_ready_edits();
+
+        if !edits.is_empty() {
+            self.apply_updates(
+                edits
+                    .into_iter()
+                    .map(|(Vec3(vx, vy, vz), id)| messages::Update {
+                        vx,
+                        vy,
+                        vz,
+                        r#type: id,
+                    })
+                    .collect(),
+            );
+        }
+
+        // send out the sub-chunks remeshed from voxel updates
+        let remeshed = self.write_resource::<Chunks>().drain_remeshed();
+        let client_meshing = self.client_meshing_players();
+
+        remeshed.into_iter().for_each(|(coords, mesh_level)| {
+            let chunks = self.read_resource::<Chunks>();
+
+            let mut component = MessageComponents::default_for(MessageType::Update);
+            component.chunks = Some(vec![chunks
+                .get_chunk(&coords)
+                .unwrap()
+                .get_protocol(true, false, false, mesh_level)]);
+
+            drop(chunks);
+
+            let new_message = create_message(component);
+            self.broadcast_around(&new_message, &[coords], client_meshing.clone());
+        });
 
         let mut dispatcher = DispatcherBuilder::new()
             .with(PhysicsSystem, "physics", &[])
+            .with(LightSystem, "lights", &["physics"])
             .build();
 
         dispatcher.dispatch(&self.ecs);
 
         self.ecs.maintain();
 
+        self.tick_dynamic_lights();
+
         // saving the chunks
         if self.read_resource::<Clock>().tick % 8000 == 0 {
             self.save()
@@ -45,12 +45,20 @@ const PLAIN_BIOME_CONFIG: BiomeConfig = BiomeConfig {
 
 pub const CAVE_SCALE: f64 = 0.03;
 
-pub fn get_biome_config(vx: i32, vz: i32, noise: &Noise) -> (i32, BiomeConfig) {
-    let vx = vx as f64;
-    let vz = vz as f64;
+/// How many columns away biome tints are blended across
+pub const TINT_BLEND_RADIUS: i32 = 2;
+
+/// Tint of faces that aren't tinted, packed as `0xRRGGBB`
+pub const UNTINTED: i32 = 0xffffff;
+
+// foliage colors at the extremes of temperature and humidity
+const HOT_DRY_TINT: [f64; 3] = [1.0, 0.93, 0.68];
+const HOT_WET_TINT: [f64; 3] = [0.82, 1.0, 0.76];
+const COLD_DRY_TINT: [f64; 3] = [0.9, 0.94, 0.86];
+const COLD_WET_TINT: [f64; 3] = [0.74, 0.9, 0.86];
 
-    let temp = noise.perlin2(vx, vz, TEMPERATURE_SCALE).abs();
-    let humidity = noise.perlin2(vx, vz, HUMIDITY_SCALE).abs();
+pub fn get_biome_config(vx: i32, vz: i32, noise: &Noise) -> (i32, BiomeConfig) {
+    let (temp, humidity) = get_climate(vx, vz, noise);
 
     if temp < 0.23 && humidity < 0.23 {
         return (50, HILL_BIOME_CONFIG);
@@ -59,6 +67,97 @@ pub fn get_biome_config(vx: i32, vz: i32, noise: &Noise) -> (i32, BiomeConfig) {
     (50, PLAIN_BIOME_CONFIG)
 }
 
+/// Temperature and humidity of a column, both within 0 to 1
+pub fn get_climate(vx: i32, vz: i32, noise: &Noise) -> (f64, f64) {
+    let vx = vx as f64;
+    let vz = vz as f64;
+
+    let temp = noise.perlin2(vx, vz, TEMPERATURE_SCALE).abs().min(1.0);
+    let humidity = noise.perlin2(vx, vz, HUMIDITY_SCALE).abs().min(1.0);
+
+    (temp, humidity)
+}
+
+/// Foliage color of a column, interpolated from its temperature and humidity
+pub fn get_biome_tint(vx: i32, vz: i32, noise: &Noise) -> [f64; 3] {
+    let (temp, humidity) = get_climate(vx, vz, noise);
+
+    let mut tint = [0.0; 3];
+
+    for (i, channel) in tint.iter_mut().enumerate() {
+        let cold = COLD_DRY_TINT[i] + (COLD_WET_TINT[i] - COLD_DRY_TINT[i]) * humidity;
+        let hot = HOT_DRY_TINT[i] + (HOT_WET_TINT[i] - HOT_DRY_TINT[i]) * humidity;
+        *channel = cold + (hot - cold) * temp;
+    }
+
+    tint
+}
+
+/// Biome tints of a rectangle of columns, sampled once and blended across neighboring columns
+/// at the corners of the voxels.
+pub struct BiomeTints {
+    min_x: i32,
+    min_z: i32,
+    depth: usize,
+    tints: Vec<[f64; 3]>,
+}
+
+impl BiomeTints {
+    /// Sample the tints for the corners of the columns from `min` to `max` (exclusive).
+    pub fn new(min_x: i32, min_z: i32, max_x: i32, max_z: i32, noise: &Noise) -> Self {
+        let min_x = min_x - TINT_BLEND_RADIUS;
+        let min_z = min_z - TINT_BLEND_RADIUS;
+        let max_x = max_x + TINT_BLEND_RADIUS;
+        let max_z = max_z + TINT_BLEND_RADIUS;
+
+        let mut tints = vec![];
+
+        for vx in min_x..max_x {
+            for vz in min_z..max_z {
+                tints.push(get_biome_tint(vx, vz, noise));
+            }
+        }
+
+        Self {
+            min_x,
+            min_z,
+            depth: (max_z - min_z) as usize,
+            tints,
+        }
+    }
+
+    /// Blended tint at a corner between columns, packed as `0xRRGGBB`.
+    pub fn get(&self, corner_x: i32, corner_z: i32) -> i32 {
+        let mut sum = [0.0; 3];
+        let mut count = 0.0;
+
+        for vx in (corner_x - TINT_BLEND_RADIUS)..(corner_x + TINT_BLEND_RADIUS) {
+            for vz in (corner_z - TINT_BLEND_RADIUS)..(corner_z + TINT_BLEND_RADIUS) {
+                let tint = self.tints
+                    [(vx - self.min_x) as usize * self.depth + (vz - self.min_z) as usize];
+
+                for (total, channel) in sum.iter_mut().zip(tint.iter()) {
+                    *total += channel;
+                }
+                count += 1.0;
+            }
+        }
+
+        let [r, g, b] = sum.map(|total| ((total / count).min(1.0) * 255.0) as i32);
+
+        (r << 16) | (g << 8) | b
+    }
+
+    /// Tints at every corner from `min` to `max` (inclusive), x-major, for clients that mesh
+    /// chunks themselves.
+    pub fn corners(&self, min_x: i32, min_z: i32, max_x: i32, max_z: i32) -> Vec<i32> {
+        (min_x..=max_x)
+            .flat_map(|corner_x| (min_z..=max_z).map(move |corner_z| (corner_x, corner_z)))
+            .map(|(corner_x, corner_z)| self.get(corner_x, corner_z))
+            .collect()
+    }
+}
+
 #[allow(dead_code)]
 pub fn get_height_within(
     x_min: i32,
@@ -9,7 +9,7 @@ use super::super::{
 
 use server_common::{
     ndarray::{ndarray, Ndarray},
-    types::Block,
+    types::{Block, LightAttenuation},
     vec::Vec3,
 };
 
@@ -70,6 +70,93 @@ impl Lights {
         (light & 0xFFF0) | (level)
     }
 
+    /// Levels a block takes off a light channel as light passes into it
+    #[inline]
+    fn get_attenuation(block: &Block, is_sunlight: bool, color: &LightColor) -> u32 {
+        let LightAttenuation {
+            sunlight,
+            red,
+            green,
+            blue,
+        } = block.light_attenuation;
+
+        if is_sunlight {
+            return sunlight;
+        }
+
+        match color {
+            LightColor::Red => red,
+            LightColor::Green => green,
+            LightColor::Blue => blue,
+            LightColor::None => 0,
+        }
+    }
+
+    /// Level of light passing from a voxel lit at `level` into a neighbor block. Sunlight at the
+    /// max level travels straight down without dimming, unless the block filters it.
+    #[inline]
+    fn next_level(
+        level: u32,
+        oy: i32,
+        block: &Block,
+        is_sunlight: bool,
+        color: &LightColor,
+        max_light_level: u32,
+    ) -> u32 {
+        let sd = is_sunlight && oy == -1 && level == max_light_level;
+        let falloff = if sd { 0 } else { 1 };
+
+        level.saturating_sub(falloff + Lights::get_attenuation(block, is_sunlight, color))
+    }
+
+    /// Level of light a block gives off on a channel by itself
+    #[inline]
+    pub fn get_emission(block: &Block, is_sunlight: bool, color: &LightColor) -> u32 {
+        if is_sunlight || !block.is_light {
+            return 0;
+        }
+
+        match color {
+            LightColor::Red => block.red_light_level,
+            LightColor::Green => block.green_light_level,
+            LightColor::Blue => block.blue_light_level,
+            LightColor::None => 0,
+        }
+    }
+
+    #[inline]
+    pub fn get_global_light(
+        chunks: &Chunks,
+        vx: i32,
+        vy: i32,
+        vz: i32,
+        is_sunlight: bool,
+        color: &LightColor,
+    ) -> u32 {
+        if is_sunlight {
+            chunks.get_sunlight(vx, vy, vz)
+        } else {
+            chunks.get_torch_light(vx, vy, vz, color)
+        }
+    }
+
+    #[inline]
+    pub fn set_global_light(
+        chunks: &mut Chunks,
+        vx: i32,
+        vy: i32,
+        vz: i32,
+        level: u32,
+        is_sunlight: bool,
+        color: &LightColor,
+    ) {
+        if is_sunlight {
+            chunks.set_sunlight(vx, vy, vz, level);
+        } else {
+            chunks.set_torch_light(vx, vy, vz, level, color);
+        }
+    }
+
     // TODO: CHANGE THIS CASTING?
     fn get_sunlight(lights: &Ndarray<u32>, x: i32, y: i32, z: i32) -> u32 {
         let x = x as usize;
@@ -200,15 +287,13 @@ impl Lights {
         }
     }
 
-    /// Remove a light source. Steps:
+    /// Remove the light of many voxels at once, visiting each lit voxel once. Steps:
     ///
     /// 1. Remove the existing lights in a flood-fill fashion
     /// 2. If external light source exists, flood fill them back
     pub fn global_remove_light(
         chunks: &mut Chunks,
-        vx: i32,
-        vy: i32,
-        vz: i32,
+        voxels: &[Vec3<i32>],
         is_sunlight: bool,
         color: &LightColor,
     ) {
@@ -218,25 +303,24 @@ impl Lights {
         let mut fill = VecDeque::<LightNode>::new();
         let mut queue = VecDeque::<LightNode>::new();
 
-        queue.push_back(LightNode {
-            voxel: Vec3(vx, vy, vz),
-            level: if is_sunlight {
-                chunks.get_sunlight(vx, vy, vz)
-            } else {
-                chunks.get_torch_light(vx, vy, vz, color)
-            },
-        });
+        for voxel in voxels {
+            let &Vec3(vx, vy, vz) = voxel;
+            let level = Lights::get_global_light(chunks, vx, vy, vz, is_sunlight, color);
 
-        if is_sunlight {
-            chunks.set_sunlight(vx, vy, vz, 0);
-        } else {
-            chunks.set_torch_light(vx, vy, vz, 0, color);
-        }
+            if level == 0 {
+                continue;
+            }
 
-        chunks.mark_saving_from_voxel(vx, vy, vz);
+            queue.push_back(LightNode {
+                voxel: voxel.to_owned(),
+                level,
+            });
 
-        while !queue.is_empty() {
-            let LightNode { voxel, level } = queue.pop_front().unwrap();
+            Lights::set_global_light(chunks, vx, vy, vz, 0, is_sunlight, color);
+            chunks.mark_saving_from_voxel(vx, vy, vz);
+        }
+
+        while let Some(LightNode { voxel, level }) = queue.pop_front() {
             let Vec3(vx, vy, vz) = voxel;
 
             for [ox, oy, oz] in VOXEL_NEIGHBORS.iter() {
@@ -250,36 +334,37 @@ impl Lights {
                 let nvz = vz + oz;
                 let n_voxel = Vec3(nvx, nvy, nvz);
 
-                let nl = if is_sunlight {
-                    chunks.get_sunlight(nvx, nvy, nvz)
-                } else {
-                    chunks.get_torch_light(nvx, nvy, nvz, color)
-                };
+                let nl = Lights::get_global_light(chunks, nvx, nvy, nvz, is_sunlight, color);
 
                 if nl == 0 {
                     continue;
                 }
 
-                // if level is less, or if sunlight is propagating downwards without stopping
-                if nl < level
-                    || (is_sunlight
-                        && *oy == -1
-                        && level == max_light_level
-                        && nl == max_light_level)
-                {
+                // at most what this voxel would have lit it to through the neighbor's filter,
+                // which includes sunlight propagating downwards without stopping
+                let block = chunks.get_block_by_voxel(nvx, nvy, nvz);
+                let expected =
+                    Lights::next_level(level, *oy, block, is_sunlight, color, max_light_level);
+
+                if nl <= expected {
+                    // light sources keep their own light, and light the removed voxels again
+                    let emitted = Lights::get_emission(block, is_sunlight, color);
+
                     queue.push_back(LightNode {
-                        voxel: n_voxel,
+                        voxel: n_voxel.clone(),
                         level: nl,
                     });
 
-                    if is_sunlight {
-                        chunks.set_sunlight(nvx, nvy, nvz, 0);
-                    } else {
-                        chunks.set_torch_light(nvx, nvy, nvz, 0, color);
+                    if emitted != 0 {
+                        fill.push_back(LightNode {
+                            voxel: n_voxel,
+                            level: emitted,
+                        });
                     }
 
+                    Lights::set_global_light(chunks, nvx, nvy, nvz, emitted, is_sunlight, color);
                     chunks.mark_saving_from_voxel(nvx, nvy, nvz);
-                } else if nl >= level && (!is_sunlight || *oy != -1 || nl > level) {
+                } else {
                     fill.push_back(LightNode {
                         voxel: n_voxel,
                         level: nl,
@@ -288,10 +373,24 @@ impl Lights {
             }
         }
 
+        // voxels queued to fill could have been removed through another voxel afterwards
+        let fill = fill
+            .into_iter()
+            .filter_map(|LightNode { voxel, .. }| {
+                let Vec3(vx, vy, vz) = voxel;
+                let level = Lights::get_global_light(chunks, vx, vy, vz, is_sunlight, color);
+
+                if level == 0 {
+                    None
+                } else {
+                    Some(LightNode { voxel, level })
+                }
+            })
+            .collect();
+
         Lights::global_flood_light(chunks, fill, is_sunlight, color);
     }
 
-    /// Flood fill light from a queue
     pub fn global_flood_light(
         chunks: &mut Chunks,
         mut queue: VecDeque<LightNode>,
@@ -314,26 +413,19 @@ impl Lights {
 
                 let nvx = vx + ox;
                 let nvz = vz + oz;
-                let sd = is_sunlight && *oy == -1 && level == max_light_level;
-                let nl = level - if sd { 0 } else { 1 };
                 let n_voxel = Vec3(nvx, nvy, nvz);
                 let block_type = chunks.get_block_by_voxel(nvx, nvy, nvz);
+                let nl =
+                    Lights::next_level(level, *oy, block_type, is_sunlight, color, max_light_level);
 
                 if !block_type.is_transparent
-                    || (if is_sunlight {
-                        chunks.get_sunlight(nvx, nvy, nvz)
-                    } else {
-                        chunks.get_torch_light(nvx, nvy, nvz, color)
-                    } >= nl)
+                    || nl == 0
+                    || Lights::get_global_light(chunks, nvx, nvy, nvz, is_sunlight, color) >= nl
                 {
                     continue;
                 }
 
-                if is_sunlight {
-                    chunks.set_sunlight(nvx, nvy, nvz, nl);
-                } else {
-                    chunks.set_torch_light(nvx, nvy, nvz, nl, color);
-                }
+                Lights::set_global_light(chunks, nvx, nvy, nvz, nl, is_sunlight, color);
 
                 chunks.mark_saving_from_voxel(nvx, nvy, nvz);
 
@@ -381,13 +473,14 @@ impl Lights {
                     continue;
                 }
 
-                let sd = is_sunlight && *oy == -1 && level == max_light_level;
-                let nl = level - if sd { 0 } else { 1 };
                 let n_voxel = Vec3(nvx, nvy, nvz);
                 let block_type =
                     registry.get_block_by_id(space.get_voxel(nvx + start_x, nvy, nvz + start_z));
+                let nl =
+                    Lights::next_level(level, *oy, block_type, is_sunlight, color, max_light_level);
 
                 if !block_type.is_transparent
+                    || nl == 0
                     || (if is_sunlight {
                         Lights::get_sunlight(&lights, nvx, nvy, nvz)
                     } else {
@@ -441,8 +534,12 @@ impl Lights {
             for x in 1..(width - 1) as i32 {
                 let h = space.get_max_height(x + start_x, z + start_z) as i32;
 
+                // sunlight coming down the column, dimmed by whatever filters it on the way
+                let mut sunlight = max_light_level;
+
                 for y in (0..max_height as i32).rev() {
                     let id = space.get_voxel(x + start_x, y, z + start_z);
+                    let block = registry.get_block_by_id(id);
                     let &Block {
                         is_transparent,
                         is_light,
@@ -450,10 +547,16 @@ impl Lights {
                         green_light_level,
                         blue_light_level,
                         ..
-                    } = registry.get_block_by_id(id);
+                    } = block;
 
                     if y > h && is_transparent {
-                        Lights::set_sunlight(&mut lights, x, y, z, max_light_level);
+                        let falloff = if sunlight == max_light_level { 0 } else { 1 };
+                        sunlight =
+                            sunlight.saturating_sub(falloff + block.light_attenuation.sunlight);
+                    }
+
+                    if y > h && is_transparent && sunlight > 0 {
+                        Lights::set_sunlight(&mut lights, x, y, z, sunlight);
 
                         for [ox, oz] in CHUNK_HORIZONTAL_NEIGHBORS.iter() {
                             let neighbor_id =
@@ -470,7 +573,7 @@ impl Lights {
                                     voxel.0 == x && voxel.1 == y && voxel.2 == z
                                 }) {
                                     sunlight_queue.push_back(LightNode {
-                                        level: max_light_level,
+                                        level: sunlight,
                                         voxel: Vec3(x, y, z),
                                     })
                                 }
@@ -560,8 +663,8 @@ impl Lights {
         for x in (margin - DATA_PADDING)..(margin + chunk_size + DATA_PADDING) {
             for z in (margin - DATA_PADDING)..(margin + chunk_size + DATA_PADDING) {
                 for cy in 0..max_height as usize {
-                    let cx = x - margin + DATA_PADDING;
-                    let cz = z - margin + DATA_PADDING;
+                    let cx = x + DATA_PADDING - margin;
+                    let cz = z + DATA_PADDING - margin;
 
                     chunk_lights[&[cx, cy, cz]] = lights[&[x, cy, z]];
                 }
@@ -3,20 +3,34 @@ use itertools::izip;
 use super::super::{
     constants::{BlockFace, CornerData, CornerSimplified, PlantFace, BLOCK_FACES, PLANT_FACES},
     engine::{
-        chunk::Chunk,
+        chunk::{Chunk, Meshes},
         registry::{get_texture_type, Registry},
         world::WorldConfig,
     },
-    gen::lights::Lights,
+    gen::{
+        biomes::{BiomeTints, UNTINTED},
+        lights::Lights,
+    },
 };
 
 use server_common::{
+    noise::Noise,
     types::{Block, MeshType, UV},
     vec::Vec3,
 };
 
 pub struct Mesher;
 
+/// How much of a sub-chunk could possibly be seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubChunkVisibility {
+    /// Nothing but air within the sub-chunk
+    Empty,
+    /// Sub-chunk and its surrounding shell are all opaque, so no face is exposed
+    Occluded,
+    Visible,
+}
+
 fn get_block_by_voxel<'a>(
     vx: i32,
     vy: i32,
@@ -28,6 +42,63 @@ fn get_block_by_voxel<'a>(
 }
 
 impl Mesher {
+    /// Classify a sub-chunk to know whether it needs meshing at all.
+    pub fn sub_chunk_visibility(
+        chunk: &Chunk,
+        sub_chunk: u32,
+        config: &WorldConfig,
+        registry: &Registry,
+    ) -> SubChunkVisibility {
+        if chunk.is_sub_chunk_empty(sub_chunk) {
+            return SubChunkVisibility::Empty;
+        }
+
+        let Chunk {
+            min_inner,
+            max_inner,
+            ..
+        } = chunk;
+
+        let sub_chunk_unit = (config.max_height / config.sub_chunks) as i32;
+        let start_y = sub_chunk as i32 * sub_chunk_unit;
+        let end_y = start_y + sub_chunk_unit;
+
+        // one extra voxel on each side to account for the neighboring faces, out of range
+        // voxels are air and thus keep the sub-chunk visible.
+        for vx in (min_inner.0 - 1)..=max_inner.0 {
+            for vz in (min_inner.2 - 1)..=max_inner.2 {
+                for vy in (start_y - 1)..=end_y {
+                    if registry.get_transparency_by_id(chunk.get_voxel(vx, vy, vz)) {
+                        return SubChunkVisibility::Visible;
+                    }
+                }
+            }
+        }
+
+        SubChunkVisibility::Occluded
+    }
+
+    /// Mesh both the opaque and transparent parts of a sub-chunk, skipping ones that can't be seen.
+    pub fn mesh_sub_chunk(
+        chunk: &Chunk,
+        sub_chunk: u32,
+        config: &WorldConfig,
+        registry: &Registry,
+        noise: &Noise,
+    ) -> Meshes {
+        if Mesher::sub_chunk_visibility(chunk, sub_chunk, config, registry)
+            != SubChunkVisibility::Visible
+        {
+            return Meshes::culled(sub_chunk);
+        }
+
+        Meshes {
+            opaque: Mesher::mesh_chunk(chunk, false, sub_chunk, config, registry, noise),
+            transparent: Mesher::mesh_chunk(chunk, true, sub_chunk, config, registry, noise),
+            sub_chunk: sub_chunk as i32,
+        }
+    }
+
     /// Meshing a chunk. Poorly written. Needs refactor.
     pub fn mesh_chunk(
         chunk: &Chunk,
@@ -35,6 +106,7 @@ impl Mesher {
         sub_chunk: u32,
         config: &WorldConfig,
         registry: &Registry,
+        noise: &Noise,
     ) -> Option<MeshType> {
         let Chunk {
             min_inner,
@@ -57,10 +129,23 @@ impl Mesher {
         let mut green_lights = Vec::<i32>::new();
         let mut blue_lights = Vec::<i32>::new();
         let mut sunlights = Vec::<i32>::new();
+        let mut tints = Vec::<i32>::new();
 
         let &Vec3(start_x, _, start_z) = min_inner;
         let &Vec3(end_x, _, end_z) = max_inner;
 
+        // only sampled once a tinted face shows up
+        let mut biome_tints: Option<BiomeTints> = None;
+        let mut tint_at = |tinted: bool, corner_x: i32, corner_z: i32| -> i32 {
+            if !tinted {
+                return UNTINTED;
+            }
+
+            biome_tints
+                .get_or_insert_with(|| BiomeTints::new(start_x, start_z, end_x, end_z, noise))
+                .get(corner_x, corner_z)
+        };
+
         let vertex_ao = |side1: u32, side2: u32, corner: u32| -> i32 {
             let num_s1 = !registry.get_transparency_by_id(side1) as i32;
             let num_s2 = !registry.get_transparency_by_id(side2) as i32;
@@ -88,6 +173,7 @@ impl Mesher {
                         is_transparent,
                         is_block,
                         is_plant,
+                        ref tinted_faces,
                         ..
                     } = registry.get_block_by_id(voxel_id);
 
@@ -107,6 +193,7 @@ impl Mesher {
                             let [dx, dz] = [0, 0];
 
                             for PlantFace { corners, mat } in PLANT_FACES.iter() {
+                                let tinted = tinted_faces.iter().any(|f| f == mat);
                                 let UV {
                                     start_u,
                                     end_u,
@@ -136,6 +223,7 @@ impl Mesher {
                                     blue_lights.push(chunk.get_blue_light(vx, vy, vz) as i32);
 
                                     aos.push(3);
+                                    tints.push(tint_at(tinted, vx + pos[0], vz + pos[2]));
                                 }
 
                                 indices.push(ndx);
@@ -176,18 +264,21 @@ impl Mesher {
                                         .map(|[a, b, c]| chunk.get_voxel(vx + a, vy + b, vz + c))
                                         .collect();
 
+                                    let side = if is_mat_1 {
+                                        "all"
+                                    } else if is_mat_3 {
+                                        *mat3
+                                    } else {
+                                        *mat6
+                                    };
+                                    let tinted = tinted_faces.iter().any(|f| f == side);
+
                                     let UV {
                                         start_u,
                                         end_u,
                                         start_v,
                                         end_v,
-                                    } = if is_mat_1 {
-                                        uv_map.get(texture.get("all").unwrap()).unwrap()
-                                    } else if is_mat_3 {
-                                        uv_map.get(texture.get(*mat3).unwrap()).unwrap()
-                                    } else {
-                                        uv_map.get(texture.get(*mat6).unwrap()).unwrap()
-                                    };
+                                    } = uv_map.get(texture.get(side).unwrap()).unwrap();
 
                                     let ndx = (positions.len() / 3) as i32;
                                     let mut face_aos = vec![];
@@ -215,6 +306,7 @@ impl Mesher {
 
                                         uvs.push(uv[0] as f32 * (end_u - start_u) + start_u);
                                         uvs.push(uv[1] as f32 * (start_v - end_v) + end_v);
+                                        tints.push(tint_at(tinted, pos_x, pos_z));
                                         face_aos.push(vertex_ao(
                                             near_voxels[*side1 as usize],
                                             near_voxels[*side2 as usize],
@@ -570,6 +662,7 @@ impl Mesher {
             uvs,
             aos,
             lights,
+            tints,
         })
     }
 }
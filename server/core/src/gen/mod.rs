@@ -3,3 +3,4 @@ pub mod builder;
 pub mod generator;
 pub mod lights;
 pub mod mesher;
+pub mod textures;
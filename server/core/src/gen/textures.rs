@@ -0,0 +1,253 @@
+use serde::Deserialize;
+
+use server_common::noise::Noise;
+
+/// Size of procedural textures that don't specify one
+pub const DEFAULT_PROCEDURAL_SIZE: u32 = 16;
+/// Largest size of procedural textures, past which generating them would stall loading
+pub const MAX_PROCEDURAL_SIZE: u32 = 256;
+
+/// Noise functions a procedural layer could sample from
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NoiseType {
+    Perlin,
+    Worley,
+    Ridged,
+}
+
+/// How a layer is combined with the layers below it
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    Add,
+    Screen,
+    Overlay,
+}
+
+impl BlendMode {
+    fn blend(self, below: f64, above: f64) -> f64 {
+        match self {
+            BlendMode::Normal => above,
+            BlendMode::Multiply => below * above,
+            BlendMode::Add => (below + above).min(1.0),
+            BlendMode::Screen => 1.0 - (1.0 - below) * (1.0 - above),
+            BlendMode::Overlay => {
+                if below < 0.5 {
+                    2.0 * below * above
+                } else {
+                    1.0 - 2.0 * (1.0 - below) * (1.0 - above)
+                }
+            }
+        }
+    }
+}
+
+/// A noise pattern colored through a gradient, from `[stop, [r, g, b]]` pairs
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextureLayer {
+    pub noise: NoiseType,
+    pub scale: f64,
+    #[serde(default = "default_octaves")]
+    pub octaves: u32,
+    pub gradient: Vec<(f64, [f64; 3])>,
+    #[serde(default)]
+    pub blend: BlendMode,
+    #[serde(default = "default_opacity")]
+    pub opacity: f64,
+}
+
+fn default_octaves() -> u32 {
+    1
+}
+
+fn default_opacity() -> f64 {
+    1.0
+}
+
+impl TextureLayer {
+    /// Noise value within 0 to 1 at a pixel, summed over the octaves
+    fn sample(&self, noise: &Noise, x: f64, y: f64) -> f64 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = self.scale;
+        let mut max = 0.0;
+
+        for _ in 0..self.octaves.max(1) {
+            let value = match self.noise {
+                NoiseType::Perlin => noise.perlin2(x, y, frequency),
+                NoiseType::Worley => noise.worley2(x, y, frequency),
+                NoiseType::Ridged => noise.ridged2(x, y, frequency),
+            };
+
+            total += (value + 1.0) / 2.0 * amplitude;
+            max += amplitude;
+            amplitude /= 2.0;
+            frequency *= 2.0;
+        }
+
+        (total / max).clamp(0.0, 1.0)
+    }
+
+    /// Color of the gradient at `t`, linearly interpolated between the closest stops
+    fn color_at(&self, t: f64) -> [f64; 3] {
+        let first = match self.gradient.first() {
+            Some(stop) => stop,
+            None => return [t, t, t],
+        };
+
+        if t <= first.0 {
+            return first.1;
+        }
+
+        for pair in self.gradient.windows(2) {
+            let (start, start_color) = pair[0];
+            let (end, end_color) = pair[1];
+
+            if t <= end {
+                let ratio = if end > start {
+                    (t - start) / (end - start)
+                } else {
+                    1.0
+                };
+
+                let mut color = [0.0; 3];
+                for (i, channel) in color.iter_mut().enumerate() {
+                    *channel = start_color[i] + (end_color[i] - start_color[i]) * ratio;
+                }
+
+                return color;
+            }
+        }
+
+        self.gradient.last().unwrap().1
+    }
+}
+
+/// Specification of a texture under `textures/procedural`. Without any layers, the texture
+/// is just the flat base `color`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProceduralTexture {
+    #[serde(default = "default_color")]
+    pub color: [f64; 3],
+    #[serde(default)]
+    pub size: Option<u32>,
+    /// Seed of the noise, defaults to one derived from the texture's name
+    #[serde(default)]
+    pub seed: Option<u32>,
+    #[serde(default)]
+    pub layers: Vec<TextureLayer>,
+}
+
+fn default_color() -> [f64; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+impl ProceduralTexture {
+    pub fn validate(&self) -> Result<(), String> {
+        match self.size {
+            Some(size) if size == 0 || size > MAX_PROCEDURAL_SIZE => Err(format!(
+                "size of {}, should be within 1 to {}.",
+                size, MAX_PROCEDURAL_SIZE
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Synthesize the texture. The same spec and name always produce the same image.
+    pub fn generate(&self, name: &str) -> image::RgbaImage {
+        let size = self.size.unwrap_or(DEFAULT_PROCEDURAL_SIZE);
+        let noise = Noise::new(self.seed.unwrap_or_else(|| name_seed(name)));
+
+        image::ImageBuffer::from_fn(size, size, |x, y| {
+            let mut color = self.color;
+
+            for layer in self.layers.iter() {
+                let value = layer.sample(&noise, x as f64, y as f64);
+                let layer_color = layer.color_at(value);
+                let opacity = layer.opacity.clamp(0.0, 1.0);
+
+                for (i, channel) in color.iter_mut().enumerate() {
+                    let blended = layer.blend.blend(*channel, layer_color[i]);
+                    *channel += (blended - *channel) * opacity;
+                }
+            }
+
+            let [r, g, b] = color;
+            let to_byte = |c: f64| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+            image::Rgba([to_byte(r), to_byte(g), to_byte(b), 255])
+        })
+    }
+}
+
+/// A stable seed from a texture name (FNV-1a), so atlases are reproducible across builds.
+fn name_seed(name: &str) -> u32 {
+    name.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> ProceduralTexture {
+        serde_json::from_str(
+            r#"{
+                "color": [0.5, 0.5, 0.5],
+                "layers": [
+                    { "noise": "perlin", "scale": 0.3, "octaves": 2, "gradient": [[0, [0, 0, 0]], [1, [1, 1, 1]]] },
+                    { "noise": "worley", "scale": 0.5, "gradient": [[0, [1, 0, 0]], [1, [0, 0, 1]]], "blend": "multiply", "opacity": 0.5 }
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn generates_deterministically() {
+        let texture = spec();
+
+        assert_eq!(texture.generate("a.json"), texture.generate("a.json"));
+        assert_ne!(texture.generate("a.json"), texture.generate("b.json"));
+    }
+
+    #[test]
+    fn flat_color_without_layers() {
+        let texture: ProceduralTexture = serde_json::from_str(r#"{ "color": [1, 0, 0] }"#).unwrap();
+        let image = texture.generate("red.json");
+
+        assert_eq!(image.dimensions(), (16, 16));
+        assert!(image.pixels().all(|p| p.0 == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn validates_size() {
+        let sized = |size: u32| ProceduralTexture {
+            size: Some(size),
+            ..spec()
+        };
+
+        assert!(spec().validate().is_ok());
+        assert!(sized(1).validate().is_ok());
+        assert!(sized(MAX_PROCEDURAL_SIZE).validate().is_ok());
+
+        assert!(sized(0).validate().is_err());
+        assert!(sized(MAX_PROCEDURAL_SIZE + 1).validate().is_err());
+    }
+
+    #[test]
+    fn interpolates_gradients() {
+        let layer = &spec().layers[0];
+
+        assert_eq!(layer.color_at(-1.0), [0.0, 0.0, 0.0]);
+        assert_eq!(layer.color_at(0.5), [0.5, 0.5, 0.5]);
+        assert_eq!(layer.color_at(2.0), [1.0, 1.0, 1.0]);
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// How long a session token stays valid after logging in. Tokens are kept in memory only, so
+/// restarting the server logs everybody out.
+pub const SESSION_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+pub const MIN_USERNAME_LENGTH: usize = 3;
+pub const MAX_USERNAME_LENGTH: usize = 16;
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// A player account, the password being an argon2 hash in PHC string format
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Account {
+    id: usize,
+    username: String,
+    password_hash: String,
+    /// World the player was last in
+    #[serde(default)]
+    world: Option<String>,
+}
+
+/// Who a client is, once their credentials or session token checked out
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Identity {
+    pub id: usize,
+    pub username: String,
+    /// World the player was last in, where they go back to unless they pick another
+    pub world: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Store {
+    last_id: usize,
+    accounts: Vec<Account>,
+}
+
+/// Player accounts saved to a local file, along with the sessions of the players logged in.
+///
+/// Hashing is slow on purpose, so it's left to `hash_password` and `verify_password` to be done
+/// off the server actor.
+#[derive(Debug, Default)]
+pub struct Accounts {
+    path: Option<PathBuf>,
+    store: Store,
+    sessions: HashMap<String, (usize, Instant)>,
+}
+
+impl Accounts {
+    /// Accounts stored at a path, none if the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let store = match File::open(path) {
+            Ok(file) => serde_json::from_reader(file)
+                .map_err(|e| format!("Accounts at {} are corrupt: {}", path.display(), e))?,
+            Err(_) => Store::default(),
+        };
+
+        Ok(Self {
+            path: Some(path.to_owned()),
+            store,
+            sessions: HashMap::new(),
+        })
+    }
+
+    /// Create an account with an already hashed password. Usernames are unique regardless of case.
+    pub fn create(&mut self, username: &str, password_hash: String) -> Result<Identity, String> {
+        validate_username(username)?;
+
+        if self.find(username).is_some() {
+            return Err(format!("Username \"{}\" is taken.", username));
+        }
+
+        let account = Account {
+            id: self.store.last_id + 1,
+            username: username.to_owned(),
+            password_hash,
+            world: None,
+        };
+        let identity = identity_of(&account);
+
+        self.store.last_id += 1;
+        self.store.accounts.push(account);
+
+        // an account that couldn't be saved would be gone on restart, so it's never made at all
+        if let Err(e) = self.save() {
+            self.store.accounts.pop();
+            self.store.last_id -= 1;
+            return Err(e);
+        }
+
+        Ok(identity)
+    }
+
+    /// The identity and password hash of an account, to check a login against
+    pub fn credentials(&self, username: &str) -> Option<(Identity, String)> {
+        self.find(username)
+            .map(|account| (identity_of(account), account.password_hash.to_owned()))
+    }
+
+    /// Start a session for an account, returning the token that proves it
+    pub fn issue_token(&mut self, id: usize) -> String {
+        let bytes: [u8; 32] = rand::thread_rng().gen();
+        let token = base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+
+        self.sessions.insert(token.to_owned(), (id, Instant::now()));
+
+        token
+    }
+
+    /// Who a session token belongs to, if it's valid. Expired sessions are dropped.
+    pub fn authenticate(&mut self, token: &str) -> Option<Identity> {
+        self.sessions
+            .retain(|_, (_, issued_at)| issued_at.elapsed() < SESSION_LIFETIME);
+
+        let (id, _) = self.sessions.get(token)?;
+
+        self.store
+            .accounts
+            .iter()
+            .find(|account| account.id == *id)
+            .map(identity_of)
+    }
+
+    /// Remember the world a player went into
+    pub fn select_world(&mut self, id: usize, world: &str) -> Result<(), String> {
+        let account = self
+            .store
+            .accounts
+            .iter_mut()
+            .find(|account| account.id == id)
+            .ok_or_else(|| format!("No account with id {}.", id))?;
+
+        if account.world.as_deref() == Some(world) {
+            return Ok(());
+        }
+
+        account.world = Some(world.to_owned());
+
+        self.save()
+    }
+
+    fn find(&self, username: &str) -> Option<&Account> {
+        self.store
+            .accounts
+            .iter()
+            .find(|account| account.username.eq_ignore_ascii_case(username))
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+
+        let json = serde_json::to_string_pretty(&self.store).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("Failed to save accounts: {}", e))
+    }
+}
+
+fn identity_of(account: &Account) -> Identity {
+    Identity {
+        id: account.id,
+        username: account.username.to_owned(),
+        world: account.world.to_owned(),
+    }
+}
+
+/// Usernames are what other players see, so only letters, digits and underscores are allowed
+pub fn validate_username(username: &str) -> Result<(), String> {
+    let length = username.chars().count();
+
+    if !(MIN_USERNAME_LENGTH..=MAX_USERNAME_LENGTH).contains(&length) {
+        return Err(format!(
+            "Usernames must be {} to {} characters long.",
+            MIN_USERNAME_LENGTH, MAX_USERNAME_LENGTH
+        ));
+    }
+
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err("Usernames can only have letters, digits and underscores.".to_owned());
+    }
+
+    Ok(())
+}
+
+pub fn validate_password(password: &str) -> Result<(), String> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(format!(
+            "Passwords must be at least {} characters long.",
+            MIN_PASSWORD_LENGTH
+        ));
+    }
+
+    Ok(())
+}
+
+/// A hash no password is known for, verified against when logging into a username that doesn't
+/// exist so that it takes as long as a wrong password would.
+pub const DUMMY_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$QEta3WoVkzbmGuJf0CZb0A$7l0ibr7WYXUILah8sKMHjAvUSJlcYrO76GnpnMJGx6o";
+
+/// Hash a password with a random salt
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Whether a password matches a hash made by `hash_password`
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn creates_unique_accounts() {
+        let mut accounts = Accounts::default();
+
+        let first = accounts.create("steve", "hash".to_owned()).unwrap();
+        let second = accounts.create("alex", "hash".to_owned()).unwrap();
+
+        assert_ne!(first.id, second.id);
+        assert!(accounts.create("Steve", "hash".to_owned()).is_err());
+        assert!(accounts.create("no spaces", "hash".to_owned()).is_err());
+        assert!(accounts.create("xx", "hash".to_owned()).is_err());
+    }
+
+    #[test]
+    fn keeps_no_accounts_that_failed_to_save() {
+        // a file in the way of the folder the accounts would be saved in
+        let blocker = env::temp_dir().join("minejs-accounts-blocker");
+        fs::write(&blocker, "").unwrap();
+
+        let mut accounts = Accounts::load(&blocker.join("accounts.json")).unwrap();
+        assert!(accounts.create("steve", "hash".to_owned()).is_err());
+        assert!(accounts.credentials("steve").is_none());
+        assert_eq!(accounts.store.last_id, 0);
+
+        fs::remove_file(blocker).unwrap();
+    }
+
+    #[test]
+    fn dummy_hash_is_verified_like_others() {
+        // a hash that fails to parse would be rejected without hashing anything
+        let fresh = hash_password("password").unwrap();
+        let dummy = PasswordHash::new(DUMMY_HASH).unwrap();
+        assert_eq!(dummy.params, PasswordHash::new(&fresh).unwrap().params);
+        assert!(!verify_password("password", DUMMY_HASH));
+    }
+
+    #[test]
+    fn authenticates_sessions() {
+        let mut accounts = Accounts::default();
+
+        let hash = hash_password("correct horse").unwrap();
+        let steve = accounts.create("steve", hash).unwrap();
+
+        let (identity, hash) = accounts.credentials("STEVE").unwrap();
+        assert_eq!(identity, steve);
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("battery staple", &hash));
+
+        let token = accounts.issue_token(steve.id);
+        assert_eq!(accounts.authenticate(&token), Some(steve.to_owned()));
+        assert_eq!(accounts.authenticate("forged"), None);
+
+        accounts.select_world(steve.id, "terrains").unwrap();
+        let identity = accounts.authenticate(&token).unwrap();
+        assert_eq!(identity.world.as_deref(), Some("terrains"));
+    }
+}
@@ -0,0 +1,120 @@
+use super::models::messages::{self, handshake::MeshEncoding};
+
+/// Version of `messages.proto`, bumped whenever a change breaks older clients
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// Oldest protocol version the server still understands
+pub const MIN_PROTOCOL_VERSION: u32 = 3;
+
+/// What the client and the server agreed on in the handshake
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    pub version: u32,
+    pub compression: bool,
+    pub mesh_encoding: MeshEncoding,
+    pub client_meshing: bool,
+    /// Radius in chunks streamed around the player
+    pub render_radius: i16,
+}
+
+impl Capabilities {
+    /// Handshake sent back to the client with what the server settled on
+    pub fn to_handshake(&self) -> messages::Handshake {
+        messages::Handshake {
+            version: self.version,
+            compression: self.compression,
+            mesh_encoding: self.mesh_encoding as i32,
+            client_meshing: self.client_meshing,
+            render_radius: self.render_radius as u32,
+        }
+    }
+}
+
+/// Settle on the capabilities of a client from its handshake, or explain why it's incompatible.
+/// Mesh encodings the server doesn't know fall back to the standard one, and render radii are
+/// capped at `max_render_radius`.
+pub fn negotiate(
+    handshake: &messages::Handshake,
+    max_render_radius: i16,
+) -> Result<Capabilities, String> {
+    let version = handshake.version;
+
+    if version > PROTOCOL_VERSION {
+        return Err(format!(
+            "Client protocol version {} is newer than the server's {}.",
+            version, PROTOCOL_VERSION
+        ));
+    }
+
+    if version < MIN_PROTOCOL_VERSION {
+        return Err(format!(
+            "Client protocol version {} is too old, the server needs at least {}.",
+            version, MIN_PROTOCOL_VERSION
+        ));
+    }
+
+    let mesh_encoding =
+        MeshEncoding::from_i32(handshake.mesh_encoding).unwrap_or(MeshEncoding::Standard);
+
+    let render_radius = match handshake.render_radius {
+        0 => max_render_radius,
+        radius => radius.min(max_render_radius as u32) as i16,
+    };
+
+    Ok(Capabilities {
+        version,
+        compression: handshake.compression,
+        mesh_encoding,
+        client_meshing: handshake.client_meshing,
+        render_radius,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handshake(version: u32) -> messages::Handshake {
+        messages::Handshake {
+            version,
+            compression: true,
+            mesh_encoding: 42,
+            client_meshing: false,
+            render_radius: 4,
+        }
+    }
+
+    #[test]
+    fn negotiates_compatible_versions() {
+        let capabilities = negotiate(&handshake(PROTOCOL_VERSION), 12).unwrap();
+
+        assert!(capabilities.compression);
+        assert_eq!(capabilities.mesh_encoding, MeshEncoding::Standard);
+        assert_eq!(capabilities.render_radius, 4);
+    }
+
+    #[test]
+    fn caps_render_radius() {
+        let mut wide = handshake(PROTOCOL_VERSION);
+        wide.render_radius = 40;
+        assert_eq!(negotiate(&wide, 12).unwrap().render_radius, 12);
+
+        wide.render_radius = 0;
+        assert_eq!(negotiate(&wide, 12).unwrap().render_radius, 12);
+    }
+
+    #[test]
+    fn negotiates_client_meshing() {
+        let mut meshing = handshake(PROTOCOL_VERSION);
+        assert!(!negotiate(&meshing, 12).unwrap().client_meshing);
+
+        meshing.client_meshing = true;
+        assert!(negotiate(&meshing, 12).unwrap().client_meshing);
+    }
+
+    #[test]
+    fn rejects_incompatible_versions() {
+        assert!(negotiate(&handshake(PROTOCOL_VERSION + 1), 12).is_err());
+        assert!(negotiate(&handshake(MIN_PROTOCOL_VERSION - 1), 12).is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::models::messages::{self, message::Type as MessageType};
+
+/// How a session makes sure its client is still there, configured in `worlds.json` in
+/// milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Heartbeat {
+    /// Time between two pings to the client
+    pub ping_interval: u64,
+    /// Time without hearing anything from the client, pongs included, before it's disconnected
+    pub heartbeat_timeout: u64,
+    /// Time without the player doing anything before they're kicked, zero to never kick them
+    pub idle_timeout: u64,
+    /// How long before being kicked for being idle the player is warned
+    pub idle_warning: u64,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            ping_interval: 10_000,
+            heartbeat_timeout: 30_000,
+            idle_timeout: 10 * 60_000,
+            idle_warning: 60_000,
+        }
+    }
+}
+
+impl Heartbeat {
+    pub fn ping_interval(&self) -> Duration {
+        Duration::from_millis(self.ping_interval)
+    }
+
+    /// Check that clients could keep up with the pings, and would be warned before being kicked.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.ping_interval == 0 {
+            return Err("ping interval should be above 0ms.".to_owned());
+        }
+
+        if self.heartbeat_timeout <= self.ping_interval {
+            return Err(format!(
+                "heartbeat timeout of {}ms, should be above the ping interval of {}ms.",
+                self.heartbeat_timeout, self.ping_interval
+            ));
+        }
+
+        if self.idle_timeout != 0
+            && (self.idle_warning == 0 || self.idle_warning >= self.idle_timeout)
+        {
+            return Err(format!(
+                "idle warning of {}ms, should be within 1ms to the idle timeout of {}ms.",
+                self.idle_warning, self.idle_timeout
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps track of when a client was last heard from and last active, along with its latency.
+///
+/// Clients report where they are on an interval whether they move or not, so only reports of
+/// another position or rotation count as activity.
+#[derive(Debug)]
+pub struct Monitor {
+    pub heartbeat: Heartbeat,
+    /// Round trip time of the last answered ping
+    pub latency: Option<Duration>,
+    heard_at: Instant,
+    active_at: Instant,
+    /// Whether the player was warned about being idle since they were last active
+    warned: bool,
+    /// Last position and rotation reported by the client
+    pose: Option<[f32; 7]>,
+    /// Payload and time of the ping waiting for its pong
+    ping: Option<(u64, Instant)>,
+    pings: u64,
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self::new(Heartbeat::default(), Instant::now())
+    }
+}
+
+impl Monitor {
+    pub fn new(heartbeat: Heartbeat, now: Instant) -> Self {
+        Self {
+            heartbeat,
+            latency: None,
+            heard_at: now,
+            active_at: now,
+            warned: false,
+            pose: None,
+            ping: None,
+            pings: 0,
+        }
+    }
+
+    /// Any frame came in from the client
+    pub fn heard(&mut self, now: Instant) {
+        self.heard_at = now;
+    }
+
+    /// A message came in from the client, which resets the idle timer unless it's the same report
+    /// of where the player is as last time.
+    pub fn observe(&mut self, message: &messages::Message, now: Instant) {
+        self.heard(now);
+
+        if message.r#type() == MessageType::Peer {
+            let pose = match message.peers.first() {
+                Some(peer) => [
+                    peer.px, peer.py, peer.pz, peer.qx, peer.qy, peer.qz, peer.qw,
+                ],
+                None => return,
+            };
+
+            let first = self.pose.is_none();
+            let moved = self.pose.replace(pose) != Some(pose);
+
+            if first || !moved {
+                return;
+            }
+        }
+
+        self.active_at = now;
+        self.warned = false;
+    }
+
+    /// Payload of the next ping to send. A ping still waiting for its pong is given up on.
+    pub fn ping(&mut self, now: Instant) -> Vec<u8> {
+        self.pings += 1;
+        self.ping = Some((self.pings, now));
+
+        self.pings.to_be_bytes().to_vec()
+    }
+
+    /// A pong came in, returns the latency if it answers the last ping
+    pub fn pong(&mut self, payload: &[u8], now: Instant) -> Option<Duration> {
+        self.heard(now);
+
+        let (pings, sent_at) = self.ping?;
+
+        if payload != pings.to_be_bytes() {
+            return None;
+        }
+
+        self.ping = None;
+        self.latency = Some(now.saturating_duration_since(sent_at));
+
+        self.latency
+    }
+
+    /// Check on the client, either a reason to disconnect it or a warning to send it if any
+    pub fn check(&mut self, now: Instant) -> Result<Option<String>, String> {
+        let Heartbeat {
+            heartbeat_timeout,
+            idle_timeout,
+            idle_warning,
+            ..
+        } = self.heartbeat;
+
+        let silent = now.saturating_duration_since(self.heard_at);
+
+        if silent > Duration::from_millis(heartbeat_timeout) {
+            return Err(format!(
+                "Timed out, nothing heard for {} seconds.",
+                silent.as_secs()
+            ));
+        }
+
+        if idle_timeout == 0 {
+            return Ok(None);
+        }
+
+        let idle = now.saturating_duration_since(self.active_at);
+        let idle_timeout = Duration::from_millis(idle_timeout);
+
+        if idle >= idle_timeout {
+            return Err("Kicked for being idle.".to_owned());
+        }
+
+        let left = idle_timeout - idle;
+
+        if !self.warned && left <= Duration::from_millis(idle_warning) {
+            self.warned = true;
+
+            return Ok(Some(format!(
+                "You will be kicked for being idle in {} seconds.",
+                left.as_secs_f32().ceil()
+            )));
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::models::create_of_type;
+    use super::*;
+
+    fn monitor(now: Instant) -> Monitor {
+        Monitor::new(
+            Heartbeat {
+                ping_interval: 1_000,
+                heartbeat_timeout: 3_000,
+                idle_timeout: 60_000,
+                idle_warning: 10_000,
+            },
+            now,
+        )
+    }
+
+    fn at(now: Instant, secs: u64) -> Instant {
+        now + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn validates_heartbeat() {
+        assert!(Heartbeat::default().validate().is_ok());
+        assert!(monitor(Instant::now()).heartbeat.validate().is_ok());
+
+        let bad = |change: fn(&mut Heartbeat)| {
+            let mut heartbeat = Heartbeat::default();
+            change(&mut heartbeat);
+            heartbeat.validate().is_err()
+        };
+
+        assert!(bad(|h| h.ping_interval = 0));
+        assert!(bad(|h| h.heartbeat_timeout = 0));
+        assert!(bad(|h| h.heartbeat_timeout = h.ping_interval));
+        assert!(bad(|h| h.idle_warning = 0));
+        assert!(bad(|h| h.idle_warning = h.idle_timeout));
+
+        // idle players are never kicked, so there's nothing to warn them about
+        assert!(!bad(|h| {
+            h.idle_timeout = 0;
+            h.idle_warning = 0;
+        }));
+    }
+
+    #[test]
+    fn measures_latency_and_times_out() {
+        let now = Instant::now();
+        let mut monitor = monitor(now);
+
+        let payload = monitor.ping(now);
+        assert_eq!(monitor.pong(b"stale", at(now, 1)), None);
+        assert_eq!(
+            monitor.pong(&payload, now + Duration::from_millis(40)),
+            Some(Duration::from_millis(40))
+        );
+
+        assert!(monitor.check(at(now, 3)).is_ok());
+        assert!(monitor.check(at(now, 4)).is_err());
+    }
+
+    #[test]
+    fn warns_then_kicks_idle_players() {
+        let now = Instant::now();
+        let mut monitor = monitor(now);
+
+        let mut peer = create_of_type(MessageType::Peer);
+        peer.peers = vec![messages::Peer::default()];
+
+        // reports of the same position keep the player connected, but still idle
+        for secs in 0..55 {
+            monitor.observe(&peer, at(now, secs));
+        }
+
+        assert_eq!(monitor.check(at(now, 49)), Ok(None));
+        assert!(monitor.check(at(now, 50)).unwrap().is_some());
+        assert_eq!(monitor.check(at(now, 51)), Ok(None));
+
+        // moving again resets the idle timer
+        peer.peers[0].px = 1.0;
+        monitor.observe(&peer, at(now, 55));
+        assert_eq!(monitor.check(at(now, 57)), Ok(None));
+
+        monitor.heard(at(now, 105));
+        assert!(monitor.check(at(now, 105)).unwrap().is_some());
+
+        monitor.heard(at(now, 115));
+        assert!(monitor.check(at(now, 115)).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use super::models::messages::{self, message::Type as MessageType};
+
+/// How often a client may send a kind of message: bursts of up to `burst` messages, after which
+/// `per_second` messages a second are let through.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rate {
+    pub burst: f32,
+    pub per_second: f32,
+}
+
+impl Rate {
+    pub const fn new(burst: f32, per_second: f32) -> Self {
+        Self { burst, per_second }
+    }
+}
+
+/// What a single client may send, configured by `limits` in `worlds.json`. Clients going over
+/// any of these are disconnected.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Limits {
+    /// Largest frame in bytes
+    pub max_frame_size: usize,
+    /// Most block updates in a single message
+    pub max_updates: usize,
+    pub peer: Rate,
+    pub update: Rate,
+    pub chat: Rate,
+    pub request: Rate,
+    /// Every other kind of message, such as configs and subscriptions
+    pub other: Rate,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_frame_size: 16 * 1024,
+            max_updates: 256,
+            // clients report where they are about 60 times a second
+            peer: Rate::new(60.0, 90.0),
+            update: Rate::new(40.0, 20.0),
+            chat: Rate::new(5.0, 1.0),
+            request: Rate::new(20.0, 10.0),
+            other: Rate::new(10.0, 5.0),
+        }
+    }
+}
+
+impl Limits {
+    /// Rate at which a kind of message is let through
+    pub fn rate_of(&self, kind: MessageType) -> &Rate {
+        match kind {
+            MessageType::Peer => &self.peer,
+            MessageType::Update => &self.update,
+            MessageType::Message => &self.chat,
+            MessageType::Request => &self.request,
+            _ => &self.other,
+        }
+    }
+}
+
+/// Tokens of a single kind of message, taken one per message and refilled over time
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f32,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: &Rate, now: Instant) -> Self {
+        Self {
+            tokens: rate.burst,
+            refilled_at: now,
+        }
+    }
+
+    /// Take a token out of the bucket, returns false if there's none left
+    fn take(&mut self, rate: &Rate, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f32();
+
+        self.tokens = (self.tokens + elapsed * rate.per_second).min(rate.burst);
+        self.refilled_at = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+
+        true
+    }
+}
+
+/// Keeps a client within its limits, with a token bucket per kind of message
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    pub limits: Limits,
+    buckets: HashMap<MessageType, TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Check a frame before it's decoded, or explain why it's not accepted
+    pub fn check_frame(&self, size: usize) -> Result<(), String> {
+        if size > self.limits.max_frame_size {
+            return Err(format!(
+                "Message of {} bytes is too large, at most {} are allowed.",
+                size, self.limits.max_frame_size
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Let a message through, or explain why it's not
+    pub fn check(&mut self, message: &messages::Message, now: Instant) -> Result<(), String> {
+        let kind = message.r#type();
+
+        if message.updates.len() > self.limits.max_updates {
+            return Err(format!(
+                "Too many block updates at once, at most {} are allowed.",
+                self.limits.max_updates
+            ));
+        }
+
+        let rate = self.limits.rate_of(kind);
+        let bucket = self
+            .buckets
+            .entry(kind)
+            .or_insert_with(|| TokenBucket::new(rate, now));
+
+        if !bucket.take(rate, now) {
+            let name = match kind {
+                MessageType::Message => "chat".to_owned(),
+                kind => format!("{:?}", kind).to_lowercase(),
+            };
+
+            return Err(format!("Too many {} messages, slow down.", name));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::models::create_of_type;
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(Limits {
+            chat: Rate::new(2.0, 1.0),
+            max_updates: 4,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn refills_buckets_over_time() {
+        let mut limiter = limiter();
+        let chat = create_of_type(MessageType::Message);
+        let now = Instant::now();
+
+        assert!(limiter.check(&chat, now).is_ok());
+        assert!(limiter.check(&chat, now).is_ok());
+        assert!(limiter.check(&chat, now).is_err());
+
+        // other kinds of messages have buckets of their own
+        assert!(limiter
+            .check(&create_of_type(MessageType::Peer), now)
+            .is_ok());
+
+        assert!(limiter.check(&chat, now + Duration::from_secs(1)).is_ok());
+        assert!(limiter.check(&chat, now + Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_messages() {
+        let mut limiter = limiter();
+
+        let mut update = create_of_type(MessageType::Update);
+        update.updates = vec![messages::Update::default(); 5];
+
+        assert!(limiter.check(&update, Instant::now()).is_err());
+        assert!(limiter.check_frame(16 * 1024).is_ok());
+        assert!(limiter.check_frame(16 * 1024 + 1).is_err());
+    }
+}
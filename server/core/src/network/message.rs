@@ -1,9 +1,21 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
 use serde::{Deserialize, Serialize};
 
 use actix::prelude::*;
+use actix_web::web::Bytes;
 
-use super::super::engine::registry::{Blocks, Ranges};
+use super::super::engine::{
+    export::Snapshot,
+    jobs::JobStats,
+    registry::{Animations, Blocks, Ranges, Registry, RegistryOptions},
+};
 
+use super::accounts::Identity;
+use super::heartbeat::Heartbeat;
+use super::limits::Limits;
 use super::models;
 
 /// Base actor message to derive from
@@ -14,24 +26,57 @@ pub struct Message(pub models::messages::Message);
 /* -------------------------------------------------------------------------- */
 /*                             Connection Messages                            */
 /* -------------------------------------------------------------------------- */
-#[derive(MessageResponse)]
 pub struct JoinResult {
     pub id: usize,
     pub time: f32,
     pub tick_speed: f32,
     pub spawn: [i32; 3],
+    /// Where a returning player's eyes were when they left, in world units
+    pub position: Option<[f32; 3]>,
+    pub rotation: Option<[f32; 4]>,
     pub passables: Vec<u32>,
+    pub can_fly: bool,
+    pub limits: Limits,
+    pub heartbeat: Heartbeat,
+    /// Blocks and atlas layout, for clients that mesh chunks themselves
+    pub registry: Option<models::messages::Registry>,
 }
 
+/// Put a logged in player into a world, failing if they're already playing
 #[derive(Clone, Message)]
-#[rtype(result = "JoinResult")]
+#[rtype(result = "Result<JoinResult, String>")]
 pub struct JoinWorld {
     pub world_name: String,
-    pub player_name: Option<String>,
+    pub player_id: usize,
+    pub player_name: String,
     pub player_addr: Recipient<Message>,
     pub render_radius: i16,
+    pub client_meshing: bool,
+}
+
+/// Create an account with a password hashed by `accounts::hash_password`
+#[derive(Clone, Message)]
+#[rtype(result = "Result<Identity, String>")]
+pub struct CreateAccount {
+    pub username: String,
+    pub password_hash: String,
 }
 
+/// Identity and password hash of an account, to check a login against
+#[derive(Clone, Message)]
+#[rtype(result = "Option<(Identity, String)>")]
+pub struct GetCredentials(pub String);
+
+/// Start a session for an account, returning its token
+#[derive(Clone, Message)]
+#[rtype(result = "String")]
+pub struct IssueToken(pub usize);
+
+/// Who a session token belongs to, if anybody
+#[derive(Clone, Message)]
+#[rtype(result = "Option<Identity>")]
+pub struct Authenticate(pub String);
+
 #[derive(Clone, Message)]
 #[rtype(result = "()")]
 pub struct LeaveWorld {
@@ -39,6 +84,15 @@ pub struct LeaveWorld {
     pub player_id: usize,
 }
 
+/// Round trip time of a player's connection, measured by their session's pings
+#[derive(Clone, Message)]
+#[rtype(result = "()")]
+pub struct ReportLatency {
+    pub world_name: String,
+    pub player_id: usize,
+    pub latency: Duration,
+}
+
 /* -------------------------------------------------------------------------- */
 /*                             Game Play Messages                             */
 /* -------------------------------------------------------------------------- */
@@ -53,10 +107,6 @@ pub struct PlayerMessage {
     pub raw: models::messages::Message,
 }
 
-#[derive(Clone, Message)]
-#[rtype(result = "()")]
-pub struct Noop;
-
 /* -------------------------------------------------------------------------- */
 /*                              Routing Messages                              */
 /* -------------------------------------------------------------------------- */
@@ -71,6 +121,7 @@ pub struct SimpleWorldData {
     pub generation: String,
     pub description: String,
     pub players: usize,
+    pub jobs: JobStats,
 }
 
 #[derive(MessageResponse, Deserialize, Serialize, Debug)]
@@ -88,14 +139,71 @@ pub struct FullWorldData {
     pub sub_chunks: u32,
     pub blocks: Blocks,
     pub ranges: Ranges,
+    pub animations: Animations,
     pub uv_side_count: u32,
     pub uv_texture_size: u32,
+    pub uv_padding: u32,
 }
 
 #[derive(Clone, Message)]
 #[rtype(result = "Vec<SimpleWorldData>")]
 pub struct ListWorlds;
 
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PlayerInfo {
+    pub id: usize,
+    pub name: String,
+    /// Round trip time in milliseconds, unknown until the player answered a ping
+    pub latency: Option<u128>,
+}
+
+/// Players in a world
+#[derive(Clone, Message)]
+#[rtype(result = "Result<Vec<PlayerInfo>, String>")]
+pub struct ListPlayers(pub String);
+
 #[derive(Clone, Message)]
 #[rtype(result = "FullWorldData")]
 pub struct GetWorld(pub String);
+
+/// Limits on what clients of a world may send, the defaults if there's no such world
+#[derive(Clone, Message)]
+#[rtype(result = "Limits")]
+pub struct GetLimits(pub String);
+
+/// Copy a region between two corners out of a world to export it, either `x,z` chunks or `x,y,z`
+/// voxels
+#[derive(Clone, Message)]
+#[rtype(result = "Result<Snapshot, String>")]
+pub struct ExportWorld {
+    pub world_name: String,
+    pub min: String,
+    pub max: String,
+}
+
+/// PNG of a world's atlas at a mip level, 0 being the atlas itself
+#[derive(Clone, Message)]
+#[rtype(result = "Option<Bytes>")]
+pub struct GetAtlas {
+    pub world_name: Option<String>,
+    pub level: usize,
+}
+
+/// Allow a player to fly or not
+#[derive(Clone, Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct SetFlight {
+    pub world_name: String,
+    pub player_id: usize,
+    pub allowed: bool,
+}
+
+/// The distinct registries of the worlds, to be rebuilt from disk off the thread running them
+#[derive(Clone, Message)]
+#[rtype(result = "Vec<Arc<Registry>>")]
+pub struct GetRegistries;
+
+/// Swap in the registries rebuilt from disk, keyed by the options they were built with
+#[derive(Clone, Message)]
+#[rtype(result = "()")]
+pub struct ReloadRegistry(pub HashMap<RegistryOptions, Registry>);
@@ -1,3 +1,7 @@
+pub mod accounts;
+pub mod handshake;
+pub mod heartbeat;
+pub mod limits;
 pub mod message;
 pub mod models;
 pub mod routes;
@@ -13,6 +13,8 @@ pub struct ChunkProtocol {
     pub meshes: Option<Vec<Meshes>>,
     pub voxels: Option<Ndarray<u32>>,
     pub lights: Option<Ndarray<u32>>,
+    /// Biome tints at the corners of the columns, for clients meshing the chunk themselves
+    pub tints: Option<Vec<i32>>,
 }
 
 #[derive(Debug)]
@@ -46,7 +48,6 @@ pub struct ChatProtocol {
 #[derive(Debug)]
 pub struct MessageComponents {
     pub r#type: messages::message::Type,
-    pub json: Option<String>,
     pub text: Option<String>,
     pub message: Option<ChatProtocol>,
     pub peers: Option<Vec<PeerProtocol>>,
@@ -58,7 +59,6 @@ impl MessageComponents {
     pub fn default_for(message_type: messages::message::Type) -> Self {
         Self {
             r#type: message_type,
-            json: None,
             text: None,
             message: None,
             peers: None,
@@ -72,12 +72,6 @@ pub mod messages {
     include!(concat!(env!("OUT_DIR"), "/protocol.rs"));
 }
 
-impl messages::Message {
-    pub fn parse_json(&self) -> Result<serde_json::Value, serde_json::Error> {
-        serde_json::from_str(&self.json)
-    }
-}
-
 pub fn create_of_type(r#type: messages::message::Type) -> messages::Message {
     create_message(MessageComponents::default_for(r#type))
 }
@@ -88,10 +82,6 @@ pub fn create_message(components: MessageComponents) -> messages::Message {
         ..Default::default()
     };
 
-    if let Some(json) = components.json {
-        message.json = json;
-    }
-
     if let Some(text) = components.text {
         message.text = text;
     }
@@ -117,6 +107,7 @@ pub fn create_message(components: MessageComponents) -> messages::Message {
                 qy: peer.qy,
                 qz: peer.qz,
                 qw: peer.qw,
+                ..Default::default()
             })
             .collect()
     }
@@ -140,6 +131,7 @@ pub fn create_message(components: MessageComponents) -> messages::Message {
                                     positions: opaque.positions.to_owned(),
                                     lights: opaque.lights.to_owned(),
                                     uvs: opaque.uvs.to_owned(),
+                                    tints: opaque.tints.to_owned(),
                                 }),
                                 transparent: transparent.map(|transparent| messages::Geometry {
                                     aos: transparent.aos.to_owned(),
@@ -147,6 +139,7 @@ pub fn create_message(components: MessageComponents) -> messages::Message {
                                     positions: transparent.positions.to_owned(),
                                     lights: transparent.lights.to_owned(),
                                     uvs: transparent.uvs.to_owned(),
+                                    tints: transparent.tints.to_owned(),
                                 }),
                             }
                         })
@@ -164,6 +157,7 @@ pub fn create_message(components: MessageComponents) -> messages::Message {
                 } else {
                     Vec::<u32>::new()
                 },
+                tints: chunk.tints.unwrap_or_default(),
                 x: chunk.x,
                 z: chunk.z,
             })
@@ -1,7 +1,9 @@
 use actix::SystemService;
 use actix_files as fs;
+use actix_http::ws::Codec;
 use actix_web::{
-    get,
+    error::BlockingError,
+    get, post,
     web::{self, Query},
     Error, HttpRequest, HttpResponse, Result,
 };
@@ -9,36 +11,170 @@ use actix_web_actors::ws;
 
 use std::{
     collections::HashMap,
+    env,
     time::{SystemTime, UNIX_EPOCH},
 };
 
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+
+use super::super::engine::export::{zip, ExportFile, ExportFormat};
+use super::accounts::{self, Identity};
 use super::{message, server::WsServer, session};
 
+/// Environment variable holding the token for admin-only routes
+pub const ADMIN_TOKEN_VAR: &str = "MINEJS_ADMIN_TOKEN";
+
+/// Whether a request carries the admin token as `Authorization: Bearer <token>`. Admin routes
+/// are disabled altogether when no token is set.
+fn is_admin(req: &HttpRequest) -> bool {
+    let token = match env::var(ADMIN_TOKEN_VAR) {
+        Ok(token) if !token.is_empty() => token,
+        _ => return false,
+    };
+
+    req.headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        // compared in constant time, so how long it takes doesn't give away the token
+        .map(|value| bool::from(value.as_bytes().ct_eq(token.as_bytes())))
+        .unwrap_or(false)
+}
+
 pub async fn ws_route(
     req: HttpRequest,
     params: Query<HashMap<String, String>>,
     stream: web::Payload,
 ) -> Result<HttpResponse, Error> {
-    let world_query = params.get("world");
+    // players log in first, and connect with the token they got
+    let identity = match params.get("token") {
+        Some(token) => WsServer::from_registry()
+            .send(message::Authenticate(token.to_owned()))
+            .await
+            .unwrap(),
+        None => None,
+    };
 
-    let world_name = match world_query {
-        Some(name) => name.to_owned(),
-        None => {
-            let world_names = WsServer::from_registry()
-                .send(message::ListWorldNames)
-                .await
-                .unwrap();
-            world_names[0].to_owned()
-        }
+    let identity = match identity {
+        Some(identity) => identity,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
     };
 
+    // players go back to the world they were last in, unless they pick one
+    let world_names = WsServer::from_registry()
+        .send(message::ListWorldNames)
+        .await
+        .unwrap();
+
+    let world_name = params
+        .get("world")
+        .or_else(|| {
+            identity
+                .world
+                .as_ref()
+                .filter(|name| world_names.contains(name))
+        })
+        .unwrap_or(&world_names[0])
+        .to_owned();
+
+    // capabilities such as client meshing are agreed on in the handshake
     let player = session::WsSession {
-        world_name,
+        id: identity.id,
+        name: identity.username,
+        world_name: world_name.clone(),
         render_radius: 12,
         ..Default::default()
     };
 
-    ws::start(player, &req, stream)
+    // frames over the limit are refused as they come in, before being buffered
+    let limits = WsServer::from_registry()
+        .send(message::GetLimits(world_name))
+        .await
+        .unwrap();
+
+    let codec = Codec::new().max_size(limits.max_frame_size);
+
+    Ok(ws::handshake(&req)?.streaming(ws::WebsocketContext::with_codec(player, stream, codec)))
+}
+
+#[derive(Deserialize)]
+pub struct Credentials {
+    username: String,
+    password: String,
+}
+
+/// What players get back from logging in, the token being what they connect with
+#[derive(Serialize)]
+struct Session {
+    id: usize,
+    username: String,
+    token: String,
+}
+
+/// Create an account and log into it, e.g. `{ "username": "steve", "password": "..." }`.
+#[post("/register")]
+pub async fn register(credentials: web::Json<Credentials>) -> Result<HttpResponse> {
+    let Credentials { username, password } = credentials.into_inner();
+
+    if let Err(e) =
+        accounts::validate_username(&username).and(accounts::validate_password(&password))
+    {
+        return Ok(HttpResponse::BadRequest().body(e));
+    }
+
+    let password_hash = web::block(move || accounts::hash_password(&password)).await?;
+
+    let identity = WsServer::from_registry()
+        .send(message::CreateAccount {
+            username,
+            password_hash,
+        })
+        .await
+        .unwrap();
+
+    match identity {
+        Ok(identity) => start_session(identity).await,
+        Err(e) => Ok(HttpResponse::BadRequest().body(e)),
+    }
+}
+
+/// Log into an account, e.g. `{ "username": "steve", "password": "..." }`.
+#[post("/login")]
+pub async fn login(credentials: web::Json<Credentials>) -> Result<HttpResponse> {
+    let Credentials { username, password } = credentials.into_inner();
+
+    // unknown usernames still go through a hash, so the response time doesn't tell them apart
+    let (identity, hash) = match WsServer::from_registry()
+        .send(message::GetCredentials(username))
+        .await
+        .unwrap()
+    {
+        Some((identity, hash)) => (Some(identity), hash),
+        None => (None, accounts::DUMMY_HASH.to_owned()),
+    };
+
+    let verified =
+        web::block(move || -> Result<bool, ()> { Ok(accounts::verify_password(&password, &hash)) })
+            .await?;
+
+    match identity {
+        Some(identity) if verified => start_session(identity).await,
+        _ => Ok(HttpResponse::Unauthorized().body("Wrong username or password.")),
+    }
+}
+
+async fn start_session(identity: Identity) -> Result<HttpResponse> {
+    let token = WsServer::from_registry()
+        .send(message::IssueToken(identity.id))
+        .await
+        .unwrap();
+
+    Ok(HttpResponse::Ok().json(Session {
+        id: identity.id,
+        username: identity.username,
+        token,
+    }))
 }
 
 #[get("/")]
@@ -46,9 +182,26 @@ pub async fn index() -> Result<fs::NamedFile> {
     Ok(fs::NamedFile::open("public/index.html")?)
 }
 
+/// Texture atlas of a world, e.g. `/atlas?world=terrains&level=1` for its first mip level.
 #[get("/atlas")]
-pub async fn atlas() -> Result<fs::NamedFile> {
-    Ok(fs::NamedFile::open("textures/atlas.png")?)
+pub async fn atlas(params: Query<HashMap<String, String>>) -> Result<HttpResponse> {
+    let level = params
+        .get("level")
+        .and_then(|level| level.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    let data = WsServer::from_registry()
+        .send(message::GetAtlas {
+            world_name: params.get("world").cloned(),
+            level,
+        })
+        .await
+        .unwrap();
+
+    Ok(match data {
+        Some(data) => HttpResponse::Ok().content_type("image/png").body(data),
+        None => HttpResponse::NotFound().finish(),
+    })
 }
 
 #[get("/worlds")]
@@ -90,3 +243,169 @@ pub async fn time(params: Query<HashMap<String, String>>) -> Result<HttpResponse
             .as_millis()
     )))
 }
+
+/// Players in a world along with their latency, e.g. `/players?world=terrains`.
+#[get("/players")]
+pub async fn players(params: Query<HashMap<String, String>>) -> Result<HttpResponse> {
+    let world_name = match params.get("world") {
+        Some(world_name) => world_name.to_owned(),
+        None => return Ok(HttpResponse::BadRequest().body("Missing world.")),
+    };
+
+    let result = WsServer::from_registry()
+        .send(message::ListPlayers(world_name))
+        .await
+        .unwrap();
+
+    Ok(match result {
+        Ok(players) => HttpResponse::Ok().json(players),
+        Err(e) => HttpResponse::NotFound().body(e),
+    })
+}
+
+/// Reload the blocks and textures of every world from disk, without restarting the server.
+#[post("/reload")]
+pub async fn reload(req: HttpRequest) -> Result<HttpResponse> {
+    if !is_admin(&req) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let registries = WsServer::from_registry()
+        .send(message::GetRegistries)
+        .await
+        .unwrap();
+
+    // packing the atlases takes a while, so only swapping them in is left to the worlds, and
+    // only if all of them are fine
+    let reloaded = web::block(move || {
+        registries
+            .iter()
+            .map(|registry| Ok((registry.options.to_owned(), registry.reload()?)))
+            .collect::<Result<HashMap<_, _>, String>>()
+    })
+    .await;
+
+    let reloaded = match reloaded {
+        Ok(reloaded) => reloaded,
+        Err(BlockingError::Error(e)) => return Ok(HttpResponse::BadRequest().body(e)),
+        Err(BlockingError::Canceled) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    WsServer::from_registry()
+        .send(message::ReloadRegistry(reloaded))
+        .await
+        .unwrap();
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Let a player fly or not, e.g. `/flight?world=terrains&player=42&allowed=true`.
+#[post("/flight")]
+pub async fn flight(
+    req: HttpRequest,
+    params: Query<HashMap<String, String>>,
+) -> Result<HttpResponse> {
+    if !is_admin(&req) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let world_name = match params.get("world") {
+        Some(world_name) => world_name.to_owned(),
+        None => return Ok(HttpResponse::BadRequest().body("Missing world.")),
+    };
+
+    let player_id = match params.get("player").map(|id| id.parse::<usize>()) {
+        Some(Ok(id)) => id,
+        _ => return Ok(HttpResponse::BadRequest().body("Missing or invalid player.")),
+    };
+
+    let allowed = match params.get("allowed").map(|allowed| allowed.parse::<bool>()) {
+        Some(Ok(allowed)) => allowed,
+        _ => return Ok(HttpResponse::BadRequest().body("Missing or invalid allowed.")),
+    };
+
+    let result = WsServer::from_registry()
+        .send(message::SetFlight {
+            world_name,
+            player_id,
+            allowed,
+        })
+        .await
+        .unwrap();
+
+    Ok(match result {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => HttpResponse::BadRequest().body(e),
+    })
+}
+
+/// Download a region of a world as a model, e.g. `/export?world=terrains&min=0,0&max=3,3&format=obj`.
+/// OBJ exports consist of several files, which are downloaded together as a zip archive.
+#[get("/export")]
+pub async fn export(
+    req: HttpRequest,
+    params: Query<HashMap<String, String>>,
+) -> Result<HttpResponse> {
+    if !is_admin(&req) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let (world_name, min, max) = match (params.get("world"), params.get("min"), params.get("max")) {
+        (Some(world_name), Some(min), Some(max)) => (world_name, min, max),
+        _ => return Ok(HttpResponse::BadRequest().body("Missing world, min or max.")),
+    };
+
+    let format = match params
+        .get("format")
+        .map(|f| f.as_str())
+        .unwrap_or("gltf")
+        .parse::<ExportFormat>()
+    {
+        Ok(format) => format,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+
+    let snapshot = WsServer::from_registry()
+        .send(message::ExportWorld {
+            world_name: world_name.to_owned(),
+            min: min.to_owned(),
+            max: max.to_owned(),
+        })
+        .await
+        .unwrap();
+
+    let snapshot = match snapshot {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+
+    let name = world_name.to_owned();
+    let files = match web::block(move || snapshot.export(format, &name)).await {
+        Ok(files) => files,
+        Err(BlockingError::Error(e)) => return Ok(HttpResponse::BadRequest().body(e)),
+        Err(BlockingError::Canceled) => return Ok(HttpResponse::InternalServerError().finish()),
+    };
+
+    // the files of an export reference each other, so they're kept together
+    let file = match files.len() {
+        1 => files.into_iter().next().unwrap(),
+        _ => ExportFile {
+            name: format!("{}.zip", world_name),
+            data: zip(&files),
+        },
+    };
+
+    let content_type = match file.name.rsplit('.').next() {
+        Some("gltf") => "model/gltf+json",
+        Some("zip") => "application/zip",
+        _ => "application/octet-stream",
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", file.name),
+        )
+        .body(file.data))
+}
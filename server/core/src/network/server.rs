@@ -1,20 +1,24 @@
 use actix::prelude::*;
 use actix_broker::BrokerSubscribe;
 
-use log::info;
+use log::{error, info};
 
 use ansi_term::Colour::Yellow;
 
-use std::collections::{HashMap, VecDeque};
-use std::fs::File;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 
 use super::super::{
+    constants::ACCOUNTS_FILE,
     engine::{
-        chunks::{Chunks, MeshLevel},
+        chunks::Chunks,
         clock::Clock,
-        players::{Player, Players},
-        registry::Registry,
+        export::{Region, Snapshot},
+        movement::Movement,
+        players::{Player, PlayerData, Players},
+        registry::{Registry, RegistryOptions},
         world::{World, WorldConfig},
     },
     network::models::create_chat_message,
@@ -22,18 +26,17 @@ use super::super::{
 
 use server_common::{quaternion::Quaternion, vec::Vec3};
 
-use server_utils::{
-    convert::{map_voxel_to_chunk, map_world_to_voxel},
-    json,
-};
+use server_utils::convert::{map_voxel_to_chunk, map_world_to_voxel};
 
+use super::accounts::Accounts;
 use super::message::{
-    FullWorldData, GetWorld, JoinResult, JoinWorld, LeaveWorld, ListWorldNames, ListWorlds, Noop,
-    PlayerMessage, SimpleWorldData,
+    Authenticate, CreateAccount, ExportWorld, FullWorldData, GetAtlas, GetCredentials, GetLimits,
+    GetRegistries, GetWorld, IssueToken, JoinResult, JoinWorld, LeaveWorld, ListPlayers,
+    ListWorldNames, ListWorlds, PlayerInfo, PlayerMessage, ReloadRegistry, ReportLatency,
+    SetFlight, SimpleWorldData,
 };
 use super::models::{
-    create_message, messages, messages::chat_message::Type as ChatType,
-    messages::message::Type as MessageType, MessageComponents,
+    messages, messages::chat_message::Type as ChatType, messages::message::Type as MessageType,
 };
 
 const SERVER_TICK: Duration = Duration::from_millis(16);
@@ -42,42 +45,129 @@ const CHUNKING_TICK: Duration = Duration::from_millis(18);
 #[derive(Default)]
 pub struct WsServer {
     worlds: HashMap<String, World>,
+    accounts: Accounts,
 }
 
 impl WsServer {
+    /// Load the worlds of `worlds.json` and the accounts, to be started before serving anyone
+    pub fn load() -> Result<Self, String> {
+        Ok(Self {
+            worlds: WsServer::load_worlds()?,
+            accounts: Accounts::load(Path::new(ACCOUNTS_FILE))?,
+        })
+    }
+
+    /// Load the worlds of `worlds.json`, worlds with the same registry options sharing the same
+    /// registry.
+    fn load_worlds() -> Result<HashMap<String, World>, String> {
+        let mut worlds = HashMap::new();
+        let mut registries: HashMap<RegistryOptions, Registry> = HashMap::new();
+
+        for world_json in World::load_configs()? {
+            let name = world_json["name"].as_str().unwrap_or_default().to_owned();
+            let options = RegistryOptions::from_config(&world_json)
+                .map_err(|e| format!("World \"{}\": {}", name, e))?;
+
+            let registry = match registries.get(&options) {
+                Some(registry) => registry.clone(),
+                None => {
+                    let registry = Registry::load(&options)
+                        .map_err(|e| format!("Metadata of world \"{}\" is invalid. {}", name, e))?;
+                    registry.save_atlas()?;
+
+                    registries.insert(options, registry.clone());
+                    registry
+                }
+            };
+
+            let mut new_world = World::new(world_json, registry);
+            new_world.preload();
+            worlds.insert(name, new_world);
+        }
+
+        Ok(worlds)
+    }
+
     fn add_player_to_world(
         &mut self,
         world_name: &str,
-        id: Option<usize>,
-        player: Player,
-    ) -> JoinResult {
-        let mut id = id.unwrap_or_else(rand::random::<usize>);
-        let world = self.worlds.get_mut(world_name).expect("World not found.");
-
-        let mut players = world.write_resource::<Players>();
-
-        loop {
-            if players.contains_key(&id) {
-                id = rand::random::<usize>();
-            } else {
-                break;
-            }
+        id: usize,
+        mut player: Player,
+    ) -> Result<JoinResult, String> {
+        // accounts can only be in one place at once
+        if self
+            .worlds
+            .values()
+            .any(|world| world.read_resource::<Players>().contains_key(&id))
+        {
+            return Err("Already playing from somewhere else.".to_owned());
         }
 
-        players.insert(id, player);
+        let world = self
+            .worlds
+            .get_mut(world_name)
+            .ok_or_else(|| format!("No world named {}.", world_name))?;
+
+        let saved = world.load_player(id);
+        let chunks = world.read_resource::<Chunks>();
+
+        let passables = chunks.registry.get_passable_solids();
+        let registry = if player.client_meshing {
+            Some(chunks.registry.get_protocol())
+        } else {
+            None
+        };
+        let WorldConfig {
+            dimension,
+            allow_flight,
+            ..
+        } = *chunks.config;
+
+        // players come back to exactly where they left, otherwise they start at the world's spawn
+        let restored = saved.is_some();
+        let saved = saved.unwrap_or_else(|| {
+            PlayerData::standing_on([0, chunks.get_max_height(0, 0) as i32, 0], dimension)
+        });
+
+        drop(chunks);
 
-        drop(players);
+        let spawn = saved.spawn(dimension);
+        let PlayerData {
+            position,
+            rotation,
+            data,
+        } = saved;
+
+        if restored {
+            let [qx, qy, qz, qw] = rotation;
+            player.rotation = Quaternion(qx, qy, qz, qw);
+            player.data = data;
+        }
+
+        let [px, py, pz] = position;
+        let eye = Vec3(px, py, pz);
+
+        player.movement = Movement::new(&eye, dimension);
+        player.position = eye;
+        player.can_fly = allow_flight;
+
+        world.write_resource::<Players>().insert(id, player);
 
         let clock = world.read_resource::<Clock>();
-        let chunks = world.read_resource::<Chunks>();
 
-        JoinResult {
+        Ok(JoinResult {
             id,
             time: clock.time,
             tick_speed: clock.tick_speed,
-            spawn: [0, chunks.get_max_height(0, 0) as i32, 0],
-            passables: chunks.registry.get_passable_solids(),
-        }
+            spawn,
+            position: restored.then_some(position),
+            rotation: restored.then_some(rotation),
+            passables,
+            can_fly: allow_flight,
+            limits: world.limits.clone(),
+            heartbeat: world.heartbeat.clone(),
+            registry,
+        })
     }
 
     fn broadcast(
@@ -94,8 +184,6 @@ impl WsServer {
     }
 
     fn tick(&mut self) {
-        let mut to_generate = vec![];
-
         for world in self.worlds.values_mut() {
             world.tick();
 
@@ -109,10 +197,11 @@ impl WsServer {
 
             drop(chunks);
 
+            let mut to_generate = vec![];
             let mut players = world.write_resource::<Players>();
 
-            for player in players.values_mut() {
-                if player.name.is_none() {
+            for (id, player) in players.iter_mut() {
+                if !player.announced {
                     continue;
                 }
 
@@ -128,77 +217,24 @@ impl WsServer {
                 {
                     player.current_chunk = Some(new_chunk.clone());
 
-                    to_generate.push((new_chunk, player.render_radius));
+                    to_generate.push((new_chunk, player.render_radius, *id));
                 }
             }
 
             drop(players);
 
-            to_generate.iter().for_each(|(coords, r)| {
-                world.write_resource::<Chunks>().generate(coords, *r, false)
+            to_generate.iter().for_each(|(coords, r, id)| {
+                world
+                    .write_resource::<Chunks>()
+                    .generate(coords, *r, false, Some(*id))
             });
         }
     }
 
     fn chunking(&mut self) {
-        let mut request_queue = vec![];
-        let mut message_queue = VecDeque::new();
-
         for world in self.worlds.values_mut() {
-            let world_name = world.name.to_owned();
-            let mut players = world.write_resource::<Players>();
-
-            players.iter_mut().for_each(|(id, player)| {
-                if player.name.is_none() {
-                    return;
-                }
-
-                let requested_chunk = player.requested_chunks.pop_front();
-                request_queue.push((
-                    requested_chunk.to_owned(),
-                    world_name.to_owned(),
-                    id.to_owned(),
-                ));
-            });
+            world.stream_chunks();
         }
-
-        request_queue
-            .into_iter()
-            .for_each(|(coords, world_name, player_id)| {
-                if let Some(coords) = coords {
-                    let mut chunks = self
-                        .worlds
-                        .get_mut(&world_name)
-                        .unwrap()
-                        .write_resource::<Chunks>();
-                    if let Some(chunk) = chunks.get(&coords, &MeshLevel::All, false) {
-                        // SEND CHUNK BACK TO PLAYER
-
-                        let mut component = MessageComponents::default_for(MessageType::Load);
-                        component.chunks =
-                            Some(vec![chunk.get_protocol(true, true, true, MeshLevel::All)]);
-
-                        let new_message = create_message(component);
-                        message_queue.push_back((world_name.to_owned(), new_message, vec![]));
-                    } else {
-                        drop(chunks);
-                        self.worlds
-                            .get_mut(&world_name)
-                            .unwrap()
-                            .write_resource::<Players>()
-                            .get_mut(&player_id)
-                            .unwrap()
-                            .requested_chunks
-                            .push_back(coords);
-                    }
-                }
-            });
-
-        message_queue
-            .into_iter()
-            .for_each(|(world_name, message, exclude)| {
-                self.broadcast(&world_name, &message, exclude);
-            })
     }
 }
 
@@ -209,6 +245,14 @@ impl Actor for WsServer {
         ctx.set_mailbox_capacity(usize::MAX);
 
         self.subscribe_system_async::<LeaveWorld>(ctx);
+
+        ctx.run_interval(SERVER_TICK, |act, _ctx| {
+            act.tick();
+        });
+
+        ctx.run_interval(CHUNKING_TICK, |act, _ctx| {
+            act.chunking();
+        });
     }
 }
 
@@ -218,21 +262,41 @@ impl Handler<JoinWorld> for WsServer {
     fn handle(&mut self, msg: JoinWorld, _ctx: &mut Self::Context) -> Self::Result {
         let JoinWorld {
             world_name,
+            player_id,
             player_name,
             player_addr,
             render_radius,
+            client_meshing,
         } = msg;
 
         let new_player = Player {
             name: player_name,
+            announced: false,
             addr: player_addr,
             current_chunk: None,
             position: Vec3::default(),
             rotation: Quaternion::default(),
-            requested_chunks: VecDeque::default(),
+            loaded_chunks: HashSet::new(),
+            stream_budget: 0,
             render_radius,
+            client_meshing,
+            light: None,
+            lights_synced_at: None,
+            subscriptions: vec![],
+            visible_peers: HashSet::new(),
+            // placed at the spawn once in a world
+            movement: Movement::new(&Vec3::default(), 1),
+            can_fly: false,
+            data: Default::default(),
+            latency: None,
         };
-        let result = self.add_player_to_world(&world_name, None, new_player);
+        let result = self.add_player_to_world(&world_name, player_id, new_player);
+
+        if result.is_ok() {
+            if let Err(e) = self.accounts.select_world(player_id, &world_name) {
+                error!("{}", e);
+            }
+        }
 
         MessageResult(result)
     }
@@ -246,10 +310,13 @@ impl Handler<LeaveWorld> for WsServer {
 
         if let Some(world) = self.worlds.get_mut(&msg.world_name) {
             let world_name = world.name.to_owned();
+
+            world.save_player(msg.player_id);
+
             let mut players = world.write_resource::<Players>();
 
             if let Some(player) = players.remove(&msg.player_id) {
-                let player_name = player.name.clone().unwrap_or_else(|| "Somebody".to_owned());
+                let player_name = player.name;
 
                 let mut new_message = create_chat_message(
                     MessageType::Leave,
@@ -265,6 +332,10 @@ impl Handler<LeaveWorld> for WsServer {
 
                 message_queue.push((world_name, new_message));
             }
+
+            players.values_mut().for_each(|player| {
+                player.visible_peers.remove(&msg.player_id);
+            });
         }
 
         message_queue.into_iter().for_each(|(world_name, message)| {
@@ -273,6 +344,20 @@ impl Handler<LeaveWorld> for WsServer {
     }
 }
 
+impl Handler<ReportLatency> for WsServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportLatency, _ctx: &mut Self::Context) {
+        if let Some(world) = self.worlds.get_mut(&msg.world_name) {
+            let mut players = world.write_resource::<Players>();
+
+            if let Some(player) = players.get_mut(&msg.player_id) {
+                player.latency = Some(msg.latency);
+            }
+        }
+    }
+}
+
 impl Handler<ListWorldNames> for WsServer {
     type Result = MessageResult<ListWorldNames>;
 
@@ -300,17 +385,13 @@ impl Handler<PlayerMessage> for WsServer {
             MessageType::Update => world.on_update(player_id, raw),
             MessageType::Peer => world.on_peer(player_id, raw),
             MessageType::Message => world.on_chat_message(player_id, raw),
+            MessageType::Subscribe => world.on_subscribe(player_id, raw),
+            MessageType::Unsubscribe => world.on_unsubscribe(player_id, raw),
             _ => {}
         }
     }
 }
 
-impl Handler<Noop> for WsServer {
-    type Result = ();
-
-    fn handle(&mut self, _msg: Noop, _ctx: &mut Self::Context) {}
-}
-
 impl Handler<ListWorlds> for WsServer {
     type Result = MessageResult<ListWorlds>;
 
@@ -328,6 +409,7 @@ impl Handler<ListWorlds> for WsServer {
                 generation: chunks.config.generation.to_owned(),
                 description: world.description.to_owned(),
                 players: players.len(),
+                jobs: chunks.job_stats(),
             });
         });
 
@@ -337,6 +419,32 @@ impl Handler<ListWorlds> for WsServer {
     }
 }
 
+impl Handler<ListPlayers> for WsServer {
+    type Result = MessageResult<ListPlayers>;
+
+    fn handle(&mut self, msg: ListPlayers, _ctx: &mut Self::Context) -> Self::Result {
+        let world = match self.worlds.get(&msg.0) {
+            Some(world) => world,
+            None => return MessageResult(Err(format!("No world named {}.", msg.0))),
+        };
+
+        let players = world.read_resource::<Players>();
+
+        let mut data: Vec<PlayerInfo> = players
+            .iter()
+            .map(|(id, player)| PlayerInfo {
+                id: *id,
+                name: player.name.to_owned(),
+                latency: player.latency.map(|latency| latency.as_millis()),
+            })
+            .collect();
+
+        data.sort_by_key(|player| player.id);
+
+        MessageResult(Ok(data))
+    }
+}
+
 impl Handler<GetWorld> for WsServer {
     type Result = MessageResult<GetWorld>;
 
@@ -362,43 +470,176 @@ impl Handler<GetWorld> for WsServer {
             time: clock.time,
             blocks: registry.blocks.to_owned(),
             ranges: registry.ranges.to_owned(),
+            animations: registry.animations.to_owned(),
             uv_side_count: registry.uv_side_count,
             uv_texture_size: registry.uv_texture_size,
+            uv_padding: registry.uv_padding,
         })
     }
 }
 
-impl SystemService for WsServer {
-    fn service_started(&mut self, ctx: &mut Context<Self>) {
-        // Loading worlds from `worlds.json`
-        let mut worlds: HashMap<String, World> = HashMap::new();
+impl Handler<GetLimits> for WsServer {
+    type Result = MessageResult<GetLimits>;
 
-        let worlds_json: serde_json::Value =
-            serde_json::from_reader(File::open("metadata/worlds.json").unwrap()).unwrap();
+    fn handle(&mut self, msg: GetLimits, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(
+            self.worlds
+                .get(&msg.0)
+                .map(|world| world.limits.clone())
+                .unwrap_or_default(),
+        )
+    }
+}
 
-        let world_default = &worlds_json["default"];
+impl Handler<GetAtlas> for WsServer {
+    type Result = MessageResult<GetAtlas>;
+
+    fn handle(&mut self, msg: GetAtlas, _ctx: &mut Self::Context) -> Self::Result {
+        let GetAtlas { world_name, level } = msg;
+
+        // without a world, any world on the default textures would do
+        let world = match world_name {
+            Some(world_name) => self.worlds.get(&world_name),
+            None => self
+                .worlds
+                .values()
+                .find(|world| {
+                    world
+                        .read_resource::<Chunks>()
+                        .registry
+                        .options
+                        .texture_pack
+                        .is_none()
+                })
+                .or_else(|| self.worlds.values().next()),
+        };
 
-        let registry = Registry::new();
+        let world = match world {
+            Some(world) => world,
+            None => return MessageResult(None),
+        };
 
-        for world_json in worlds_json["worlds"].as_array().unwrap() {
-            let mut world_json = world_json.clone();
-            json::merge(&mut world_json, world_default, false);
+        let chunks = world.read_resource::<Chunks>();
+        MessageResult(chunks.registry.pngs.get(level).cloned())
+    }
+}
 
-            let mut new_world = World::new(world_json, registry.clone());
-            new_world.preload();
-            worlds.insert(new_world.name.to_owned(), new_world);
+impl Handler<ExportWorld> for WsServer {
+    type Result = MessageResult<ExportWorld>;
+
+    fn handle(&mut self, msg: ExportWorld, _ctx: &mut Self::Context) -> Self::Result {
+        let ExportWorld {
+            world_name,
+            min,
+            max,
+        } = msg;
+
+        // meshing and writing the region is left to the caller, off the thread running the worlds
+        let result = match self.worlds.get_mut(&world_name) {
+            Some(world) => {
+                let mut chunks = world.write_resource::<Chunks>();
+                let config = chunks.config.clone();
+
+                Region::parse(&min, &max, config.chunk_size, config.max_height)
+                    .and_then(|region| Snapshot::take(&mut chunks, region))
+            }
+            None => Err(format!("World \"{}\" not found.", world_name)),
+        };
+
+        MessageResult(result)
+    }
+}
+
+impl Handler<CreateAccount> for WsServer {
+    type Result = MessageResult<CreateAccount>;
+
+    fn handle(&mut self, msg: CreateAccount, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.accounts.create(&msg.username, msg.password_hash))
+    }
+}
+
+impl Handler<GetCredentials> for WsServer {
+    type Result = MessageResult<GetCredentials>;
+
+    fn handle(&mut self, msg: GetCredentials, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.accounts.credentials(&msg.0))
+    }
+}
+
+impl Handler<IssueToken> for WsServer {
+    type Result = MessageResult<IssueToken>;
+
+    fn handle(&mut self, msg: IssueToken, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.accounts.issue_token(msg.0))
+    }
+}
+
+impl Handler<Authenticate> for WsServer {
+    type Result = MessageResult<Authenticate>;
+
+    fn handle(&mut self, msg: Authenticate, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.accounts.authenticate(&msg.0))
+    }
+}
+
+impl Handler<SetFlight> for WsServer {
+    type Result = MessageResult<SetFlight>;
+
+    fn handle(&mut self, msg: SetFlight, _ctx: &mut Self::Context) -> Self::Result {
+        let world = match self.worlds.get_mut(&msg.world_name) {
+            Some(world) => world,
+            None => return MessageResult(Err(format!("No world named {}.", msg.world_name))),
+        };
+
+        if !world.set_player_flight(msg.player_id, msg.allowed) {
+            return MessageResult(Err(format!("No player with id {}.", msg.player_id)));
         }
 
-        self.worlds = worlds;
+        MessageResult(Ok(()))
+    }
+}
 
-        ctx.run_interval(SERVER_TICK, |act, _ctx| {
-            act.tick();
-        });
+impl Handler<GetRegistries> for WsServer {
+    type Result = MessageResult<GetRegistries>;
 
-        ctx.run_interval(CHUNKING_TICK, |act, _ctx| {
-            act.chunking();
-        });
+    fn handle(&mut self, _msg: GetRegistries, _ctx: &mut Self::Context) -> Self::Result {
+        let mut registries: HashMap<RegistryOptions, Arc<Registry>> = HashMap::new();
+
+        for world in self.worlds.values() {
+            let registry = &world.read_resource::<Chunks>().registry;
+
+            if !registries.contains_key(&registry.options) {
+                registries.insert(registry.options.to_owned(), registry.clone());
+            }
+        }
+
+        MessageResult(registries.into_values().collect())
     }
 }
 
+impl Handler<ReloadRegistry> for WsServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReloadRegistry, _ctx: &mut Self::Context) {
+        let ReloadRegistry(registries) = msg;
+
+        for world in self.worlds.values_mut() {
+            let options = world.read_resource::<Chunks>().registry.options.to_owned();
+            let registry = match registries.get(&options) {
+                Some(registry) => registry.to_owned(),
+                None => continue,
+            };
+
+            world.reload_registry(registry);
+
+            info!(
+                "Reloaded the registry of world {}.",
+                Yellow.bold().paint(&world.name)
+            );
+        }
+    }
+}
+
+impl SystemService for WsServer {}
+
 impl Supervised for WsServer {}
@@ -1,86 +1,97 @@
 use libflate::zlib::Encoder;
 
 use std::io::Write;
+use std::time::{Duration, Instant};
 
 use actix::prelude::*;
-use actix_broker::BrokerIssue;
 use actix_web_actors::ws;
 
-use super::super::network::models::{create_of_type, encode_message, messages};
+use super::super::engine::players::{MAX_SUBSCRIPTIONS, MAX_SUBSCRIPTION_RADIUS};
+use super::super::network::models::{
+    create_chat_message, create_of_type, encode_message, messages,
+    messages::{chat_message::Type as ChatType, message::Type as MessageType},
+};
 
+use super::handshake::{self, Capabilities};
+use super::heartbeat::{Heartbeat, Monitor};
+use super::limits::RateLimiter;
 use super::message::{self, PlayerMessage};
-use super::message::{JoinWorld, LeaveWorld};
+use super::message::{JoinWorld, LeaveWorld, ReportLatency};
 use super::models;
 use super::server::WsServer;
 
+/// Time a client has to send its handshake after connecting
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most chunks a client can request in a single message
+const MAX_CHUNK_REQUESTS: usize = 64;
+
 #[derive(Debug, Default)]
 pub struct WsSession {
-    // unique sessions id
+    // id of the player's account
     pub id: usize,
-    // joined world
+    // world to join
     pub world_name: String,
-    // name in world
-    pub name: Option<String>,
-    // radius of render?
+    // username of the player's account
+    pub name: String,
+    // whether the player is in the world, and has to leave it when disconnecting
+    pub joined: bool,
+    // largest radius of chunks streamed to the client, which may ask for less
     pub render_radius: i16,
+    // capabilities agreed on in the handshake, none until then
+    pub capabilities: Option<Capabilities>,
+    // what the client may send, the defaults until it's in a world
+    pub limiter: RateLimiter,
+    // when the client was last heard from and last active
+    pub monitor: Monitor,
 }
 
 impl WsSession {
     pub fn join_world(&self, ctx: &mut ws::WebsocketContext<Self>) {
         let world_name = self.world_name.to_owned();
 
-        // First send a leave message for the current room
-        let leave_msg = LeaveWorld {
-            world_name: self.world_name.clone(),
-            player_id: self.id,
-        };
-
-        // issue_sync comes from having the `BrokerIssue` trait in scope
-        self.issue_system_sync(leave_msg, ctx);
-
         let join_msg = JoinWorld {
             world_name: world_name.to_owned(),
-            player_name: self.name.clone(),
+            player_id: self.id,
+            player_name: self.name.to_owned(),
             player_addr: ctx.address().recipient(),
             render_radius: self.render_radius,
+            client_meshing: self
+                .capabilities
+                .as_ref()
+                .is_some_and(|capabilities| capabilities.client_meshing),
         };
 
         WsServer::from_registry()
             .send(join_msg)
             .into_actor(self)
-            .then(|id, act, ctx| {
-                if let Ok(result) = id {
-                    act.id = result.id;
-                    act.world_name = world_name;
-
-                    // TODO: fix this?
-                    let passables: Vec<String> =
-                        result.passables.iter().map(|&id| id.to_string()).collect();
-                    let passables = passables.join(",");
-                    let data = format!(
-                        r#"
-                    {{
-                        "id": "{}",
-                        "time": {},
-                        "tickSpeed": {},
-                        "spawn": [{}, {}, {}],
-                        "passables": {}
-                    }}
-                    "#,
-                        result.id,
-                        result.time,
-                        result.tick_speed,
-                        result.spawn[0],
-                        result.spawn[1],
-                        result.spawn[2],
-                        format!("[{}]", passables)
-                    );
-
-                    let mut message = create_of_type(messages::message::Type::Init);
-                    message.json = data;
-                    let encoded = encode_message(&message);
-
-                    ctx.binary(encoded);
+            .then(|result, act, ctx| {
+                match result {
+                    Ok(Ok(result)) => {
+                        act.joined = true;
+                        act.world_name = world_name;
+                        act.limiter = RateLimiter::new(result.limits);
+                        act.start_heartbeat(result.heartbeat, ctx);
+
+                        let mut message = create_of_type(messages::message::Type::Init);
+                        message.init = Some(messages::Init {
+                            id: result.id.to_string(),
+                            time: result.time,
+                            tick_speed: result.tick_speed,
+                            spawn: result.spawn.to_vec(),
+                            position: result.position.map(Vec::from).unwrap_or_default(),
+                            rotation: result.rotation.map(Vec::from).unwrap_or_default(),
+                            passables: result.passables,
+                            can_fly: result.can_fly,
+                            name: act.name.to_owned(),
+                            registry: result.registry,
+                        });
+                        let encoded = encode_message(&message);
+
+                        ctx.binary(encoded);
+                    }
+                    Ok(Err(reason)) => act.disconnect(&reason, ctx),
+                    Err(_) => ctx.stop(),
                 }
 
                 fut::ready(())
@@ -88,7 +99,79 @@ impl WsSession {
             .wait(ctx);
     }
 
-    fn on_request(&mut self, message: messages::Message) {
+    /// The first message of a client must be a handshake with a compatible protocol version.
+    /// Joins the world once the capabilities are agreed on.
+    fn on_handshake(&mut self, message: messages::Message, ctx: &mut ws::WebsocketContext<Self>) {
+        let handshake = match (message.r#type(), message.handshake) {
+            (messages::message::Type::Handshake, Some(handshake)) => handshake,
+            _ => {
+                self.disconnect("Expected a handshake first.", ctx);
+                return;
+            }
+        };
+
+        match handshake::negotiate(&handshake, self.render_radius) {
+            Ok(capabilities) => {
+                let mut message = create_of_type(messages::message::Type::Handshake);
+                message.handshake = Some(capabilities.to_handshake());
+                ctx.binary(encode_message(&message));
+
+                self.render_radius = capabilities.render_radius;
+                self.capabilities = Some(capabilities);
+                self.join_world(ctx);
+            }
+            Err(reason) => self.disconnect(&reason, ctx),
+        }
+    }
+
+    /// Ping the client on an interval, disconnecting it once it stops answering or idles for too
+    /// long.
+    fn start_heartbeat(&mut self, heartbeat: Heartbeat, ctx: &mut ws::WebsocketContext<Self>) {
+        let interval = heartbeat.ping_interval();
+
+        // the time it took to join doesn't count against the player
+        self.monitor = Monitor::new(heartbeat, Instant::now());
+
+        ctx.run_interval(interval, |act, ctx| {
+            let now = Instant::now();
+
+            match act.monitor.check(now) {
+                Ok(Some(warning)) => {
+                    let warning =
+                        create_chat_message(MessageType::Message, ChatType::Info, "", &warning);
+                    ctx.binary(encode_message(&warning));
+                }
+                Ok(None) => {}
+                Err(reason) => {
+                    act.disconnect(&reason, ctx);
+                    return;
+                }
+            }
+
+            ctx.ping(&act.monitor.ping(now));
+        });
+    }
+
+    /// Tell the client why it's being disconnected, then close the connection.
+    fn disconnect(&self, reason: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        let mut message = create_of_type(messages::message::Type::Error);
+        message.text = reason.to_owned();
+        ctx.binary(encode_message(&message));
+
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Policy,
+            description: Some(reason.to_owned()),
+        }));
+        ctx.stop();
+    }
+
+    fn on_request(&mut self, message: messages::Message, ctx: &mut ws::WebsocketContext<Self>) {
+        if let Err(reason) = validate(&message) {
+            let error = create_chat_message(MessageType::Message, ChatType::Error, "", &reason);
+            ctx.binary(encode_message(&error));
+            return;
+        }
+
         WsServer::from_registry().do_send(PlayerMessage {
             player_id: self.id,
             world_name: self.world_name.to_owned(),
@@ -97,14 +180,104 @@ impl WsSession {
     }
 }
 
+/// Check the messages of a client that the worlds would otherwise trust.
+fn validate(message: &messages::Message) -> Result<(), String> {
+    match message.r#type() {
+        MessageType::Request => {
+            if message.requests.is_empty() {
+                return Err("Chunk request without any chunks.".to_owned());
+            }
+
+            if message.requests.len() > MAX_CHUNK_REQUESTS {
+                return Err(format!(
+                    "Too many chunks requested at once, at most {} are allowed.",
+                    MAX_CHUNK_REQUESTS
+                ));
+            }
+        }
+        MessageType::Config => {
+            use messages::config::{MaybeTickSpeed, MaybeTime};
+
+            let config = message
+                .config
+                .as_ref()
+                .ok_or_else(|| "Config change without a config.".to_owned())?;
+
+            if let Some(MaybeTime::Time(time)) = config.maybe_time {
+                if !time.is_finite() || time < 0.0 {
+                    return Err(format!("Invalid time: {}.", time));
+                }
+            }
+
+            if let Some(MaybeTickSpeed::TickSpeed(tick_speed)) = config.maybe_tick_speed {
+                if !tick_speed.is_finite() || tick_speed < 0.0 {
+                    return Err(format!("Invalid tick speed: {}.", tick_speed));
+                }
+            }
+
+            if config.maybe_can_fly.is_some() {
+                return Err("Players can't allow themselves to fly.".to_owned());
+            }
+        }
+        MessageType::Peer => {
+            let peer = match message.peers.as_slice() {
+                [peer] => peer,
+                _ => return Err("Peer update should be about exactly one peer.".to_owned()),
+            };
+
+            let pose = [
+                peer.px, peer.py, peer.pz, peer.qx, peer.qy, peer.qz, peer.qw,
+            ];
+
+            if pose.iter().any(|value| !value.is_finite()) {
+                return Err("Peer update with an invalid position or rotation.".to_owned());
+            }
+        }
+        MessageType::Subscribe => {
+            if message.areas.is_empty() {
+                return Err("Subscription without any areas.".to_owned());
+            }
+
+            if message.areas.len() > MAX_SUBSCRIPTIONS {
+                return Err(format!(
+                    "Too many areas subscribed to at once, at most {} are allowed.",
+                    MAX_SUBSCRIPTIONS
+                ));
+            }
+
+            if let Some(area) = message
+                .areas
+                .iter()
+                .find(|area| area.radius > MAX_SUBSCRIPTION_RADIUS)
+            {
+                return Err(format!(
+                    "Area radius {} is too large, at most {} is allowed.",
+                    area.radius, MAX_SUBSCRIPTION_RADIUS
+                ));
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
 impl Actor for WsSession {
     type Context = ws::WebsocketContext<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        self.join_world(ctx);
+        ctx.run_later(HANDSHAKE_TIMEOUT, |act, ctx| {
+            if act.capabilities.is_none() {
+                act.disconnect("Timed out waiting for a handshake.", ctx);
+            }
+        });
     }
 
     fn stopped(&mut self, _ctx: &mut Self::Context) {
+        if !self.joined {
+            return;
+        }
+
         WsServer::from_registry().do_send(LeaveWorld {
             world_name: self.world_name.clone(),
             player_id: self.id,
@@ -119,7 +292,12 @@ impl Handler<message::Message> for WsSession {
         let message::Message(msg) = msg;
         let encoded = encode_message(&msg);
 
-        if encoded.len() > 1024 {
+        let compression = self
+            .capabilities
+            .as_ref()
+            .is_some_and(|capabilities| capabilities.compression);
+
+        if compression && encoded.len() > 1024 {
             let mut encoder = Encoder::new(Vec::new()).unwrap();
             encoder.write_all(encoded.as_slice()).unwrap();
             let encoded = encoder.finish().into_result().unwrap();
@@ -133,6 +311,10 @@ impl Handler<message::Message> for WsSession {
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         let msg = match msg {
+            Err(ws::ProtocolError::Overflow) => {
+                self.disconnect("Message is too large.", ctx);
+                return;
+            }
             Err(_) => {
                 ctx.stop();
                 return;
@@ -142,8 +324,48 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
 
         match msg {
             ws::Message::Binary(bytes) => {
-                let message = models::decode_message(&bytes.to_vec()).unwrap();
-                self.on_request(message);
+                if let Err(reason) = self.limiter.check_frame(bytes.len()) {
+                    self.disconnect(&reason, ctx);
+                    return;
+                }
+
+                let message = match models::decode_message(&bytes) {
+                    Ok(message) => message,
+                    Err(_) => {
+                        self.disconnect("Malformed message.", ctx);
+                        return;
+                    }
+                };
+
+                let now = Instant::now();
+
+                if let Err(reason) = self.limiter.check(&message, now) {
+                    self.disconnect(&reason, ctx);
+                    return;
+                }
+
+                self.monitor.observe(&message, now);
+
+                if self.capabilities.is_none() {
+                    self.on_handshake(message, ctx);
+                } else {
+                    self.on_request(message, ctx);
+                }
+            }
+            ws::Message::Ping(bytes) => {
+                self.monitor.heard(Instant::now());
+                ctx.pong(&bytes);
+            }
+            ws::Message::Pong(bytes) => {
+                if let Some(latency) = self.monitor.pong(&bytes, Instant::now()) {
+                    if self.joined {
+                        WsServer::from_registry().do_send(ReportLatency {
+                            world_name: self.world_name.to_owned(),
+                            player_id: self.id,
+                            latency,
+                        });
+                    }
+                }
             }
             ws::Message::Close(reason) => {
                 ctx.close(reason);
@@ -153,3 +375,23 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_peer_updates() {
+        let mut message = create_of_type(MessageType::Peer);
+        assert!(validate(&message).is_err());
+
+        message.peers = vec![messages::Peer::default()];
+        assert!(validate(&message).is_ok());
+
+        message.peers[0].qw = f32::NAN;
+        assert!(validate(&message).is_err());
+
+        message.peers = vec![messages::Peer::default(), messages::Peer::default()];
+        assert!(validate(&message).is_err());
+    }
+}
@@ -0,0 +1,20 @@
+use specs::{ReadStorage, System, WriteStorage};
+
+use super::super::comp::{light::DynamicLight, phys::Phys};
+
+/// Carries the dynamic lights of entities along with their bodies.
+pub struct LightSystem;
+
+impl<'a> System<'a> for LightSystem {
+    type SystemData = (ReadStorage<'a, Phys>, WriteStorage<'a, DynamicLight>);
+
+    fn run(&mut self, data: Self::SystemData) {
+        use specs::Join;
+
+        let (phys, mut lights) = data;
+
+        for (p, light) in (&phys, &mut lights).join() {
+            light.position = p.body.get_position();
+        }
+    }
+}
@@ -1,3 +1,5 @@
+mod lights;
 mod physics;
 
+pub use lights::LightSystem;
 pub use physics::PhysicsSystem;
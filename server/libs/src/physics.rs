@@ -7,7 +7,7 @@ use server_utils::sweep::sweep;
 
 use super::rigidbody::RigidBody;
 
-type TestFunction<'a> = &'a dyn Fn(i32, i32, i32) -> bool;
+pub type TestFunction<'a> = &'a dyn Fn(i32, i32, i32) -> bool;
 
 pub struct BodyOptions<'a> {
     pub aabb: Aabb,
@@ -38,6 +38,10 @@ impl Physics {
         Self { options }
     }
 
+    pub fn options(&self) -> &PhysicsOptions {
+        &self.options
+    }
+
     pub fn iterate_body(
         &self,
         b: &mut RigidBody,
@@ -2,6 +2,7 @@
 
 use server_common::{aabb::Aabb, vec::Vec3};
 
+#[derive(Debug, Clone)]
 pub struct RigidBody {
     // flags for test
     pub collided: Option<Vec3<f32>>,
@@ -1,11 +1,21 @@
-use log::info;
+use log::{error, info};
 
-use actix::SystemService;
+use std::{collections::HashSet, env, fs, path::Path, process};
+
+use actix::{Actor, SystemRegistry};
 use actix_cors::Cors;
-use actix_files as fs;
+use actix_files::Files;
 use actix_web::{web, App, HttpServer};
 
-use server_core::network::{message, routes, server::WsServer};
+use server_core::{
+    engine::{
+        chunks::Chunks,
+        export::{ExportFormat, Exporter, Region},
+        registry::{Registry, RegistryOptions},
+        world::World,
+    },
+    network::{routes, server::WsServer},
+};
 
 fn setup_logger() -> Result<(), fern::InitError> {
     fern::Dispatch::new()
@@ -26,12 +36,120 @@ fn setup_logger() -> Result<(), fern::InitError> {
     Ok(())
 }
 
+/// Export a region of a world without running the server:
+///
+/// `main export <world> <min> <max> [obj|gltf] [out_dir]`
+fn export(args: &[String]) -> Result<(), String> {
+    let (world_name, min, max) = match args {
+        [world_name, min, max, ..] => (world_name, min, max),
+        _ => return Err("Usage: export <world> <min> <max> [obj|gltf] [out_dir]".to_owned()),
+    };
+
+    let format = args
+        .get(3)
+        .map(|f| f.as_str())
+        .unwrap_or("gltf")
+        .parse::<ExportFormat>()?;
+    let out_dir = Path::new(args.get(4).map(|d| d.as_str()).unwrap_or("."));
+
+    let world_json = World::load_configs()?
+        .into_iter()
+        .find(|json| json["name"].as_str() == Some(world_name.as_str()))
+        .ok_or(format!("World \"{}\" not found.", world_name))?;
+
+    let options = RegistryOptions::from_config(&world_json)?;
+    let registry = Registry::load(&options).map_err(|e| e.to_string())?;
+    let mut world = World::new(world_json, registry);
+    let mut chunks = world.write_resource::<Chunks>();
+
+    let region = Region::parse(min, max, chunks.config.chunk_size, chunks.config.max_height)?;
+    let files = Exporter::export(&mut chunks, &region, format, world_name)?;
+
+    fs::create_dir_all(out_dir).map_err(|e| e.to_string())?;
+
+    for file in files {
+        let path = out_dir.join(&file.name);
+        fs::write(&path, file.data).map_err(|e| e.to_string())?;
+        info!("Exported {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Validate the metadata and textures of every world without running the server, reporting
+/// every problem found:
+///
+/// `main --check-metadata`
+fn check_metadata() -> Result<(), String> {
+    let mut checked = HashSet::new();
+    let mut failed = false;
+
+    for world_json in World::load_configs()? {
+        let name = world_json["name"].as_str().unwrap_or_default();
+        let options = RegistryOptions::from_config(&world_json)
+            .map_err(|e| format!("World \"{}\": {}", name, e))?;
+
+        // worlds sharing options share the same registry
+        if !checked.insert(options.to_owned()) {
+            continue;
+        }
+
+        match Registry::load(&options) {
+            Ok(registry) => info!(
+                "Metadata of world \"{}\" is valid, {} blocks.",
+                name,
+                registry.blocks.len()
+            ),
+            Err(e) => {
+                error!("Metadata of world \"{}\" is invalid. {}", name, e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        return Err("Metadata check failed.".to_owned());
+    }
+
+    Ok(())
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     setup_logger().expect("Something went wrong with fern...");
 
+    let args: Vec<String> = env::args().collect();
+
+    if args.get(1).map(|a| a.as_str()) == Some("export") {
+        if let Err(e) = export(&args[2..]) {
+            error!("{}", e);
+            process::exit(1);
+        }
+
+        return Ok(());
+    }
+
+    if args.get(1).map(|a| a.as_str()) == Some("--check-metadata") {
+        if let Err(e) = check_metadata() {
+            error!("{}", e);
+            process::exit(1);
+        }
+
+        return Ok(());
+    }
+
     let addr = "localhost:4000";
 
+    // the worlds are loaded before serving, so that bad metadata stops the server right away
+    let server = match WsServer::load() {
+        Ok(server) => server,
+        Err(e) => {
+            error!("Failed to load the worlds. {}", e);
+            process::exit(1);
+        }
+    };
+    SystemRegistry::set(server.start());
+
     let srv = HttpServer::new(move || {
         let cors = Cors::permissive();
 
@@ -42,15 +160,18 @@ async fn main() -> std::io::Result<()> {
             .service(routes::worlds)
             .service(routes::world)
             .service(routes::time)
+            .service(routes::players)
+            .service(routes::export)
+            .service(routes::reload)
+            .service(routes::flight)
+            .service(routes::register)
+            .service(routes::login)
             .service(web::resource("/ws/").to(routes::ws_route))
-            .service(fs::Files::new("/", "public/").show_files_listing())
+            .service(Files::new("/", "public/").show_files_listing())
     })
     .bind(&addr)?;
 
     info!("🚀  MineJS running on http://{}", &addr);
 
-    // Wake up the sever
-    WsServer::from_registry().do_send(message::Noop);
-
     srv.run().await
 }